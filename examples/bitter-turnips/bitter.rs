@@ -3,22 +3,27 @@ mod events;
 
 use rand::{
     distributions::{Distribution, Standard},
-    Rng,
+    rngs::StdRng,
+    Rng, SeedableRng,
 };
 
+use serde::{Deserialize, Serialize};
+
 use slotmap::{new_key_type, SecondaryMap, SlotMap};
 
+use sweet_turnips::sprites::GridUnit;
+
 use entities::{Cursor, DeathMarker, Farm, GameEntity, Villager};
 use events::{WorldEvent, WE};
 
-pub const GRID_WIDTH: u8 = 8;
-pub const GRID_HEIGHT: u8 = 8;
+pub const GRID_WIDTH: GridUnit = 8;
+pub const GRID_HEIGHT: GridUnit = 8;
 
 new_key_type! { pub struct EntityKey; }
 
 pub type Ticks = usize;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -41,9 +46,9 @@ impl Distribution<Direction> for Standard {
     }
 }
 
-pub type Coords = (u8, u8);
+pub type Coords = (GridUnit, GridUnit);
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct World {
     events: Vec<WorldEvent>,
     entities: SlotMap<EntityKey, GameEntity>,
@@ -54,6 +59,9 @@ pub struct World {
     pub satiation: SecondaryMap<EntityKey, u8>,
     pub villagers: SecondaryMap<EntityKey, Villager>,
     pub cursors: SecondaryMap<EntityKey, Cursor>,
+    // Randomness comes from these rather than the thread's RNG, so that seeded runs repeat
+    seed: u64,
+    rolls: u64,
 }
 
 impl World {
@@ -68,9 +76,26 @@ impl World {
             satiation: SecondaryMap::new(),
             villagers: SecondaryMap::new(),
             cursors: SecondaryMap::new(),
+            seed: rand::random(),
+            rolls: 0,
+        }
+    }
+
+    pub fn seeded(self, seed: u64) -> Self {
+        Self {
+            seed,
+            rolls: 0,
+            ..self
         }
     }
 
+    // A fresh generator for each use, so that only the seed and a count need saving
+    fn rng(&mut self) -> StdRng {
+        self.rolls += 1;
+
+        StdRng::seed_from_u64(self.seed ^ self.rolls.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
     pub fn cursor_coords(&self) -> Coords {
         let cursor = self.cursors.values().nth(0).expect("Found no cursor");
         self.coords[cursor.key]
@@ -250,7 +275,7 @@ impl World {
             return new_events;
         }
 
-        let mut rng = rand::thread_rng();
+        let mut rng = self.rng();
 
         let ci = rng.gen_range(0, all_possible_coords.len());
 
@@ -270,7 +295,7 @@ impl World {
     fn villager_died(&mut self, vk: EntityKey) -> Vec<WorldEvent> {
         let coords = self.coords[vk];
 
-        let dmk = self.entities.insert(GameEntity);
+        let dmk = self.entities.insert(GameEntity {});
 
         let dm = DeathMarker { key: dmk };
 
@@ -285,7 +310,7 @@ impl World {
     fn farm_added(&mut self, coords: Coords) -> Vec<WorldEvent> {
         let (x, y) = (coords.0, coords.1);
 
-        let entity = GameEntity;
+        let entity = GameEntity {};
         let key = self.entities.insert(entity);
 
         let farm = Farm::new(key, x, y, self.ticks);
@@ -299,7 +324,7 @@ impl World {
     fn villager_harvested(&mut self, vk: EntityKey) -> Vec<WorldEvent> {
         let mut new_events = vec![];
 
-        let mut rng = rand::thread_rng();
+        let mut rng = self.rng();
 
         let mut unharvested_farms: Vec<&Farm> = self.farms.values().collect();
 
@@ -335,8 +360,10 @@ impl World {
     fn villagers_moved(&mut self) -> Vec<WorldEvent> {
         let mut new_events = vec![];
 
+        let mut rng = self.rng();
+
         for key in self.villagers.keys() {
-            let direction: Direction = rand::random();
+            let direction: Direction = rng.gen();
 
             new_events.push(WE::VillagerMoved(key, direction));
         }
@@ -347,7 +374,7 @@ impl World {
     fn egg_laid(&mut self, coords: Coords) -> Vec<WorldEvent> {
         let (x, y) = (coords.0, coords.1);
 
-        let entity = GameEntity;
+        let entity = GameEntity {};
         let key = self.entities.insert(entity);
 
         let villager = Villager::new(key, self.ticks);
@@ -360,14 +387,14 @@ impl World {
     }
 
     pub fn add_cursor_at(&mut self, coords: Coords) {
-        let ck = self.entities.insert(GameEntity);
+        let ck = self.entities.insert(GameEntity {});
         let cursor = Cursor { key: ck };
         self.cursors.insert(ck, cursor);
         self.coords.insert(ck, coords);
     }
 
-    pub fn add_villager_at(&mut self, x: u8, y: u8) {
-        let entity = GameEntity;
+    pub fn add_villager_at(&mut self, x: GridUnit, y: GridUnit) {
+        let entity = GameEntity {};
         let key = self.entities.insert(entity);
 
         let villager = Villager::new(key, self.ticks);
@@ -377,8 +404,8 @@ impl World {
         self.satiation.insert(key, 4);
     }
 
-    pub fn add_farm_at(&mut self, x: u8, y: u8) {
-        let entity = GameEntity;
+    pub fn add_farm_at(&mut self, x: GridUnit, y: GridUnit) {
+        let entity = GameEntity {};
         let key = self.entities.insert(entity);
 
         let farm = Farm::new(key, x, y, self.ticks);
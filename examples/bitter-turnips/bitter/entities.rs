@@ -1,19 +1,24 @@
+use serde::{Deserialize, Serialize};
+
 use crate::bitter::{EntityKey, Ticks};
 
-#[derive(Copy, Clone, Debug)]
-pub struct GameEntity;
+use sweet_turnips::sprites::GridUnit;
+
+// Braced so that it saves as {} rather than null, which would read back as an empty slot
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct GameEntity {}
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Cursor {
     pub key: EntityKey,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct DeathMarker {
     pub key: EntityKey,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Villager {
     pub key: EntityKey,
     pub last_ate: Ticks,
@@ -25,16 +30,16 @@ impl Villager {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Farm {
     pub key: EntityKey,
     pub last_grew: Ticks,
-    pub x: u8,
-    pub y: u8,
+    pub x: GridUnit,
+    pub y: GridUnit,
 }
 
 impl Farm {
-    pub fn new(key: EntityKey, x: u8, y: u8, now: Ticks) -> Self {
+    pub fn new(key: EntityKey, x: GridUnit, y: GridUnit, now: Ticks) -> Self {
         Farm {
             key,
             last_grew: now,
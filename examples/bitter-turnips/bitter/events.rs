@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::bitter::{Coords, Direction, EntityKey};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum WorldEvent {
     CursorMoved(Direction),
     GravesCleared,
@@ -1,25 +1,31 @@
 use serde::{Deserialize, Serialize};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use sweet_turnips::default_game_config_setup;
+use sweet_turnips::actions::ActionMap;
+use sweet_turnips::config::{ConfigPath, ConfigWatcher, Migrations, Validate, ValidationReport};
+use sweet_turnips::event::KeyCode;
+use sweet_turnips::sprites::GridUnit;
+use sweet_turnips::{default_config_layers, default_game_config_setup};
+
+use crate::bitter::{GRID_HEIGHT, GRID_WIDTH};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct VillagerConfig {
-    pub x: u8,
-    pub y: u8,
+    pub x: GridUnit,
+    pub y: GridUnit,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct FarmConfig {
-    pub x: u8,
-    pub y: u8,
+    pub x: GridUnit,
+    pub y: GridUnit,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct CursorConfig {
-    pub x: u8,
-    pub y: u8,
+    pub x: GridUnit,
+    pub y: GridUnit,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -29,9 +35,76 @@ pub struct WorldConfig {
     pub starting_cursor: CursorConfig,
 }
 
+/// Everything a player can do from the keyboard
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveUp,
+    MoveLeft,
+    MoveDown,
+    MoveRight,
+    SpawnVillager,
+    CycleTheme,
+    QuickSave,
+    QuickLoad,
+    Quit,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct GameConfig {
     pub world: WorldConfig,
+    pub controls: ActionMap<Action>,
+}
+
+// Villagers, farms and the cursor all have to stay inside the border
+fn check_within_border(path: &ConfigPath, report: &mut ValidationReport, x: GridUnit, y: GridUnit) {
+    let (max_x, max_y) = (GRID_WIDTH - 2, GRID_HEIGHT - 2);
+
+    if x < 1 || x > max_x {
+        let message = format!("x = {} is outside of the border (1 to {})", x, max_x);
+        report.error(path.field("x"), message);
+    }
+
+    if y < 1 || y > max_y {
+        let message = format!("y = {} is outside of the border (1 to {})", y, max_y);
+        report.error(path.field("y"), message);
+    }
+}
+
+impl Validate for VillagerConfig {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        check_within_border(path, report, self.x, self.y);
+    }
+}
+
+impl Validate for FarmConfig {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        check_within_border(path, report, self.x, self.y);
+    }
+}
+
+impl Validate for CursorConfig {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        check_within_border(path, report, self.x, self.y);
+    }
+}
+
+impl Validate for WorldConfig {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        self.starting_villagers
+            .validate(&path.field("starting_villagers"), report);
+        self.starting_farms
+            .validate(&path.field("starting_farms"), report);
+        self.starting_cursor
+            .validate(&path.field("starting_cursor"), report);
+    }
+}
+
+impl Validate for GameConfig {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        self.world.validate(&path.field("world"), report);
+        self.controls.validate(&path.field("controls"), report);
+    }
 }
 
 fn example_game_config() -> GameConfig {
@@ -45,11 +118,54 @@ fn example_game_config() -> GameConfig {
         starting_cursor: CursorConfig { x: 2, y: 2 },
     };
 
+    let controls = ActionMap::new()
+        .bind(Action::MoveUp, KeyCode::W)
+        .bind(Action::MoveUp, KeyCode::Up)
+        .bind(Action::MoveLeft, KeyCode::A)
+        .bind(Action::MoveLeft, KeyCode::Left)
+        .bind(Action::MoveDown, KeyCode::S)
+        .bind(Action::MoveDown, KeyCode::Down)
+        .bind(Action::MoveRight, KeyCode::D)
+        .bind(Action::MoveRight, KeyCode::Right)
+        .bind(Action::SpawnVillager, KeyCode::Space)
+        .bind(Action::CycleTheme, KeyCode::T)
+        .bind(Action::QuickSave, KeyCode::F5)
+        .bind(Action::QuickLoad, KeyCode::F9)
+        .bind(Action::Quit, KeyCode::Escape);
+
     GameConfig {
         world: world_config,
+        controls,
     }
 }
 
-pub fn setup_game_config(config_path: PathBuf) -> GameConfig {
-    default_game_config_setup(config_path, example_game_config())
+// Bump this and register a migration whenever GameConfig changes shape, so that existing
+// config files get upgraded instead of failing to load
+const CONFIG_VERSION: u32 = 1;
+
+fn migrations() -> Migrations {
+    Migrations::new(CONFIG_VERSION)
+}
+
+pub fn setup_game_config(config_path: PathBuf, user_config_path: &Path) -> GameConfig {
+    default_game_config_setup(
+        config_path,
+        user_config_path,
+        example_game_config(),
+        &migrations(),
+    )
+}
+
+pub fn watch_game_config(
+    config_path: PathBuf,
+    user_config_path: &Path,
+) -> ConfigWatcher<GameConfig> {
+    let config_layers = default_config_layers(
+        &config_path,
+        user_config_path,
+        &example_game_config(),
+        &migrations(),
+    );
+
+    ConfigWatcher::new(config_layers)
 }
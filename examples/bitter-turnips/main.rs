@@ -4,38 +4,79 @@ mod render;
 
 use bitter::{Coords, Direction, EntityKey, Ticks, World, GRID_HEIGHT, GRID_WIDTH};
 
-use config::{GameConfig, WorldConfig};
+use config::{Action, GameConfig, WorldConfig};
 
 use sweet_turnips;
+use sweet_turnips::actions::{ActionHandler, ActionMap};
+use sweet_turnips::config::{ConfigWatcher, Migrations};
 use sweet_turnips::event;
 use sweet_turnips::event::{KeyCode, KeyMods};
+use sweet_turnips::saves::Saves;
 use sweet_turnips::sprites::SpriteContext;
-use sweet_turnips::AppConfig;
+use sweet_turnips::{AppConfig, CliOptions};
 use sweet_turnips::{Context, GameResult};
 
+use serde_yaml::Value;
+
 use std::convert::From;
 
 const GAME_NAME: &str = "bitter-turnips";
 const AUTHOR_NAME: &str = "JC Holder";
 
+const QUICKSAVE_SLOT: &str = "quicksave";
+
+// Bump this and register a migration whenever World changes shape, so that existing saves can
+// still be loaded
+const SAVE_VERSION: u32 = 2;
+
+fn save_migrations() -> Migrations {
+    Migrations::new(SAVE_VERSION).migration(1, add_world_seed)
+}
+
+// Version 2 gave each world its own seed, so older saves carry on with a random one
+fn add_world_seed(save: &mut Value) -> Result<(), String> {
+    let world = save.as_mapping_mut().ok_or("the save is not a mapping")?;
+
+    world.insert("seed".into(), rand::random::<u64>().into());
+    world.insert("rolls".into(), 0.into());
+
+    Ok(())
+}
+
 struct MainState {
     world: World,
     sprite_context: SpriteContext,
+    config_watcher: ConfigWatcher<GameConfig>,
+    controls: ActionMap<Action>,
+    saves: Saves,
+    seed: Option<u64>,
     selected_villager_key: Option<EntityKey>,
     ticks: Ticks,
+    tick_rate: usize,
 }
 
 impl MainState {
-    fn new(ctx: &mut Context, game_config: GameConfig) -> GameResult<MainState> {
-        let sprite_context = SpriteContext::new(ctx)?;
+    fn new(
+        ctx: &mut Context,
+        app_config: &AppConfig,
+        game_config: GameConfig,
+        config_watcher: ConfigWatcher<GameConfig>,
+        seed: Option<u64>,
+    ) -> GameResult<MainState> {
+        let sprite_context = SpriteContext::from_app_config(ctx, app_config)?;
 
         let ticks: Ticks = 0;
 
         let s = MainState {
-            world: game_config.world.into(),
+            world: new_world(game_config.world, seed),
             sprite_context,
+            config_watcher,
+            controls: game_config.controls,
+            saves: Saves::new(app_config.save_dir(), save_migrations()),
+            seed,
             selected_villager_key: None,
             ticks,
+            tick_rate: app_config.tick_rate(),
         };
         Ok(s)
     }
@@ -47,13 +88,77 @@ impl MainState {
     fn spawn_egg(&mut self, coords: Coords) {
         self.world = self.world.with_egg_spawn_requested_at(coords);
     }
+
+    // Reports back with a notice so any config error on the overlay stays put
+    fn quick_save(&mut self) {
+        match self.saves.save(QUICKSAVE_SLOT, &self.world) {
+            Ok(_) => self.sprite_context.show_notice("Saved"),
+            Err(e) => self
+                .sprite_context
+                .show_notice(format!("Could not save\n{}", e)),
+        }
+    }
+
+    fn quick_load(&mut self) {
+        match self.saves.load(QUICKSAVE_SLOT) {
+            Ok(world) => {
+                self.world = world;
+                self.selected_villager_key = None;
+                self.sprite_context.show_notice("Loaded");
+            }
+            Err(e) => self
+                .sprite_context
+                .show_notice(format!("Could not load\n{}", e)),
+        }
+    }
+
+    // Rebuilds the world from scratch whenever config.yaml is saved, keeping the old one if the
+    // new config has problems
+    fn apply_config_changes(&mut self) {
+        match self.config_watcher.poll() {
+            Some(Ok(game_config)) => {
+                self.world = new_world(game_config.world, self.seed);
+                self.controls = game_config.controls;
+                self.selected_villager_key = None;
+                self.sprite_context.clear_overlay();
+            }
+            Some(Err(report)) => {
+                let message = format!("Could not reload config\n{}", report);
+
+                self.sprite_context.show_overlay(message);
+            }
+            None => (),
+        }
+    }
+}
+
+impl ActionHandler<Action> for MainState {
+    fn action_map(&self) -> &ActionMap<Action> {
+        &self.controls
+    }
+
+    fn perform(&mut self, ctx: &mut Context, action: Action) {
+        match action {
+            Action::MoveUp => self.move_cursor(Direction::Up),
+            Action::MoveLeft => self.move_cursor(Direction::Left),
+            Action::MoveDown => self.move_cursor(Direction::Down),
+            Action::MoveRight => self.move_cursor(Direction::Right),
+            Action::SpawnVillager => self.spawn_egg(self.world.cursor_coords()),
+            Action::CycleTheme => self.sprite_context.cycle_theme(),
+            Action::QuickSave => self.quick_save(),
+            Action::QuickLoad => self.quick_load(),
+            Action::Quit => event::quit(ctx),
+        }
+    }
 }
 
 impl event::EventHandler for MainState {
     fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        self.apply_config_changes();
+
         self.ticks += 1;
 
-        if (self.ticks + 1) % 40 == 0 {
+        if (self.ticks + 1) % self.tick_rate == 0 {
             self.world = self.world.ticked();
         } else {
             self.world = self.world.events_processed();
@@ -71,19 +176,11 @@ impl event::EventHandler for MainState {
         _keymods: KeyMods,
         _repeat: bool,
     ) {
-        match keycode {
-            KeyCode::Escape => event::quit(ctx),
-            KeyCode::W => self.move_cursor(Direction::Up),
-            KeyCode::A => self.move_cursor(Direction::Left),
-            KeyCode::S => self.move_cursor(Direction::Down),
-            KeyCode::D => self.move_cursor(Direction::Right),
-            KeyCode::Space => self.spawn_egg(self.world.cursor_coords()),
-            _ => (),
-        }
+        self.handle_key_down(ctx, keycode);
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        let sprite_grid = render::sprite_grid_from_world(&self.world, self.selected_villager_key);
+        let sprite_grid = render::sprite_grid_from_world(&self.world, self.selected_villager_key)?;
 
         self.sprite_context.render_sprite_grid(sprite_grid, ctx)?;
 
@@ -110,18 +207,116 @@ impl From<WorldConfig> for World {
     }
 }
 
+fn new_world(world_config: WorldConfig, seed: Option<u64>) -> World {
+    let world = World::from(world_config);
+
+    match seed {
+        Some(seed) => world.seeded(seed),
+        None => world,
+    }
+}
+
+// Runs the world without a window and reports how the colony fared. Each tick's events are
+// processed straight away, as update() would before the next one.
+fn run_headless(world: World, ticks: usize) {
+    let mut world = world;
+
+    for _ in 0..ticks {
+        world = world.ticked().events_processed();
+    }
+
+    println!(
+        "After {} ticks: {} villagers, {} farms",
+        ticks,
+        world.villagers.len(),
+        world.farms.len()
+    );
+}
+
 pub fn main() -> GameResult {
+    let cli_options = CliOptions::from_env()?;
+
+    if cli_options.help {
+        println!("{}", CliOptions::usage(GAME_NAME));
+        return Ok(());
+    }
+
     let app_config = AppConfig::new((GRID_WIDTH, GRID_HEIGHT))
         .game_name(GAME_NAME)
-        .author_name(AUTHOR_NAME);
+        .author_name(AUTHOR_NAME)
+        .tick_rate(40)
+        .cli_options(&cli_options)
+        .finalize()?;
 
     let config_path = sweet_turnips::prep_config_path(&app_config)?;
 
-    let game_config = config::setup_game_config(config_path);
+    let user_config_path = app_config.user_config_path();
+
+    let game_config = config::setup_game_config(config_path.clone(), &user_config_path);
+
+    if let Some(ticks) = cli_options.headless_ticks {
+        run_headless(new_world(game_config.world, cli_options.seed), ticks);
+        return Ok(());
+    }
+
+    let config_watcher = config::watch_game_config(config_path, &user_config_path);
 
     let (ctx, event_loop) = &mut sweet_turnips::build_context_and_event_loop(&app_config)?;
 
-    let state = &mut MainState::new(ctx, game_config)?;
+    let state = &mut MainState::new(
+        ctx,
+        &app_config,
+        game_config,
+        config_watcher,
+        cli_options.seed,
+    )?;
 
     event::run(ctx, event_loop, state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn saves_from_before_world_seeds_still_load() {
+        let dir =
+            std::env::temp_dir().join(format!("bitter-turnips-old-save-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut world = World::new().seeded(7);
+        world.add_villager_at(4, 4);
+        world.add_farm_at(5, 4);
+        world.add_cursor_at((2, 2));
+
+        // The world as it was saved, apart from what version 2 added
+        let mut saved = serde_yaml::to_value(&world).unwrap();
+        let saved = saved.as_mapping_mut().unwrap();
+        saved.remove(&"seed".into());
+        saved.remove(&"rolls".into());
+
+        // A version 1 save, from before worlds had seeds
+        let mut old_save = saved.clone();
+        old_save.insert("version".into(), 1.into());
+
+        let saves = Saves::new(&dir, save_migrations());
+        fs::write(saves.path("old"), serde_yaml::to_string(&old_save).unwrap()).unwrap();
+
+        let loaded: World = saves.load("old").unwrap();
+        let mut loaded = serde_yaml::to_value(&loaded).unwrap();
+
+        assert_eq!(loaded["rolls"], Value::from(0));
+        assert!(loaded["seed"].as_u64().is_some());
+
+        let loaded = loaded.as_mapping_mut().unwrap();
+        loaded.remove(&"seed".into());
+        loaded.remove(&"rolls".into());
+
+        assert_eq!(loaded, saved);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,5 +1,5 @@
-use crate::bitter::{EntityKey, World};
-use sweet_turnips::sprites::{Color, Sprite, SpriteGrid};
+use crate::bitter::{Coords, EntityKey, World, GRID_HEIGHT, GRID_WIDTH};
+use sweet_turnips::sprites::{Color, GridUnit, OutOfBoundsError, Sprite, SpriteGrid};
 
 const RED: Color = Color {
     r: 1.0,
@@ -11,60 +11,72 @@ const RED: Color = Color {
 // This trait exists solely to map more domain-specific
 // (i.e. game-specific) language onto SpriteGrid's commands
 trait BitterSpriteGrid {
-    fn border_at(&mut self, x: u8, y: u8);
-    fn farm_at(&mut self, x: u8, y: u8);
-    fn villager_at(&mut self, color: Color, x: u8, y: u8);
-    fn death_marker_at(&mut self, x: u8, y: u8);
-    fn cursor_at(&mut self, x: u8, y: u8);
+    fn border_at(&mut self, x: GridUnit, y: GridUnit) -> Result<(), OutOfBoundsError>;
+    fn farm_at(&mut self, x: GridUnit, y: GridUnit) -> Result<(), OutOfBoundsError>;
+    fn villager_at(
+        &mut self,
+        color: Color,
+        x: GridUnit,
+        y: GridUnit,
+    ) -> Result<(), OutOfBoundsError>;
+    fn death_marker_at(&mut self, x: GridUnit, y: GridUnit) -> Result<(), OutOfBoundsError>;
+    fn cursor_at(&mut self, x: GridUnit, y: GridUnit) -> Result<(), OutOfBoundsError>;
 }
 
 impl BitterSpriteGrid for SpriteGrid {
-    fn border_at(&mut self, x: u8, y: u8) {
-        self.render_sprite_at(Sprite::big_circle(), x, y);
+    fn border_at(&mut self, x: GridUnit, y: GridUnit) -> Result<(), OutOfBoundsError> {
+        self.render_sprite_at(Sprite::big_circle(), x, y)
     }
 
-    fn farm_at(&mut self, x: u8, y: u8) {
-        self.render_sprite_at(Sprite::turnip().colored(RED), x, y);
+    fn farm_at(&mut self, x: GridUnit, y: GridUnit) -> Result<(), OutOfBoundsError> {
+        self.render_sprite_at(Sprite::turnip().colored(RED), x, y)
     }
 
-    fn villager_at(&mut self, color: Color, x: u8, y: u8) {
-        self.render_sprite_at(Sprite::lizard().colored(color), x, y);
+    fn villager_at(
+        &mut self,
+        color: Color,
+        x: GridUnit,
+        y: GridUnit,
+    ) -> Result<(), OutOfBoundsError> {
+        self.render_sprite_at(Sprite::lizard().colored(color), x, y)
     }
 
-    fn death_marker_at(&mut self, x: u8, y: u8) {
-        self.render_sprite_at(Sprite::skull(), x, y);
+    fn death_marker_at(&mut self, x: GridUnit, y: GridUnit) -> Result<(), OutOfBoundsError> {
+        self.render_sprite_at(Sprite::skull(), x, y)
     }
 
-    fn cursor_at(&mut self, x: u8, y: u8) {
-        self.render_sprite_at(Sprite::cursor(), x, y);
+    fn cursor_at(&mut self, x: GridUnit, y: GridUnit) -> Result<(), OutOfBoundsError> {
+        self.render_sprite_at(Sprite::cursor(), x, y)
     }
 }
 
 pub fn sprite_grid_from_world(
     world: &World,
     selected_villager_key: Option<EntityKey>,
-) -> SpriteGrid {
+) -> Result<SpriteGrid, OutOfBoundsError> {
     let selected_villager = match selected_villager_key {
         Some(key) => world.villager(key),
         None => None,
     };
 
-    let mut sprite_grid = SpriteGrid::new();
+    let mut sprite_grid = SpriteGrid::new(GRID_WIDTH, GRID_HEIGHT);
 
-    for x in 0..8 {
-        sprite_grid.border_at(x, 0);
-        sprite_grid.border_at(x, 7);
+    let (max_x, max_y) = (GRID_WIDTH - 1, GRID_HEIGHT - 1);
+
+    for x in 0..GRID_WIDTH {
+        sprite_grid.border_at(x, 0)?;
+        sprite_grid.border_at(x, max_y)?;
     }
 
-    for y in 0..8 {
-        sprite_grid.border_at(0, y);
-        sprite_grid.border_at(7, y);
+    for y in 0..GRID_HEIGHT {
+        sprite_grid.border_at(0, y)?;
+        sprite_grid.border_at(max_x, y)?;
     }
 
-    let farm_coords: Vec<(u8, u8)> = world.farms.values().map(|v| world.coords[v.key]).collect();
+    let farm_coords: Vec<Coords> = world.farms.values().map(|v| world.coords[v.key]).collect();
 
     for (x, y) in farm_coords {
-        sprite_grid.farm_at(x, y);
+        sprite_grid.farm_at(x, y)?;
     }
 
     for key in world.villagers.keys() {
@@ -80,27 +92,27 @@ pub fn sprite_grid_from_world(
             a: 1.0,
         };
 
-        sprite_grid.villager_at(color, x, y);
+        sprite_grid.villager_at(color, x, y)?;
     }
 
     if let Some(villager) = selected_villager {
         let satiation = world.satiation[villager.key];
 
-        for x in 1..7 {
-            if satiation >= x {
-                sprite_grid.farm_at(x, 7);
+        for x in 1..max_x {
+            if satiation as GridUnit >= x {
+                sprite_grid.farm_at(x, max_y)?;
             }
         }
     }
 
     for dm in world.death_markers.values() {
         let (x, y) = world.coords[dm.key];
-        sprite_grid.death_marker_at(x, y);
+        sprite_grid.death_marker_at(x, y)?;
     }
 
     let coords = world.cursor_coords();
 
-    sprite_grid.cursor_at(coords.0 + 1, coords.1 + 1);
+    sprite_grid.cursor_at(coords.0 + 1, coords.1 + 1)?;
 
-    sprite_grid
+    Ok(sprite_grid)
 }
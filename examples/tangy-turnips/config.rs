@@ -1,30 +1,108 @@
 use serde::{Deserialize, Serialize};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use sweet_turnips::default_game_config_setup;
+use sweet_turnips::actions::ActionMap;
+use sweet_turnips::config::{ConfigPath, ConfigWatcher, Migrations, Validate, ValidationReport};
+use sweet_turnips::event::KeyCode;
+use sweet_turnips::midi::{MidiBindings, MidiConfig};
+use sweet_turnips::sprites::GridUnit;
+use sweet_turnips::{default_config_layers, default_game_config_setup};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+use crate::tangy::{GRID_HEIGHT, GRID_WIDTH};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PlayerShipConfig {
-    pub x: u8,
-    pub y: u8,
+    pub x: GridUnit,
+    pub y: GridUnit,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct EnemyShipConfig {
-    pub x: u8,
-    pub y: u8,
+    pub x: GridUnit,
+    pub y: GridUnit,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WorldConfig {
     pub starting_player_ship: PlayerShipConfig,
     pub starting_enemy_ships: Vec<EnemyShipConfig>,
 }
 
+/// Everything a player can do from the keyboard
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveUp,
+    MoveLeft,
+    MoveDown,
+    MoveRight,
+    Fire,
+    CycleTheme,
+    QuickSave,
+    QuickLoad,
+    LearnMidi,
+    Quit,
+}
+
+/// Everything a player can do from a MIDI controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiAction {
+    MoveUp,
+    MoveLeft,
+    MoveDown,
+    MoveRight,
+    Fire,
+    /// Frames between world ticks, set by how far a knob or fader is turned
+    TickSpeed,
+}
+
+impl MidiAction {
+    pub const ALL: [MidiAction; 6] = [
+        MidiAction::MoveUp,
+        MidiAction::MoveLeft,
+        MidiAction::MoveDown,
+        MidiAction::MoveRight,
+        MidiAction::Fire,
+        MidiAction::TickSpeed,
+    ];
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct GameConfig {
     pub world: WorldConfig,
+    pub controls: ActionMap<Action>,
+    pub midi: MidiConfig<MidiAction>,
+}
+
+impl Validate for PlayerShipConfig {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        report.check_within_grid(path, (self.x, self.y), (GRID_WIDTH, GRID_HEIGHT));
+    }
+}
+
+impl Validate for EnemyShipConfig {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        report.check_within_grid(path, (self.x, self.y), (GRID_WIDTH, GRID_HEIGHT));
+    }
+}
+
+impl Validate for WorldConfig {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        self.starting_player_ship
+            .validate(&path.field("starting_player_ship"), report);
+        self.starting_enemy_ships
+            .validate(&path.field("starting_enemy_ships"), report);
+    }
+}
+
+impl Validate for GameConfig {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        self.world.validate(&path.field("world"), report);
+        self.controls.validate(&path.field("controls"), report);
+        self.midi.validate(&path.field("midi"), report);
+    }
 }
 
 fn example_game_config() -> GameConfig {
@@ -33,11 +111,103 @@ fn example_game_config() -> GameConfig {
         starting_enemy_ships: vec![EnemyShipConfig { x: 3, y: 1 }],
     };
 
+    let controls = ActionMap::new()
+        .bind(Action::MoveUp, KeyCode::W)
+        .bind(Action::MoveUp, KeyCode::Up)
+        .bind(Action::MoveLeft, KeyCode::A)
+        .bind(Action::MoveLeft, KeyCode::Left)
+        .bind(Action::MoveDown, KeyCode::S)
+        .bind(Action::MoveDown, KeyCode::Down)
+        .bind(Action::MoveRight, KeyCode::D)
+        .bind(Action::MoveRight, KeyCode::Right)
+        .bind(Action::Fire, KeyCode::Space)
+        .bind(Action::CycleTheme, KeyCode::T)
+        .bind(Action::QuickSave, KeyCode::F5)
+        .bind(Action::QuickLoad, KeyCode::F9)
+        .bind(Action::LearnMidi, KeyCode::F2)
+        .bind(Action::Quit, KeyCode::Escape);
+
+    // Named in resources/midi-profiles/nanokontrol2.yaml
+    let midi_bindings = MidiBindings::new()
+        .bind(MidiAction::MoveLeft, "rewind")
+        .bind(MidiAction::MoveRight, "fast_forward")
+        .bind(MidiAction::Fire, "play")
+        .bind(MidiAction::TickSpeed, "knob_1");
+
     GameConfig {
         world: world_config,
+        controls,
+        midi: MidiConfig {
+            bindings: midi_bindings,
+            ..MidiConfig::default()
+        },
     }
 }
 
-pub fn setup_game_config(config_path: PathBuf) -> GameConfig {
-    default_game_config_setup(config_path, example_game_config())
+// Bump this and register a migration whenever GameConfig changes shape, so that existing
+// config files get upgraded instead of failing to load
+const CONFIG_VERSION: u32 = 1;
+
+fn migrations() -> Migrations {
+    Migrations::new(CONFIG_VERSION)
+}
+
+pub fn setup_game_config(config_path: PathBuf, user_config_path: &Path) -> GameConfig {
+    default_game_config_setup(
+        config_path,
+        user_config_path,
+        example_game_config(),
+        &migrations(),
+    )
+}
+
+pub fn watch_game_config(
+    config_path: PathBuf,
+    user_config_path: &Path,
+) -> ConfigWatcher<GameConfig> {
+    let config_layers = default_config_layers(
+        &config_path,
+        user_config_path,
+        &example_game_config(),
+        &migrations(),
+    );
+
+    ConfigWatcher::new(config_layers)
+}
+
+/// Writes the MIDI bindings for `actions` into the user config, so that ones learned in game are
+/// kept for next time
+pub fn save_midi_bindings(
+    user_config_path: &Path,
+    bindings: &MidiBindings<MidiAction>,
+    actions: &[MidiAction],
+) -> Result<(), String> {
+    let bindings_path = ConfigPath::root().field("midi").field("bindings");
+
+    for action in actions {
+        bindings.save_binding(user_config_path, &bindings_path, *action, &migrations())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sweet_turnips::config::ConfigLayers;
+
+    #[test]
+    fn a_clock_tick_needs_at_least_one_pulse() {
+        let report = ConfigLayers::new(&example_game_config())
+            .migrations(migrations())
+            .args(vec!["--set", "midi.clock_pulses_per_tick=0"])
+            .resolve::<GameConfig>()
+            .unwrap_err();
+
+        let errors = report.errors();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path.to_string(), "midi.clock_pulses_per_tick");
+    }
 }
@@ -0,0 +1,646 @@
+use sweet_turnips::midi::{
+    MidiBindings, MidiClock, MidiControl, MidiDeviceEvent, MidiError, MidiLearn, MidiMessage,
+    MidiOutput, MidiProfile, MidiRecorder, MidiSource,
+};
+
+use std::path::PathBuf;
+
+use crate::config::MidiAction;
+use crate::tangy::{Direction, Ticks, World};
+
+// Buttons and switches count as pressed from halfway up
+const BUTTON_PRESSED: u8 = 64;
+
+// Controllers like the nanoKONTROL2 (with its LEDs in external mode) light a button when sent
+// its own control
+const LIGHT_ON: u8 = 127;
+const LIGHT_OFF: u8 = 0;
+const FIRE_LIGHT_FRAMES: Ticks = 10;
+
+// Where MIDI is coming from (a controller or a recording of one), and what its controls are
+// called
+pub struct MidiDevice {
+    // Held so that MIDI keeps arriving until the game quits
+    pub source: Box<dyn MidiSource>,
+    pub profile: Option<MidiProfile>,
+    // Set when recording what's played, to be saved when the game quits
+    pub recorder: Option<MidiRecorder>,
+    // Set once the source has run out, so that it's only announced once
+    finished: bool,
+}
+
+impl MidiDevice {
+    pub fn new(source: Box<dyn MidiSource>, profile: Option<MidiProfile>) -> Self {
+        Self {
+            source,
+            profile,
+            recorder: None,
+            finished: false,
+        }
+    }
+
+    pub fn recorder(self, recorder: Option<MidiRecorder>) -> Self {
+        Self { recorder, ..self }
+    }
+}
+
+// What happened over MIDI that the window has to show or act on
+#[derive(Clone, Debug, PartialEq)]
+pub enum MidiNews {
+    Connected(String),
+    Disconnected(String),
+    // The configured port can't be picked out, but is still being watched for
+    NotFound(MidiError),
+    RecordingFinished,
+    // These actions were bound by MIDI learn, or lost their control to one that was
+    Learned(Vec<MidiAction>),
+    // Feedback was turned off after a light couldn't be sent
+    FeedbackStopped(MidiError),
+    Profile(ProfileNews),
+}
+
+// What came of looking for a device's profile, which matters to whoever's setting it up
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProfileNews {
+    // The name of the profile that's being used
+    Found(String),
+    // The port that has no profile
+    Missing(String),
+    // A profile that couldn't be read, and was passed over
+    Skipped(MidiError),
+    // Two actions bound to names that turn out to be the same control on the named profile
+    SharedControl(MidiAction, MidiAction, String),
+    // Control names in the bindings that the profile (if there is one) doesn't have
+    UnknownNames(Vec<String>),
+}
+
+// The world and everything that drives it, apart from the window, so that MIDI can be played
+// into it from tests
+pub struct Game {
+    pub world: World,
+    pub midi_bindings: MidiBindings<MidiAction>,
+    // Set while the player is binding a MIDI control
+    pub midi_learn: Option<MidiLearn<MidiAction>>,
+    pub midi: Option<MidiDevice>,
+    midi_profile_dirs: Vec<PathBuf>,
+    // Takes over from tick_speed while a sequencer is playing
+    pub midi_clock: MidiClock,
+    pub midi_out: Option<Box<dyn MidiOutput>>,
+    fire_light_off_at: Option<Ticks>,
+    // News from between updates (like firing from the keyboard), handed over by the next one
+    news: Vec<MidiNews>,
+    pub tick_speed: usize,
+    ticks: Ticks,
+}
+
+impl Game {
+    pub fn new(
+        world: World,
+        midi_bindings: MidiBindings<MidiAction>,
+        midi_clock: MidiClock,
+        tick_speed: usize,
+    ) -> Self {
+        Self {
+            world,
+            midi_bindings,
+            midi_learn: None,
+            midi: None,
+            midi_profile_dirs: vec![],
+            midi_clock,
+            midi_out: None,
+            fire_light_off_at: None,
+            news: vec![],
+            tick_speed,
+            ticks: 0,
+        }
+    }
+
+    pub fn midi(self, midi: Option<MidiDevice>) -> Self {
+        Self { midi, ..self }
+    }
+
+    // Where to look for the profile of a device plugged in later
+    pub fn midi_profile_dirs(self, midi_profile_dirs: Vec<PathBuf>) -> Self {
+        Self {
+            midi_profile_dirs,
+            ..self
+        }
+    }
+
+    pub fn midi_out(self, midi_out: Option<Box<dyn MidiOutput>>) -> Self {
+        Self { midi_out, ..self }
+    }
+
+    pub fn midi_profile(&self) -> Option<&MidiProfile> {
+        self.midi.as_ref()?.profile.as_ref()
+    }
+
+    pub fn move_player_ship(&mut self, direction: Direction) {
+        self.world = self.world.with_player_ship_move_requested(direction);
+    }
+
+    pub fn fire_bullets(&mut self) {
+        self.world = self.world.with_player_bullets_fired();
+
+        self.set_fire_light(LIGHT_ON);
+        self.fire_light_off_at = Some(self.ticks + FIRE_LIGHT_FRAMES);
+    }
+
+    pub fn start_midi_learn(&mut self) {
+        self.midi_learn = MidiLearn::new(MidiAction::ALL.to_vec());
+    }
+
+    // Reads what's arrived over MIDI and moves the world on by a frame
+    pub fn update(&mut self) -> Vec<MidiNews> {
+        let mut news = vec![];
+
+        let (device_events, messages) = match &mut self.midi {
+            Some(midi) => {
+                // Checked first, so that the last of a recording is polled before it's announced
+                let finished = !midi.finished && midi.source.is_finished();
+
+                let device_events = midi.source.poll_events();
+                let stamped = midi.source.poll_stamped();
+
+                if let Some(recorder) = &mut midi.recorder {
+                    for (stamp, message) in stamped.iter() {
+                        recorder.record_stamped(*stamp, message.clone());
+                    }
+                }
+
+                let messages: Vec<MidiMessage> =
+                    stamped.into_iter().map(|(_, message)| message).collect();
+
+                if finished {
+                    midi.finished = true;
+                    news.push(MidiNews::RecordingFinished);
+                }
+
+                (device_events, messages)
+            }
+            None => (vec![], vec![]),
+        };
+
+        for event in device_events {
+            news.extend(self.handle_midi_device_event(event));
+        }
+
+        let mut clock_ticks = 0;
+
+        for message in messages.iter() {
+            if self.midi_clock.handle(message) {
+                clock_ticks += 1;
+            }
+
+            if let Some(learned) = self.handle_midi(message) {
+                news.push(learned);
+            }
+        }
+
+        self.ticks += 1;
+
+        if self.fire_light_off_at == Some(self.ticks) {
+            self.set_fire_light(LIGHT_OFF);
+            self.fire_light_off_at = None;
+        }
+
+        let world_ticks = if self.midi_clock.is_running() {
+            clock_ticks
+        } else if (self.ticks + 1) % self.tick_speed == 0 {
+            1
+        } else {
+            0
+        };
+
+        if world_ticks == 0 {
+            self.world = self.world.with_events_processed();
+        }
+
+        for _ in 0..world_ticks {
+            self.world = self.world.ticked();
+        }
+
+        news.append(&mut self.news);
+
+        news
+    }
+
+    // Lights up whichever control fires, if it's bound to one
+    fn set_fire_light(&mut self, value: u8) {
+        let fire_control = self
+            .midi_bindings
+            .control_for(MidiAction::Fire, self.midi_profile());
+
+        let midi_out = match &mut self.midi_out {
+            Some(midi_out) => midi_out,
+            None => return,
+        };
+
+        let sent = match fire_control {
+            Some(MidiControl::Controller {
+                channel,
+                controller,
+            }) => midi_out.control_change(channel, controller, value),
+            Some(MidiControl::Note { channel, note }) => midi_out.note_on(channel, note, value),
+            None => Ok(()),
+        };
+
+        if let Err(e) = sent {
+            self.news.push(MidiNews::FeedbackStopped(e));
+            self.midi_out = None;
+        }
+    }
+
+    fn handle_midi_device_event(&mut self, event: MidiDeviceEvent) -> Vec<MidiNews> {
+        match event {
+            MidiDeviceEvent::DeviceConnected(port_name) => {
+                let mut news = vec![MidiNews::Connected(port_name.clone())];
+
+                if let Some(midi) = &mut self.midi {
+                    let (profile, profile_news) =
+                        find_midi_profile(&self.midi_profile_dirs, &port_name, &self.midi_bindings);
+
+                    midi.profile = profile;
+                    news.extend(profile_news.into_iter().map(MidiNews::Profile));
+                }
+
+                news
+            }
+            MidiDeviceEvent::DeviceDisconnected(port_name) => {
+                vec![MidiNews::Disconnected(port_name)]
+            }
+            MidiDeviceEvent::NotFound(e) => vec![MidiNews::NotFound(e)],
+        }
+    }
+
+    fn handle_midi(&mut self, message: &MidiMessage) -> Option<MidiNews> {
+        if let Some(midi_learn) = &self.midi_learn {
+            let (action, control) = midi_learn.capture(message)?;
+            return Some(self.learn_midi_binding(action, control));
+        }
+
+        match self.midi_bindings.action_for(message, self.midi_profile()) {
+            Some((MidiAction::TickSpeed, value)) => self.tick_speed = value.max(1) as usize,
+            Some((action, value)) if value >= BUTTON_PRESSED => match action {
+                MidiAction::MoveUp => self.move_player_ship(Direction::Up),
+                MidiAction::MoveLeft => self.move_player_ship(Direction::Left),
+                MidiAction::MoveDown => self.move_player_ship(Direction::Down),
+                MidiAction::MoveRight => self.move_player_ship(Direction::Right),
+                MidiAction::Fire => self.fire_bullets(),
+                MidiAction::TickSpeed => (),
+            },
+            _ => (),
+        }
+
+        None
+    }
+
+    fn learn_midi_binding(&mut self, action: MidiAction, control: MidiControl) -> MidiNews {
+        self.midi_learn = None;
+
+        let profile = self.midi.as_ref().and_then(|midi| midi.profile.as_ref());
+        let displaced = self.midi_bindings.rebind(action, control, profile);
+
+        MidiNews::Learned(Some(action).into_iter().chain(displaced).collect())
+    }
+}
+
+// Bindings can only use control names once there's a profile for the port saying what they are
+pub fn find_midi_profile(
+    profile_dirs: &[PathBuf],
+    port_name: &str,
+    bindings: &MidiBindings<MidiAction>,
+) -> (Option<MidiProfile>, Vec<ProfileNews>) {
+    let (profile, errors) = MidiProfile::find_for_port(profile_dirs, port_name);
+
+    let mut news: Vec<ProfileNews> = errors.into_iter().map(ProfileNews::Skipped).collect();
+
+    match &profile {
+        Some(profile) => news.push(ProfileNews::Found(profile.name.clone())),
+        None => news.push(ProfileNews::Missing(port_name.to_string())),
+    }
+
+    // Two names can turn out to be the same control, which only the profile knows
+    if let Some(profile) = &profile {
+        for (action, other_action) in bindings.duplicates(Some(profile)) {
+            news.push(ProfileNews::SharedControl(
+                other_action,
+                action,
+                profile.name.clone(),
+            ));
+        }
+    }
+
+    let unknown_names = bindings.unknown_names(profile.as_ref());
+
+    if !unknown_names.is_empty() {
+        news.push(ProfileNews::UnknownNames(
+            unknown_names.into_iter().map(String::from).collect(),
+        ));
+    }
+
+    (profile, news)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sweet_turnips::midi::{MidiSender, VirtualMidiSource};
+
+    use std::fs;
+
+    use crate::tangy::Coords;
+
+    const FIRE_NOTE: MidiControl = MidiControl::Note {
+        channel: 0,
+        note: 41,
+    };
+    const LEFT_BUTTON: MidiControl = MidiControl::Controller {
+        channel: 0,
+        controller: 43,
+    };
+    const SPEED_KNOB: MidiControl = MidiControl::Controller {
+        channel: 0,
+        controller: 16,
+    };
+
+    // Never ticks by itself, so only the clock moves the world on
+    const SLOW_TICK_SPEED: usize = 1000;
+
+    fn test_bindings() -> MidiBindings<MidiAction> {
+        MidiBindings::new()
+            .bind(MidiAction::Fire, FIRE_NOTE)
+            .bind(MidiAction::MoveLeft, LEFT_BUTTON)
+            .bind(MidiAction::TickSpeed, SPEED_KNOB)
+    }
+
+    // A game listening to a virtual source, and somewhere to push its MIDI from
+    fn test_game(source: VirtualMidiSource) -> (Game, MidiSender) {
+        let sender = source.sender();
+
+        let world = World::new().with_player_ship_added_at((3, 6));
+        let midi = MidiDevice::new(Box::new(source), None);
+
+        let game =
+            Game::new(world, test_bindings(), MidiClock::new(6), SLOW_TICK_SPEED).midi(Some(midi));
+
+        (game, sender)
+    }
+
+    // Feedback to a device that's gone away
+    struct UnpluggedOutput;
+
+    impl MidiOutput for UnpluggedOutput {
+        fn send(&mut self, _: &MidiMessage) -> Result<(), MidiError> {
+            Err(MidiError::SendFailed("the device has gone".to_string()))
+        }
+    }
+
+    // A fresh directory holding each of `profiles` as `name.yaml`
+    fn profile_dir(name: &str, profiles: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("tangy-turnips-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for (file_name, source) in profiles {
+            fs::write(dir.join(format!("{}.yaml", file_name)), source).unwrap();
+        }
+
+        dir
+    }
+
+    fn push(sender: &MidiSender, message: MidiMessage) {
+        sender.send((0, message)).unwrap();
+    }
+
+    fn player_ship_coords(world: &World) -> Coords {
+        let ship = world
+            .player_ships
+            .values()
+            .next()
+            .expect("Found no player ship");
+        world.coords[ship.key]
+    }
+
+    #[test]
+    fn notes_and_controls_drive_their_actions() {
+        let (mut game, sender) = test_game(VirtualMidiSource::new());
+
+        push(
+            &sender,
+            MidiMessage::NoteOn {
+                channel: 0,
+                note: 41,
+                velocity: 100,
+            },
+        );
+        push(
+            &sender,
+            MidiMessage::ControlChange {
+                channel: 0,
+                controller: 43,
+                value: 127,
+            },
+        );
+        push(
+            &sender,
+            MidiMessage::ControlChange {
+                channel: 0,
+                controller: 16,
+                value: 5,
+            },
+        );
+
+        assert_eq!(game.update(), vec![]);
+
+        assert_eq!(game.world.player_bullets.len(), 1);
+        assert_eq!(player_ship_coords(&game.world), (2, 6));
+        assert_eq!(game.tick_speed, 5);
+    }
+
+    #[test]
+    fn buttons_let_go_of_do_nothing() {
+        let (mut game, sender) = test_game(VirtualMidiSource::new());
+
+        push(
+            &sender,
+            MidiMessage::ControlChange {
+                channel: 0,
+                controller: 43,
+                value: 0,
+            },
+        );
+        game.update();
+
+        assert_eq!(player_ship_coords(&game.world), (3, 6));
+    }
+
+    #[test]
+    fn the_clock_ticks_the_world_while_it_runs() {
+        let (mut game, sender) = test_game(VirtualMidiSource::new());
+
+        push(&sender, MidiMessage::Start);
+        for _ in 0..12 {
+            push(&sender, MidiMessage::Clock);
+        }
+        game.update();
+
+        assert_eq!(game.world.ticks, 2);
+
+        push(&sender, MidiMessage::Stop);
+        for _ in 0..12 {
+            push(&sender, MidiMessage::Clock);
+        }
+        game.update();
+
+        assert_eq!(game.world.ticks, 2);
+    }
+
+    #[test]
+    fn learning_binds_the_next_control_touched() {
+        let (mut game, sender) = test_game(VirtualMidiSource::new());
+
+        game.start_midi_learn();
+        // The first action learned is moving up, which takes the fire note
+        push(
+            &sender,
+            MidiMessage::NoteOn {
+                channel: 0,
+                note: 41,
+                velocity: 100,
+            },
+        );
+
+        assert_eq!(
+            game.update(),
+            vec![MidiNews::Learned(vec![
+                MidiAction::MoveUp,
+                MidiAction::Fire
+            ])]
+        );
+
+        assert!(game.midi_learn.is_none());
+        assert_eq!(game.world.player_bullets.len(), 0);
+        assert_eq!(
+            game.midi_bindings.control_for(MidiAction::MoveUp, None),
+            Some(FIRE_NOTE)
+        );
+        assert_eq!(game.midi_bindings.control_for(MidiAction::Fire, None), None);
+    }
+
+    #[test]
+    fn devices_coming_and_going_and_their_profiles_are_news() {
+        let source = VirtualMidiSource::new();
+        source.push_event(MidiDeviceEvent::DeviceConnected("Test Pad".to_string()));
+        source.push_event(MidiDeviceEvent::DeviceDisconnected("Test Pad".to_string()));
+
+        let dir = profile_dir("no-profiles", &[]);
+
+        let (game, _) = test_game(source);
+        let mut game = game.midi_profile_dirs(vec![dir.clone()]);
+
+        assert_eq!(
+            game.update(),
+            vec![
+                MidiNews::Connected("Test Pad".to_string()),
+                MidiNews::Profile(ProfileNews::Missing("Test Pad".to_string())),
+                MidiNews::Disconnected("Test Pad".to_string()),
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn ports_that_cant_be_found_are_news() {
+        let not_found = || MidiError::PortNotFound {
+            wanted: "Test Pad".to_string(),
+            available: vec!["Midi Through".to_string()],
+        };
+
+        let source = VirtualMidiSource::new();
+        source.push_event(MidiDeviceEvent::NotFound(not_found()));
+
+        let (mut game, _) = test_game(source);
+
+        assert_eq!(game.update(), vec![MidiNews::NotFound(not_found())]);
+        assert_eq!(game.update(), vec![]);
+    }
+
+    #[test]
+    fn feedback_that_cant_be_sent_is_stopped_and_is_news() {
+        let (game, sender) = test_game(VirtualMidiSource::new());
+        let mut game = game.midi_out(Some(Box::new(UnpluggedOutput)));
+
+        push(
+            &sender,
+            MidiMessage::NoteOn {
+                channel: 0,
+                note: 41,
+                velocity: 100,
+            },
+        );
+
+        assert_eq!(
+            game.update(),
+            vec![MidiNews::FeedbackStopped(MidiError::SendFailed(
+                "the device has gone".to_string()
+            ))]
+        );
+        assert!(game.midi_out.is_none());
+
+        // Firing from the keyboard has nothing left to light
+        game.fire_bullets();
+
+        assert_eq!(game.update(), vec![]);
+    }
+
+    #[test]
+    fn finding_a_profile_is_news() {
+        let dir = profile_dir(
+            "profiles",
+            &[
+                ("broken", "name: [\n"),
+                (
+                    "pad",
+                    "name: Test Pad\nports: [test pad]\ncontrols:\n  \
+                     big_red: { channel: 0, note: 41 }\n  also_red: { channel: 0, note: 41 }\n",
+                ),
+            ],
+        );
+
+        let bindings = MidiBindings::new()
+            .bind(MidiAction::Fire, "big_red")
+            .bind(MidiAction::MoveUp, "also_red")
+            .bind(MidiAction::MoveDown, "wheel");
+
+        let (profile, news) =
+            find_midi_profile(std::slice::from_ref(&dir), "Test Pad 1", &bindings);
+
+        assert_eq!(
+            profile.map(|profile| profile.name),
+            Some("Test Pad".to_string())
+        );
+
+        assert!(matches!(
+            news[0],
+            ProfileNews::Skipped(MidiError::UnreadableProfile(..))
+        ));
+        assert_eq!(
+            news[1..].to_vec(),
+            vec![
+                ProfileNews::Found("Test Pad".to_string()),
+                ProfileNews::SharedControl(
+                    MidiAction::MoveUp,
+                    MidiAction::Fire,
+                    "Test Pad".to_string()
+                ),
+                ProfileNews::UnknownNames(vec!["wheel".to_string()]),
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
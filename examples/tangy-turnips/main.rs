@@ -1,105 +1,232 @@
 mod config;
+mod game;
 mod render;
 mod tangy;
 
-use tangy::{Direction, Ticks, World, GRID_HEIGHT, GRID_WIDTH};
+use tangy::{Direction, World, GRID_HEIGHT, GRID_WIDTH};
 
-use config::{GameConfig, WorldConfig};
+use config::{Action, GameConfig, MidiAction, WorldConfig};
+use game::{find_midi_profile, Game, MidiDevice, MidiNews, ProfileNews};
 
 use sweet_turnips;
+use sweet_turnips::actions::{action_name, ActionHandler, ActionMap};
+use sweet_turnips::config::{ConfigWatcher, Migrations};
 use sweet_turnips::event;
 use sweet_turnips::event::{KeyCode, KeyMods};
-use sweet_turnips::midi::{connect_to_midi, MidiReceiver};
+use sweet_turnips::midi::{
+    connect_to_midi_output, input_port_names, play_midi_file, profile_dirs, watch_midi, MidiClock,
+    MidiDeviceEvent, MidiLearn, MidiOutput, MidiRecorder, MidiSource,
+};
+use sweet_turnips::saves::Saves;
 use sweet_turnips::sprites::SpriteContext;
-use sweet_turnips::AppConfig;
+use sweet_turnips::{AppConfig, CliError, CliOptions};
 use sweet_turnips::{Context, GameResult};
 
 use std::convert::From;
-use std::sync::mpsc;
+use std::path::PathBuf;
 
 const GAME_NAME: &str = "tangy-turnips";
 const AUTHOR_NAME: &str = "JC Holder";
 
+const QUICKSAVE_SLOT: &str = "quicksave";
+
+// Bump this and register a migration whenever World changes shape, so that existing saves can
+// still be loaded
+const SAVE_VERSION: u32 = 1;
+
 struct MainState {
-    world: World,
+    game: Game,
+    world_config: WorldConfig,
     sprite_context: SpriteContext,
-    ticks: Ticks,
-    rx: Option<MidiReceiver>,
-    tick_speed: usize,
+    config_watcher: ConfigWatcher<GameConfig>,
+    user_config_path: PathBuf,
+    controls: ActionMap<Action>,
+    saves: Saves,
+    // Where feedback goes when it doesn't just follow the input
+    midi_output_port: Option<String>,
 }
 
 impl MainState {
     fn new(
         ctx: &mut Context,
+        app_config: &AppConfig,
         game_config: GameConfig,
-        rx: Option<MidiReceiver>,
+        config_watcher: ConfigWatcher<GameConfig>,
+        midi: Option<MidiDevice>,
+        midi_out: Option<Box<dyn MidiOutput>>,
     ) -> GameResult<MainState> {
-        let sprite_context = SpriteContext::new(ctx)?;
-
-        let ticks: Ticks = 0;
+        let sprite_context = SpriteContext::from_app_config(ctx, app_config)?;
+
+        let game = Game::new(
+            game_config.world.clone().into(),
+            game_config.midi.bindings,
+            MidiClock::new(game_config.midi.clock_pulses_per_tick),
+            app_config.tick_rate(),
+        )
+        .midi(midi)
+        .midi_profile_dirs(profile_dirs(app_config))
+        .midi_out(midi_out);
 
         let s = MainState {
-            world: game_config.world.into(),
+            game,
+            world_config: game_config.world,
             sprite_context,
-            ticks,
-            rx,
-            tick_speed: 20,
+            config_watcher,
+            user_config_path: app_config.user_config_path(),
+            controls: game_config.controls,
+            saves: Saves::new(app_config.save_dir(), Migrations::new(SAVE_VERSION)),
+            midi_output_port: game_config.midi.output_port,
         };
         Ok(s)
     }
 
-    fn move_player_ship(&mut self, direction: Direction) {
-        self.world = self.world.with_player_ship_move_requested(direction);
-    }
+    // Opens feedback for a newly connected device, and tells the player what MIDI has been up to
+    fn handle_midi_news(&mut self, news: MidiNews) {
+        match news {
+            MidiNews::Connected(port_name) => {
+                if self.game.midi_out.is_none() {
+                    self.game.midi_out =
+                        open_midi_feedback(self.midi_output_port.as_deref(), Some(&port_name));
+                }
+
+                self.sprite_context
+                    .show_notice(format!("MIDI connected: {}", port_name));
+            }
+            MidiNews::Disconnected(port_name) => {
+                // Feedback that follows the input went with it
+                if self.midi_output_port.is_none() {
+                    self.game.midi_out = None;
+                }
 
-    fn fire_bullets(&mut self) {
-        self.world = self.world.with_player_bullets_fired();
+                self.sprite_context
+                    .show_notice(format!("MIDI disconnected: {}", port_name));
+            }
+            MidiNews::NotFound(e) => {
+                println!("{}", e);
+                self.sprite_context.show_notice(e.to_string());
+            }
+            MidiNews::RecordingFinished => self
+                .sprite_context
+                .show_overlay("The MIDI recording has finished"),
+            MidiNews::Learned(changed) => self.save_learned_midi_bindings(&changed),
+            MidiNews::FeedbackStopped(e) => println!("Stopping MIDI feedback: {}", e),
+            MidiNews::Profile(news) => print_profile_news(&news),
+        }
     }
-}
 
-impl event::EventHandler for MainState {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        let mut tick_speed: usize = self.tick_speed;
-        let mut dir: Option<Direction> = None;
+    fn start_midi_learn(&mut self) {
+        self.game.start_midi_learn();
 
-        if let Some(receiver) = &self.rx {
-            // Mapped to a Korg nanoKONTROL2
-            // TODO: Add mapping options to config
+        if let Some(midi_learn) = &self.game.midi_learn {
+            show_midi_learn_prompt(&mut self.sprite_context, midi_learn);
+        }
+    }
 
-            while let Ok(key_value) = receiver.try_recv() {
-                let (key, value) = key_value;
+    // Saves learned bindings to the user config straight away. The config watcher then sees the
+    // change, but the world is left alone since only the bindings changed.
+    fn save_learned_midi_bindings(&mut self, changed: &[MidiAction]) {
+        let bindings = &self.game.midi_bindings;
 
-                if key == 43 && value == 127 {
-                    // rewind
-                    dir = Some(Direction::Left);
-                }
+        match config::save_midi_bindings(&self.user_config_path, bindings, changed) {
+            Ok(()) => self.sprite_context.clear_overlay(),
+            Err(e) => {
+                let message = format!("Could not save MIDI binding\n{}", e);
+                self.sprite_context.show_overlay(message);
+            }
+        }
+    }
 
-                if key == 44 && value == 127 {
-                    // fast forward
-                    dir = Some(Direction::Right);
+    fn handle_midi_learn_key(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::Tab => {
+                if let Some(midi_learn) = &mut self.game.midi_learn {
+                    midi_learn.next_action();
+                    show_midi_learn_prompt(&mut self.sprite_context, midi_learn);
                 }
+            }
+            KeyCode::Escape => {
+                self.game.midi_learn = None;
+                self.sprite_context.clear_overlay();
+            }
+            _ => (),
+        }
+    }
 
-                if key == 16 {
-                    if value > 0 {
-                        tick_speed = value as usize;
-                    } else {
-                        tick_speed = 1;
-                    }
+    // Reports back with a notice so any config error on the overlay stays put
+    fn quick_save(&mut self) {
+        match self.saves.save(QUICKSAVE_SLOT, &self.game.world) {
+            Ok(_) => self.sprite_context.show_notice("Saved"),
+            Err(e) => self
+                .sprite_context
+                .show_notice(format!("Could not save\n{}", e)),
+        }
+    }
+
+    fn quick_load(&mut self) {
+        match self.saves.load(QUICKSAVE_SLOT) {
+            Ok(world) => {
+                self.game.world = world;
+                self.sprite_context.show_notice("Loaded");
+            }
+            Err(e) => self
+                .sprite_context
+                .show_notice(format!("Could not load\n{}", e)),
+        }
+    }
+
+    // Rebuilds the world from scratch whenever its part of config.yaml changes, keeping the old
+    // one if the new config has problems
+    fn apply_config_changes(&mut self) {
+        match self.config_watcher.poll() {
+            Some(Ok(game_config)) => {
+                if game_config.world != self.world_config {
+                    self.game.world = game_config.world.clone().into();
+                    self.world_config = game_config.world;
                 }
+                self.controls = game_config.controls;
+                self.game.midi_bindings = game_config.midi.bindings;
+                self.game
+                    .midi_clock
+                    .set_pulses_per_tick(game_config.midi.clock_pulses_per_tick);
+                self.sprite_context.clear_overlay();
+            }
+            Some(Err(report)) => {
+                let message = format!("Could not reload config\n{}", report);
+
+                self.sprite_context.show_overlay(message);
             }
+            None => (),
         }
+    }
+}
+
+impl ActionHandler<Action> for MainState {
+    fn action_map(&self) -> &ActionMap<Action> {
+        &self.controls
+    }
 
-        self.tick_speed = tick_speed;
-        if let Some(d) = dir {
-            self.move_player_ship(d);
+    fn perform(&mut self, ctx: &mut Context, action: Action) {
+        match action {
+            Action::MoveUp => self.game.move_player_ship(Direction::Up),
+            Action::MoveLeft => self.game.move_player_ship(Direction::Left),
+            Action::MoveDown => self.game.move_player_ship(Direction::Down),
+            Action::MoveRight => self.game.move_player_ship(Direction::Right),
+            Action::Fire => self.game.fire_bullets(),
+            Action::CycleTheme => self.sprite_context.cycle_theme(),
+            Action::QuickSave => self.quick_save(),
+            Action::QuickLoad => self.quick_load(),
+            Action::LearnMidi => self.start_midi_learn(),
+            Action::Quit => event::quit(ctx),
         }
+    }
+}
 
-        self.ticks += 1;
+impl event::EventHandler for MainState {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        self.apply_config_changes();
 
-        if (self.ticks + 1) % self.tick_speed == 0 {
-            self.world = self.world.ticked();
-        } else {
-            self.world = self.world.with_events_processed();
+        for news in self.game.update() {
+            self.handle_midi_news(news);
         }
 
         Ok(())
@@ -112,19 +239,16 @@ impl event::EventHandler for MainState {
         _keymods: KeyMods,
         _repeat: bool,
     ) {
-        match keycode {
-            KeyCode::Escape => event::quit(ctx),
-            KeyCode::W => self.move_player_ship(Direction::Up),
-            KeyCode::A => self.move_player_ship(Direction::Left),
-            KeyCode::S => self.move_player_ship(Direction::Down),
-            KeyCode::D => self.move_player_ship(Direction::Right),
-            KeyCode::Space => self.fire_bullets(),
-            _ => (),
+        if self.game.midi_learn.is_some() {
+            self.handle_midi_learn_key(keycode);
+            return;
         }
+
+        self.handle_key_down(ctx, keycode);
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        let sprite_grid = render::sprite_grid_from_world(&self.world);
+        let sprite_grid = render::sprite_grid_from_world(&self.game.world)?;
 
         self.sprite_context.render_sprite_grid(sprite_grid, ctx)?;
 
@@ -149,22 +273,210 @@ impl From<WorldConfig> for World {
     }
 }
 
+fn show_midi_learn_prompt(sprite_context: &mut SpriteContext, midi_learn: &MidiLearn<MidiAction>) {
+    let message = format!(
+        "MIDI learn: touch a control for {}\nTab: next action  Escape: cancel",
+        action_name(&midi_learn.action())
+    );
+
+    sprite_context.show_overlay(message);
+}
+
+// Runs the world without a window and reports who's left. Each tick's events are processed
+// straight away, as update() would before the next one.
+fn run_headless(world: World, ticks: usize) {
+    let mut world = world;
+
+    for _ in 0..ticks {
+        world = world.ticked().with_events_processed();
+    }
+
+    println!(
+        "After {} ticks: {} player ships, {} enemy ships",
+        ticks,
+        world.player_ships.len(),
+        world.enemy_ships.len()
+    );
+}
+
+// Feedback goes to the configured output, or else to the output named the same as the input
+fn open_midi_feedback(
+    output_port: Option<&str>,
+    input_port: Option<&str>,
+) -> Option<Box<dyn MidiOutput>> {
+    let output_port = output_port.or(input_port)?;
+
+    match connect_to_midi_output(Some(output_port)) {
+        Ok(output) => {
+            println!("Sending MIDI feedback to {}", output.port_name());
+            Some(Box::new(output))
+        }
+        Err(e) => {
+            println!("Playing without MIDI feedback: {}", e);
+            None
+        }
+    }
+}
+
+// Only printed, as it's for whoever's setting up the controller rather than the player
+fn print_profile_news(news: &ProfileNews) {
+    match news {
+        ProfileNews::Found(profile_name) => println!("Using the {} MIDI profile", profile_name),
+        ProfileNews::Missing(port_name) => println!("There's no MIDI profile for {}", port_name),
+        ProfileNews::Skipped(e) => println!("Skipping a MIDI profile: {}", e),
+        ProfileNews::SharedControl(action, other_action, profile_name) => println!(
+            "{} and {} are bound to the same MIDI control on {}",
+            action_name(action),
+            action_name(other_action),
+            profile_name
+        ),
+        ProfileNews::UnknownNames(names) => println!(
+            "These MIDI controls won't work without a profile that names them: {}",
+            names.join(", ")
+        ),
+    }
+}
+
+fn list_midi_ports() {
+    match input_port_names() {
+        Ok(names) if names.is_empty() => println!("There are no MIDI inputs"),
+        Ok(names) => {
+            for name in names {
+                println!("{}", name);
+            }
+        }
+        Err(e) => println!("{}", e),
+    }
+}
+
 pub fn main() -> GameResult {
+    let cli_options = CliOptions::from_env()?;
+
+    if cli_options.help {
+        println!("{}", CliOptions::usage(GAME_NAME));
+        return Ok(());
+    }
+
+    if cli_options.list_midi_ports {
+        list_midi_ports();
+        return Ok(());
+    }
+
+    // Nothing in tangy's world is random, so a seed would be silently ignored
+    if cli_options.seed.is_some() {
+        return Err(CliError::UnsupportedOption("--seed".to_string()).into());
+    }
+
     let app_config = AppConfig::new((GRID_WIDTH, GRID_HEIGHT))
         .game_name(GAME_NAME)
-        .author_name(AUTHOR_NAME);
+        .author_name(AUTHOR_NAME)
+        .tick_rate(20)
+        .cli_options(&cli_options)
+        .finalize()?;
 
     let config_path = sweet_turnips::prep_config_path(&app_config)?;
 
-    let game_config = config::setup_game_config(config_path);
+    let user_config_path = app_config.user_config_path();
+
+    let game_config = config::setup_game_config(config_path.clone(), &user_config_path);
+
+    if let Some(ticks) = cli_options.headless_ticks {
+        run_headless(game_config.world.into(), ticks);
+        return Ok(());
+    }
+
+    let config_watcher = config::watch_game_config(config_path, &user_config_path);
 
     let (ctx, event_loop) = &mut sweet_turnips::build_context_and_event_loop(&app_config)?;
 
-    let (tx, rx) = mpsc::channel();
+    let midi_port = cli_options
+        .midi_port
+        .or_else(|| game_config.midi.port.clone());
 
-    connect_to_midi(tx);
+    let source = match &cli_options.play_midi {
+        Some(path) => {
+            play_midi_file(path).map(|playback| Box::new(playback) as Box<dyn MidiSource>)
+        }
+        None => {
+            watch_midi(midi_port.as_deref()).map(|watcher| Box::new(watcher) as Box<dyn MidiSource>)
+        }
+    };
+
+    let midi = match source {
+        Ok(mut source) => {
+            // Whatever's plugged in at launch is set up here rather than announced, though a port
+            // that can't be found is still worth knowing about
+            for event in source.poll_events() {
+                if let MidiDeviceEvent::NotFound(e) = event {
+                    println!("{}", e);
+                }
+            }
+
+            match (&cli_options.play_midi, source.port_name()) {
+                (Some(path), _) => println!("Playing MIDI from {}", path.display()),
+                (None, Some(port_name)) => println!("Listening to MIDI from {}", port_name),
+                (None, None) => println!("Waiting for a MIDI device to be plugged in"),
+            }
+
+            // A recording is played with the profile for the port it's configured to stand in for
+            let profile = source
+                .port_name()
+                .or(midi_port.as_deref())
+                .and_then(|port_name| {
+                    let (profile, news) = find_midi_profile(
+                        &profile_dirs(&app_config),
+                        port_name,
+                        &game_config.midi.bindings,
+                    );
+
+                    for news in news.iter() {
+                        print_profile_news(news);
+                    }
+
+                    profile
+                });
 
-    let state = &mut MainState::new(ctx, game_config, Some(rx))?;
+            let recorder = cli_options
+                .record_midi
+                .as_ref()
+                .map(|_| MidiRecorder::new());
+
+            Some(MidiDevice::new(source, profile).recorder(recorder))
+        }
+        Err(e) => {
+            println!("Playing without MIDI: {}", e);
+            None
+        }
+    };
+
+    let midi_out = open_midi_feedback(
+        game_config.midi.output_port.as_deref(),
+        midi.as_ref().and_then(|midi| midi.source.port_name()),
+    );
+
+    let state = &mut MainState::new(
+        ctx,
+        &app_config,
+        game_config,
+        config_watcher,
+        midi,
+        midi_out,
+    )?;
+
+    let result = event::run(ctx, event_loop, state);
+
+    let midi_recorder = state
+        .game
+        .midi
+        .as_ref()
+        .and_then(|midi| midi.recorder.as_ref());
+
+    if let (Some(path), Some(midi_recorder)) = (&cli_options.record_midi, midi_recorder) {
+        match midi_recorder.save(path) {
+            Ok(()) => println!("Recorded the MIDI played to {}", path.display()),
+            Err(e) => println!("{}", e),
+        }
+    }
 
-    event::run(ctx, event_loop, state)
+    result
 }
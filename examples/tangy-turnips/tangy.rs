@@ -6,13 +6,17 @@ use rand::{
     Rng,
 };
 
+use serde::{Deserialize, Serialize};
+
 use slotmap::{new_key_type, SecondaryMap, SlotMap};
 
+use sweet_turnips::sprites::GridUnit;
+
 use entities::{EnemyShip, GameEntity, PlayerBullet, PlayerShip};
 use events::{WorldEvent, WE};
 
-pub const GRID_WIDTH: u8 = 8;
-pub const GRID_HEIGHT: u8 = 8;
+pub const GRID_WIDTH: GridUnit = 8;
+pub const GRID_HEIGHT: GridUnit = 8;
 
 new_key_type! {
     pub struct EntityKey;
@@ -26,7 +30,7 @@ new_key_type! {
 
 pub type Ticks = usize;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -45,9 +49,9 @@ impl Distribution<Direction> for Standard {
     }
 }
 
-pub type Coords = (u8, u8);
+pub type Coords = (GridUnit, GridUnit);
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct World {
     events: Vec<WorldEvent>,
     entities: SlotMap<EntityKey, GameEntity>,
@@ -164,7 +168,7 @@ impl World {
     pub fn with_player_ship_added_at(self, coords: Coords) -> Self {
         let mut world = self.clone();
 
-        let key = world.entities.insert(GameEntity);
+        let key = world.entities.insert(GameEntity {});
 
         let ship = PlayerShip { key };
 
@@ -177,7 +181,7 @@ impl World {
     pub fn with_enemy_ship_added_at(self, coords: Coords) -> Self {
         let mut world = self.clone();
 
-        let key = world.entities.insert(GameEntity);
+        let key = world.entities.insert(GameEntity {});
 
         let ship = EnemyShip { key };
 
@@ -385,7 +389,7 @@ impl World {
 
         let y = y - 1;
 
-        let key = world.entities.insert(GameEntity);
+        let key = world.entities.insert(GameEntity {});
 
         let bullet = PlayerBullet { key };
 
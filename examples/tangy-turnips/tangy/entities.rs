@@ -1,19 +1,22 @@
+use serde::{Deserialize, Serialize};
+
 use crate::tangy::EntityKey;
 
-#[derive(Copy, Clone, Debug)]
-pub struct GameEntity;
+// Braced so that it saves as {} rather than null, which would read back as an empty slot
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct GameEntity {}
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct PlayerShip {
     pub key: EntityKey,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct PlayerBullet {
     pub key: EntityKey,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct EnemyShip {
     pub key: EntityKey,
 }
@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::tangy::{Coords, Direction, EnemyShipKey, PlayerBulletKey};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum WorldEvent {
     PlayerShipMoved(Direction),
     PlayerShipDied(Coords),
@@ -0,0 +1,302 @@
+use ggez::event::KeyCode;
+use ggez::Context;
+use serde::de;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_yaml;
+use serde_yaml::Value;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::config::{ConfigPath, Validate, ValidationReport};
+
+/// Which keys trigger which of a game's actions
+///
+/// Games declare their actions as an enum, then keep an `ActionMap` in their game config so that
+/// players can rebind keys in their config files. Each action can have several keys, but each key
+/// can only trigger one action.
+///
+/// ## Examples
+///
+/// ```ignore
+/// #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+/// #[serde(rename_all = "snake_case")]
+/// pub enum Action {
+///     MoveUp,
+///     Fire,
+/// }
+///
+/// let controls = ActionMap::new()
+///     .bind(Action::MoveUp, KeyCode::W)
+///     .bind(Action::MoveUp, KeyCode::Up)
+///     .bind(Action::Fire, KeyCode::Space);
+/// ```
+///
+/// In a YAML config this reads as:
+///
+/// ```yaml
+/// controls:
+///   move_up: [W, Up]
+///   fire: [Space]
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ActionMap<A: Ord> {
+    bindings: BTreeMap<A, Vec<Key>>,
+}
+
+impl<A: Ord + Copy> ActionMap<A> {
+    pub fn new() -> Self {
+        Self {
+            bindings: BTreeMap::new(),
+        }
+    }
+
+    /// Adds `key` to the keys that trigger `action`
+    pub fn bind(self, action: A, key: KeyCode) -> Self {
+        let mut bindings = self.bindings.clone();
+        bindings.entry(action).or_default().push(Key(key));
+
+        Self { bindings }
+    }
+
+    /// The action a key press should trigger, if any
+    pub fn action_for(&self, key: KeyCode) -> Option<A> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.contains(&Key(key)))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn keys_for(&self, action: A) -> Vec<KeyCode> {
+        match self.bindings.get(&action) {
+            Some(keys) => keys.iter().map(|key| key.0).collect(),
+            None => vec![],
+        }
+    }
+}
+
+/// A game that handles its actions rather than raw key presses
+///
+/// Implement it for the game's state, then hand key presses to `handle_key_down` from
+/// `EventHandler::key_down_event`.
+///
+/// ## Examples
+///
+/// ```ignore
+/// impl ActionHandler<Action> for MainState {
+///     fn action_map(&self) -> &ActionMap<Action> {
+///         &self.controls
+///     }
+///
+///     fn perform(&mut self, ctx: &mut Context, action: Action) {
+///         match action {
+///             Action::Fire => self.fire_bullets(),
+///             Action::Quit => event::quit(ctx),
+///         }
+///     }
+/// }
+///
+/// fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, _: KeyMods, _: bool) {
+///     self.handle_key_down(ctx, keycode);
+/// }
+/// ```
+pub trait ActionHandler<A: Ord + Copy> {
+    fn action_map(&self) -> &ActionMap<A>;
+
+    fn perform(&mut self, ctx: &mut Context, action: A);
+
+    /// Performs whichever action `key` is bound to, returning whether it was bound to one
+    fn handle_key_down(&mut self, ctx: &mut Context, key: KeyCode) -> bool {
+        match self.action_map().action_for(key) {
+            Some(action) => {
+                self.perform(ctx, action);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<A: Ord + Copy> Default for ActionMap<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A: Ord + Serialize> Validate for ActionMap<A> {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        let mut bound: Vec<(Key, String)> = vec![];
+
+        for (action, keys) in &self.bindings {
+            let action_name = action_name(action);
+            let action_path = path.field(&action_name);
+
+            for (i, key) in keys.iter().enumerate() {
+                let other_action = bound
+                    .iter()
+                    .find(|(other_key, _)| other_key == key)
+                    .map(|(_, other_action)| other_action);
+
+                match other_action {
+                    Some(other_action) if *other_action != action_name => {
+                        let message = format!("{} is already bound to {}", key, other_action);
+                        report.error(action_path.index(i), message);
+                    }
+                    Some(_) => (),
+                    None => bound.push((*key, action_name.clone())),
+                }
+            }
+        }
+    }
+}
+
+// Action names are read as plain strings first, so that errors about the keys beneath them
+// can still say which action they're under
+impl<'de, A: Ord + Deserialize<'de>> Deserialize<'de> for ActionMap<A> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let named_bindings = BTreeMap::<String, Vec<Key>>::deserialize(deserializer)?;
+        let mut bindings = BTreeMap::new();
+
+        for (name, keys) in named_bindings {
+            let name_deserializer: de::value::StrDeserializer<de::value::Error> =
+                name.as_str().into_deserializer();
+            let action = A::deserialize(name_deserializer).map_err(de::Error::custom)?;
+
+            bindings.insert(action, keys);
+        }
+
+        Ok(Self { bindings })
+    }
+}
+
+/// The name an action goes by in config files, e.g. `move_up` for `Action::MoveUp`
+pub fn action_name<A: Serialize>(action: &A) -> String {
+    match serde_yaml::to_value(action) {
+        Ok(Value::String(name)) => name,
+        _ => "(unnamed action)".to_string(),
+    }
+}
+
+/// A key as it's named in config files, e.g. `W`, `Space`, `Up`, `Key1` or `F1`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Key(pub KeyCode);
+
+impl Key {
+    pub fn from_name(name: &str) -> Option<Self> {
+        KEY_NAMES
+            .iter()
+            .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+            .map(|(_, key)| Key(*key))
+    }
+
+    pub fn name(self) -> Option<&'static str> {
+        KEY_NAMES
+            .iter()
+            .find(|(_, key)| *key == self.0)
+            .map(|(name, _)| *name)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{:?}", self.0),
+        }
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+
+        Key::from_name(&name).ok_or_else(|| {
+            de::Error::custom(format!(
+                "unknown key `{}`, expected a name like W, Space, Up, Key1 or F1",
+                name
+            ))
+        })
+    }
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        const KEY_NAMES: &[(&str, KeyCode)] = &[$((stringify!($key), KeyCode::$key)),*];
+    };
+}
+
+#[rustfmt::skip]
+key_names![
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Escape, Space, Return, Back, Tab, Insert, Delete, Home, End, PageUp, PageDown,
+    Left, Up, Right, Down,
+    LShift, RShift, LControl, RControl, LAlt, RAlt,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Add, Subtract, Multiply, Divide, Decimal, NumpadEnter,
+    Minus, Equals, LBracket, RBracket, Backslash, Semicolon, Apostrophe, Comma, Period, Slash,
+    Grave,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Action {
+        MoveUp,
+        Fire,
+    }
+
+    fn problems(controls: &ActionMap<Action>) -> Vec<String> {
+        let mut report = ValidationReport::default();
+        controls.validate(&ConfigPath::root(), &mut report);
+
+        report.errors().iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn keys_can_share_an_action() {
+        let controls = ActionMap::new()
+            .bind(Action::MoveUp, KeyCode::W)
+            .bind(Action::MoveUp, KeyCode::Up)
+            .bind(Action::Fire, KeyCode::Space);
+
+        assert_eq!(problems(&controls), Vec::<String>::new());
+        assert_eq!(controls.action_for(KeyCode::Up), Some(Action::MoveUp));
+        assert_eq!(controls.action_for(KeyCode::A), None);
+    }
+
+    #[test]
+    fn keys_bound_to_two_actions_are_reported() {
+        let controls = ActionMap::new()
+            .bind(Action::MoveUp, KeyCode::W)
+            .bind(Action::Fire, KeyCode::Space)
+            .bind(Action::Fire, KeyCode::W);
+
+        let problems = problems(&controls);
+
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("fire[1]"), "{}", problems[0]);
+        assert!(problems[0].contains("W is already bound to move_up"));
+    }
+}
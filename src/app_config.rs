@@ -0,0 +1,513 @@
+use ggez::GameError;
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::app_dirs::{AppDirs, StorageMode};
+use crate::cli::CliOptions;
+use crate::config::ConfigFormat;
+use crate::sprites::{GridUnit, SpriteTheme, SPRITE_SCALE};
+use crate::{resource_dir, user_config_path};
+
+pub const DEFAULT_TICK_RATE: usize = 40;
+
+/// App settings that haven't been vetted yet
+///
+/// Start with `AppConfig::new`, chain the settings you need, then call `finalize()` to catch
+/// invalid combinations before any window opens.
+///
+/// ## Examples
+///
+/// ```ignore
+/// let app_config = AppConfig::new((GRID_WIDTH, GRID_HEIGHT))
+///     .game_name(GAME_NAME)
+///     .window_scale(2.0)
+///     .tick_rate(20)
+///     .finalize()?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct UnfinalizedAppConfig {
+    game_name: String,
+    author_name: String,
+    grid_dimensions: (GridUnit, GridUnit),
+    window_scale: f32,
+    vsync: bool,
+    resource_paths: Vec<PathBuf>,
+    sprite_theme: SpriteTheme,
+    tick_rate: usize,
+    config_format: ConfigFormat,
+    config_path: Option<PathBuf>,
+    storage_mode: StorageMode,
+}
+
+/// App settings that have passed `UnfinalizedAppConfig::finalize()`
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    game_name: String,
+    author_name: String,
+    grid_dimensions: (GridUnit, GridUnit),
+    window_scale: f32,
+    vsync: bool,
+    resource_paths: Vec<PathBuf>,
+    sprite_theme: SpriteTheme,
+    tick_rate: usize,
+    config_format: ConfigFormat,
+    config_path: Option<PathBuf>,
+    storage_mode: StorageMode,
+    dirs: AppDirs,
+}
+
+impl AppConfig {
+    pub fn new(grid_dimensions: (GridUnit, GridUnit)) -> UnfinalizedAppConfig {
+        UnfinalizedAppConfig {
+            game_name: "A Sweet Turnips Game".to_string(),
+            author_name: "Your Name Goes Here".to_string(),
+            grid_dimensions,
+            window_scale: SPRITE_SCALE,
+            vsync: true,
+            resource_paths: vec![resource_dir()],
+            sprite_theme: SpriteTheme::bitter_jam(),
+            tick_rate: DEFAULT_TICK_RATE,
+            config_format: ConfigFormat::default(),
+            config_path: None,
+            storage_mode: StorageMode::default(),
+        }
+    }
+
+    pub fn game_name(&self) -> &str {
+        &self.game_name
+    }
+
+    pub fn author_name(&self) -> &str {
+        &self.author_name
+    }
+
+    pub fn grid_dimensions(&self) -> (GridUnit, GridUnit) {
+        self.grid_dimensions
+    }
+
+    pub fn window_scale(&self) -> f32 {
+        self.window_scale
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.vsync
+    }
+
+    pub fn resource_paths(&self) -> &[PathBuf] {
+        &self.resource_paths
+    }
+
+    pub fn sprite_theme(&self) -> &SpriteTheme {
+        &self.sprite_theme
+    }
+
+    pub fn tick_rate(&self) -> usize {
+        self.tick_rate
+    }
+
+    pub fn config_format(&self) -> ConfigFormat {
+        self.config_format
+    }
+
+    /// The game config picked in place of the usual one, e.g. with `--config`
+    pub fn config_path(&self) -> Option<&Path> {
+        self.config_path.as_deref()
+    }
+
+    pub fn storage_mode(&self) -> StorageMode {
+        self.storage_mode
+    }
+
+    pub fn dirs(&self) -> &AppDirs {
+        &self.dirs
+    }
+
+    /// Where the player's overrides for the game config live
+    pub fn config_dir(&self) -> &Path {
+        &self.dirs.config_dir
+    }
+
+    /// The player's overrides for the game config, e.g. `config.user.yaml` in the config
+    /// directory, or beside a config picked with `config_path`
+    pub fn user_config_path(&self) -> PathBuf {
+        match &self.config_path {
+            Some(config_path) => user_config_path(config_path),
+            None => {
+                let config_name = format!("config.{}", self.config_format.extension());
+                user_config_path(&self.dirs.config_dir.join(config_name))
+            }
+        }
+    }
+
+    pub fn save_dir(&self) -> &Path {
+        &self.dirs.save_dir
+    }
+
+    pub fn screenshot_dir(&self) -> &Path {
+        &self.dirs.screenshot_dir
+    }
+}
+
+impl UnfinalizedAppConfig {
+    pub fn game_name<S>(self, game_name: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        Self {
+            game_name: game_name.as_ref().to_string(),
+            ..self
+        }
+    }
+
+    pub fn author_name<S>(self, author_name: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        Self {
+            author_name: author_name.as_ref().to_string(),
+            ..self
+        }
+    }
+
+    pub fn grid_dimensions(self, grid_dimensions: (GridUnit, GridUnit)) -> Self {
+        Self {
+            grid_dimensions,
+            ..self
+        }
+    }
+
+    /// How many screen pixels each pixel of an 8x8 sprite takes up
+    pub fn window_scale(self, window_scale: f32) -> Self {
+        Self {
+            window_scale,
+            ..self
+        }
+    }
+
+    pub fn vsync(self, vsync: bool) -> Self {
+        Self { vsync, ..self }
+    }
+
+    /// Adds another directory to load resources from, behind the ones added before it
+    pub fn resource_path<P>(self, resource_path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let mut resource_paths = self.resource_paths.clone();
+        resource_paths.push(resource_path.as_ref().to_path_buf());
+
+        Self {
+            resource_paths,
+            ..self
+        }
+    }
+
+    /// The sprite theme to start out with
+    pub fn sprite_theme(self, sprite_theme: SpriteTheme) -> Self {
+        Self {
+            sprite_theme,
+            ..self
+        }
+    }
+
+    /// How many frames pass between each world tick
+    pub fn tick_rate(self, tick_rate: usize) -> Self {
+        Self { tick_rate, ..self }
+    }
+
+    /// The format a new game config file gets written in
+    pub fn config_format(self, config_format: ConfigFormat) -> Self {
+        Self {
+            config_format,
+            ..self
+        }
+    }
+
+    /// Loads the game config from `config_path` instead of the one shipped in
+    /// `resources/<game_name>/`
+    pub fn config_path<P>(self, config_path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            config_path: Some(config_path.as_ref().to_path_buf()),
+            ..self
+        }
+    }
+
+    /// Whether per-user files go in the platform's usual directories or under `resources/`
+    pub fn storage_mode(self, storage_mode: StorageMode) -> Self {
+        Self {
+            storage_mode,
+            ..self
+        }
+    }
+
+    /// Applies whichever of `--config`, `--tick-rate`, `--scale` and `--portable` were given,
+    /// over the settings chosen so far
+    pub fn cli_options(self, cli_options: &CliOptions) -> Self {
+        let mut app_config = self;
+
+        if let Some(config_path) = &cli_options.config_path {
+            app_config = app_config.config_path(config_path);
+        }
+
+        if let Some(tick_rate) = cli_options.tick_rate {
+            app_config = app_config.tick_rate(tick_rate);
+        }
+
+        if let Some(window_scale) = cli_options.window_scale {
+            app_config = app_config.window_scale(window_scale);
+        }
+
+        if cli_options.portable {
+            app_config = app_config.storage_mode(StorageMode::Portable);
+        }
+
+        app_config
+    }
+
+    pub fn finalize(self) -> Result<AppConfig, AppConfigError> {
+        let (width, height) = self.grid_dimensions;
+
+        if width == 0 || height == 0 {
+            return Err(AppConfigError::ZeroSizedGrid(self.grid_dimensions));
+        }
+
+        if !(self.window_scale > 0.0 && self.window_scale.is_finite()) {
+            return Err(AppConfigError::InvalidWindowScale(self.window_scale));
+        }
+
+        if self.tick_rate == 0 {
+            return Err(AppConfigError::ZeroTickRate);
+        }
+
+        // The default resource directory is optional, since its contents may be embedded
+        for resource_path in self.resource_paths.iter().skip(1) {
+            if !resource_path.is_dir() {
+                return Err(AppConfigError::MissingResourcePath(resource_path.clone()));
+            }
+        }
+
+        self.check_sprite_theme()?;
+
+        let dirs = match self.storage_mode {
+            StorageMode::Platform => {
+                AppDirs::platform(&self.game_name).ok_or(AppConfigError::NoUserDirectory)?
+            }
+            StorageMode::Portable => AppDirs::portable(&self.game_name),
+        };
+
+        Ok(AppConfig {
+            game_name: self.game_name,
+            author_name: self.author_name,
+            grid_dimensions: self.grid_dimensions,
+            window_scale: self.window_scale,
+            vsync: self.vsync,
+            resource_paths: self.resource_paths,
+            sprite_theme: self.sprite_theme,
+            tick_rate: self.tick_rate,
+            config_format: self.config_format,
+            config_path: self.config_path,
+            storage_mode: self.storage_mode,
+            dirs,
+        })
+    }
+
+    fn check_sprite_theme(&self) -> Result<(), AppConfigError> {
+        let theme = &self.sprite_theme;
+        let sprite_size = theme.sprite_size;
+
+        if !(sprite_size >= 1.0 && sprite_size.fract() == 0.0) {
+            return Err(AppConfigError::InvalidSpriteSize(sprite_size));
+        }
+
+        let atlas = match &theme.atlas {
+            Some(atlas) => atlas,
+            None => return Ok(()),
+        };
+
+        // Atlases packed into zips or embedded in the binary can't be read before the context
+        // exists, so those are checked when the sprites are loaded instead
+        let atlas_path = self
+            .resource_paths
+            .iter()
+            .map(|dir| dir.join(atlas.trim_start_matches('/')))
+            .find(|path| path.is_file());
+
+        let atlas_path = match atlas_path {
+            Some(atlas_path) => atlas_path,
+            None => return Ok(()),
+        };
+
+        let atlas_dimensions = png_dimensions(&atlas_path)
+            .map_err(|e| AppConfigError::UnreadableAtlas(atlas_path.clone(), e.to_string()))?;
+
+        let size = sprite_size as u32;
+
+        if atlas_dimensions.0 % size != 0 || atlas_dimensions.1 % size != 0 {
+            return Err(AppConfigError::SpriteSizeMismatch {
+                atlas: atlas_path.display().to_string(),
+                atlas_dimensions,
+                sprite_size,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Why `UnfinalizedAppConfig::finalize()` rejected a combination of settings
+#[derive(Clone, Debug, PartialEq)]
+pub enum AppConfigError {
+    ZeroSizedGrid((GridUnit, GridUnit)),
+    InvalidWindowScale(f32),
+    ZeroTickRate,
+    MissingResourcePath(PathBuf),
+    InvalidSpriteSize(f32),
+    UnreadableAtlas(PathBuf, String),
+    SpriteSizeMismatch {
+        atlas: String,
+        atlas_dimensions: (u32, u32),
+        sprite_size: f32,
+    },
+    NoUserDirectory,
+}
+
+impl fmt::Display for AppConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppConfigError::ZeroSizedGrid((width, height)) => {
+                write!(f, "The grid can't be {}x{}", width, height)
+            }
+            AppConfigError::InvalidWindowScale(scale) => {
+                write!(f, "The window scale must be above zero, not {}", scale)
+            }
+            AppConfigError::ZeroTickRate => write!(f, "The tick rate must be at least one frame"),
+            AppConfigError::MissingResourcePath(path) => {
+                write!(f, "Resource path {} is not a directory", path.display())
+            }
+            AppConfigError::InvalidSpriteSize(size) => {
+                write!(
+                    f,
+                    "Sprite size must be a whole number of pixels, not {}",
+                    size
+                )
+            }
+            AppConfigError::UnreadableAtlas(path, reason) => {
+                write!(
+                    f,
+                    "Could not read sprite atlas {}: {}",
+                    path.display(),
+                    reason
+                )
+            }
+            AppConfigError::SpriteSizeMismatch {
+                atlas,
+                atlas_dimensions,
+                sprite_size,
+            } => write!(
+                f,
+                "Sprite size {} doesn't evenly divide the {}x{} atlas {}",
+                sprite_size, atlas_dimensions.0, atlas_dimensions.1, atlas
+            ),
+            AppConfigError::NoUserDirectory => write!(
+                f,
+                "Could not find a home directory for config and saves; try portable mode"
+            ),
+        }
+    }
+}
+
+impl Error for AppConfigError {}
+
+impl From<AppConfigError> for GameError {
+    fn from(e: AppConfigError) -> Self {
+        GameError::ConfigError(e.to_string())
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// Just enough of the PNG header to get at the image's dimensions without decoding it
+fn png_dimensions(path: &Path) -> io::Result<(u32, u32)> {
+    let mut header = [0u8; 24];
+    File::open(path)?.read_exact(&mut header)?;
+
+    if &header[0..8] != PNG_SIGNATURE || &header[12..16] != b"IHDR" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a PNG"));
+    }
+
+    let width = u32::from_be_bytes([header[16], header[17], header[18], header[19]]);
+    let height = u32::from_be_bytes([header[20], header[21], header[22], header[23]]);
+
+    Ok((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::test_support::scratch_dir;
+
+    fn app_config() -> UnfinalizedAppConfig {
+        AppConfig::new((8, 8)).storage_mode(StorageMode::Portable)
+    }
+
+    // Just the header `png_dimensions` reads, which is all `finalize()` looks at
+    fn write_png_header(path: &Path, width: u32, height: u32) {
+        let mut header = PNG_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0, 0, 0, 13]);
+        header.extend_from_slice(b"IHDR");
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+
+        fs::write(path, header).unwrap();
+    }
+
+    #[test]
+    fn atlases_on_disk_must_fit_the_sprite_size() {
+        let dir = scratch_dir("atlas-check");
+        write_png_header(&dir.join("fits.png"), 32, 16);
+        write_png_header(&dir.join("too-wide.png"), 36, 16);
+
+        let theme = |atlas| SpriteTheme::from_atlas("sheet", atlas, 8.0);
+
+        let result = app_config()
+            .resource_path(&dir)
+            .sprite_theme(theme("/fits.png"))
+            .finalize();
+        assert!(result.is_ok());
+
+        let result = app_config()
+            .resource_path(&dir)
+            .sprite_theme(theme("/too-wide.png"))
+            .finalize();
+        assert_eq!(
+            result.map(|_| ()),
+            Err(AppConfigError::SpriteSizeMismatch {
+                atlas: dir.join("too-wide.png").display().to_string(),
+                atlas_dimensions: (36, 16),
+                sprite_size: 8.0,
+            })
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn atlases_that_arent_on_disk_are_left_for_loading() {
+        let result = app_config()
+            .sprite_theme(SpriteTheme::from_atlas("packed", "/in-a-zip.png", 8.0))
+            .finalize();
+
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,258 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::resource_dir;
+
+/// Where a game keeps its per-user files
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StorageMode {
+    /// The platform's usual per-user directories, e.g. the XDG base directories on Linux
+    #[default]
+    Platform,
+    /// Everything under `resources/<game_name>/`, for running from a checkout or a USB stick
+    Portable,
+}
+
+/// The directories a game reads and writes its per-user files in
+///
+/// On Linux these follow the XDG base directories:
+///
+/// * config: `$XDG_CONFIG_HOME/<game_name>/` (`~/.config/<game_name>/`)
+/// * saves: `$XDG_DATA_HOME/<game_name>/saves/` (`~/.local/share/<game_name>/saves/`)
+/// * screenshots: `$XDG_DATA_HOME/<game_name>/screenshots/`
+///
+/// In portable mode they all live under `resources/<game_name>/` instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppDirs {
+    pub config_dir: PathBuf,
+    pub save_dir: PathBuf,
+    pub screenshot_dir: PathBuf,
+}
+
+impl AppDirs {
+    /// The platform's directories for `game_name`, if the user's home can be found
+    pub fn platform(game_name: &str) -> Option<Self> {
+        Self::for_platform(Platform::CURRENT, game_name, &|var| env::var_os(var))
+    }
+
+    /// Directories alongside the game's resources, as in `resources/<game_name>/`
+    pub fn portable(game_name: &str) -> Self {
+        Self::within(&resource_dir().join(game_name))
+    }
+
+    /// Config directly in `dir`, with saves and screenshots in subdirectories of it
+    pub fn within(dir: &Path) -> Self {
+        Self {
+            config_dir: dir.to_path_buf(),
+            save_dir: dir.join("saves"),
+            screenshot_dir: dir.join("screenshots"),
+        }
+    }
+
+    fn for_platform(platform: Platform, game_name: &str, env_var: EnvVar) -> Option<Self> {
+        let (config_home, data_home) = platform.homes(env_var)?;
+
+        let data_dir = data_home.join(game_name);
+
+        Some(Self {
+            config_dir: config_home.join(game_name),
+            save_dir: data_dir.join("saves"),
+            screenshot_dir: data_dir.join("screenshots"),
+        })
+    }
+}
+
+// Looks up an environment variable. The real environment outside of tests.
+type EnvVar<'a> = &'a dyn Fn(&str) -> Option<OsString>;
+
+// Each kind of platform keeps per-user files somewhere different. They're all here, whichever
+// one this is built for, so that each can be tested anywhere.
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Platform {
+    Xdg,
+    MacOs,
+    Windows,
+    Unknown,
+}
+
+impl Platform {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    const CURRENT: Platform = Platform::Xdg;
+
+    #[cfg(target_os = "macos")]
+    const CURRENT: Platform = Platform::MacOs;
+
+    #[cfg(windows)]
+    const CURRENT: Platform = Platform::Windows;
+
+    #[cfg(not(any(unix, windows)))]
+    const CURRENT: Platform = Platform::Unknown;
+
+    // The base directories for config and for data, in that order
+    fn homes(self, env_var: EnvVar) -> Option<(PathBuf, PathBuf)> {
+        match self {
+            Platform::Xdg => {
+                let home = home_dir(env_var);
+
+                let config_home = xdg_dir(env_var, "XDG_CONFIG_HOME")
+                    .or_else(|| Some(home.clone()?.join(".config")))?;
+                let data_home = xdg_dir(env_var, "XDG_DATA_HOME")
+                    .or_else(|| Some(home?.join(".local/share")))?;
+
+                Some((config_home, data_home))
+            }
+            Platform::MacOs => {
+                let support = home_dir(env_var)?.join("Library/Application Support");
+
+                Some((support.clone(), support))
+            }
+            Platform::Windows => {
+                let app_data = env_var("APPDATA").map(PathBuf::from)?;
+
+                Some((app_data.clone(), app_data))
+            }
+            Platform::Unknown => None,
+        }
+    }
+}
+
+fn home_dir(env_var: EnvVar) -> Option<PathBuf> {
+    env_var("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+// The spec says relative paths in these variables are invalid and should be ignored
+fn xdg_dir(env_var: EnvVar, var: &str) -> Option<PathBuf> {
+    env_var(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    // The directories `platform` picks with only `vars` set
+    fn dirs_with(platform: Platform, vars: &[(&str, &str)]) -> Option<AppDirs> {
+        let vars: HashMap<String, OsString> = vars
+            .iter()
+            .map(|(var, value)| (var.to_string(), OsString::from(value)))
+            .collect();
+
+        AppDirs::for_platform(platform, "turnips", &|var| vars.get(var).cloned())
+    }
+
+    fn app_dirs(config_dir: &str, data_dir: &str) -> AppDirs {
+        let data_dir = PathBuf::from(data_dir);
+
+        AppDirs {
+            config_dir: PathBuf::from(config_dir),
+            save_dir: data_dir.join("saves"),
+            screenshot_dir: data_dir.join("screenshots"),
+        }
+    }
+
+    #[test]
+    fn xdg_directories_fall_back_on_home() {
+        assert_eq!(
+            dirs_with(Platform::Xdg, &[("HOME", "/home/ann")]),
+            Some(app_dirs(
+                "/home/ann/.config/turnips",
+                "/home/ann/.local/share/turnips"
+            ))
+        );
+
+        assert_eq!(
+            dirs_with(
+                Platform::Xdg,
+                &[
+                    ("HOME", "/home/ann"),
+                    ("XDG_CONFIG_HOME", "/etc/ann"),
+                    ("XDG_DATA_HOME", "/var/ann"),
+                ]
+            ),
+            Some(app_dirs("/etc/ann/turnips", "/var/ann/turnips"))
+        );
+
+        // Without a home, both have to be set
+        assert_eq!(
+            dirs_with(
+                Platform::Xdg,
+                &[
+                    ("XDG_CONFIG_HOME", "/etc/ann"),
+                    ("XDG_DATA_HOME", "/var/ann")
+                ]
+            ),
+            Some(app_dirs("/etc/ann/turnips", "/var/ann/turnips"))
+        );
+        assert_eq!(
+            dirs_with(Platform::Xdg, &[("XDG_CONFIG_HOME", "/etc/ann")]),
+            None
+        );
+    }
+
+    #[test]
+    fn relative_xdg_directories_are_ignored() {
+        assert_eq!(
+            dirs_with(
+                Platform::Xdg,
+                &[
+                    ("HOME", "/home/ann"),
+                    ("XDG_CONFIG_HOME", "config"),
+                    ("XDG_DATA_HOME", ""),
+                ]
+            ),
+            Some(app_dirs(
+                "/home/ann/.config/turnips",
+                "/home/ann/.local/share/turnips"
+            ))
+        );
+    }
+
+    #[test]
+    fn macos_keeps_everything_in_application_support() {
+        let support = "/Users/ann/Library/Application Support/turnips";
+
+        assert_eq!(
+            dirs_with(Platform::MacOs, &[("HOME", "/Users/ann")]),
+            Some(app_dirs(support, support))
+        );
+        assert_eq!(dirs_with(Platform::MacOs, &[("HOME", "")]), None);
+    }
+
+    #[test]
+    fn windows_keeps_everything_in_app_data() {
+        let app_data = r"C:\Users\ann\AppData\Roaming";
+
+        assert_eq!(
+            dirs_with(Platform::Windows, &[("APPDATA", app_data)]),
+            Some(AppDirs::within(&PathBuf::from(app_data).join("turnips")))
+        );
+        // A home isn't enough without APPDATA
+        assert_eq!(dirs_with(Platform::Windows, &[("HOME", "/home/ann")]), None);
+    }
+
+    #[test]
+    fn unknown_platforms_have_no_directories() {
+        assert_eq!(dirs_with(Platform::Unknown, &[("HOME", "/home/ann")]), None);
+    }
+
+    #[test]
+    fn portable_directories_are_under_the_resources() {
+        let game_dir = resource_dir().join("turnips");
+
+        assert_eq!(
+            AppDirs::portable("turnips"),
+            AppDirs {
+                config_dir: game_dir.clone(),
+                save_dir: game_dir.join("saves"),
+                screenshot_dir: game_dir.join("screenshots"),
+            }
+        );
+    }
+}
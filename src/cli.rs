@@ -0,0 +1,316 @@
+use ggez::GameError;
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const USAGE: &str = "\
+Options:
+  --config <path>       Load the game config from <path> instead of the default
+  --set <path>=<value>  Override one config value, e.g. --set world.starting_cursor.x=3
+  --seed <n>            Seed the world's randomness, so that runs can be repeated
+  --tick-rate <frames>  Frames between world ticks
+  --scale <n>           Screen pixels per sprite pixel
+  --headless            Run without a window (needs --ticks)
+  --ticks <n>           How many world ticks a headless run lasts
+  --midi-port <name>    The MIDI input to use, by (part of) its name
+  --midi-ports          List the MIDI inputs and exit
+  --record-midi <path>  Record the MIDI played to a .mid file at <path>
+  --play-midi <path>    Play the .mid file at <path> as MIDI input, in place of a device
+  --portable            Keep config, saves and screenshots under resources/
+  --help                Show this message";
+
+/// The command line options every game understands
+///
+/// `--set` overrides are left for `ConfigLayers::args` to pick up.
+///
+/// ## Examples
+///
+/// ```ignore
+/// let cli_options = CliOptions::from_env()?;
+///
+/// let app_config = AppConfig::new((GRID_WIDTH, GRID_HEIGHT))
+///     .tick_rate(20)
+///     .cli_options(&cli_options)
+///     .finalize()?;
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CliOptions {
+    pub config_path: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub tick_rate: Option<usize>,
+    pub window_scale: Option<f32>,
+    /// How many ticks to run for without a window, if headless
+    pub headless_ticks: Option<usize>,
+    pub midi_port: Option<String>,
+    pub list_midi_ports: bool,
+    pub record_midi: Option<PathBuf>,
+    pub play_midi: Option<PathBuf>,
+    pub portable: bool,
+    pub help: bool,
+}
+
+impl CliOptions {
+    /// Reads the options this program was started with
+    pub fn from_env() -> Result<Self, CliError> {
+        Self::parse(env::args().skip(1))
+    }
+
+    /// Reads options from `args`, which shouldn't include the program name
+    pub fn parse<I, S>(args: I) -> Result<Self, CliError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let args: Vec<String> = args.into_iter().map(|a| a.as_ref().to_string()).collect();
+        let mut args = args.into_iter();
+
+        let mut options = Self::default();
+        let mut headless = false;
+        let mut ticks: Option<usize> = None;
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                return Err(CliError::UnexpectedArgument(arg));
+            }
+
+            let mut parts = arg.splitn(2, '=');
+            let option = parts.next().unwrap_or("").to_string();
+            let inline_value = parts.next().map(|v| v.to_string());
+
+            // The next argument is only taken as the value if it isn't another option
+            let mut value = || match inline_value.clone() {
+                Some(value) => Ok(value),
+                None => match args.next() {
+                    Some(value) if !value.starts_with("--") => Ok(value),
+                    _ => Err(CliError::MissingValue(option.clone())),
+                },
+            };
+
+            match option.as_str() {
+                "--config" => options.config_path = Some(PathBuf::from(value()?)),
+                "--set" => {
+                    value()?;
+                }
+                "--seed" => options.seed = Some(parse_value(&option, value()?)?),
+                "--tick-rate" => options.tick_rate = Some(parse_value(&option, value()?)?),
+                "--scale" => options.window_scale = Some(parse_value(&option, value()?)?),
+                "--headless" => headless = true,
+                "--ticks" => ticks = Some(parse_value(&option, value()?)?),
+                "--midi-port" => options.midi_port = Some(value()?),
+                "--midi-ports" => options.list_midi_ports = true,
+                "--record-midi" => options.record_midi = Some(PathBuf::from(value()?)),
+                "--play-midi" => options.play_midi = Some(PathBuf::from(value()?)),
+                "--portable" => options.portable = true,
+                "--help" => options.help = true,
+                _ => return Err(CliError::UnknownOption(option.clone())),
+            }
+        }
+
+        options.headless_ticks = match (headless, ticks) {
+            (true, Some(ticks)) => Some(ticks),
+            (false, None) => None,
+            (true, None) => return Err(CliError::HeadlessWithoutTicks),
+            (false, Some(_)) => return Err(CliError::TicksWithoutHeadless),
+        };
+
+        Ok(options)
+    }
+
+    /// What `--help` prints, headed by the program's name
+    pub fn usage(program_name: &str) -> String {
+        format!("Usage: {} [options]\n\n{}", program_name, USAGE)
+    }
+}
+
+fn parse_value<T: FromStr>(option: &str, value: String) -> Result<T, CliError> {
+    value.parse().map_err(|_| CliError::InvalidValue {
+        option: option.to_string(),
+        value,
+    })
+}
+
+/// Why the command line couldn't be understood
+#[derive(Clone, Debug, PartialEq)]
+pub enum CliError {
+    UnknownOption(String),
+    UnexpectedArgument(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+    HeadlessWithoutTicks,
+    TicksWithoutHeadless,
+    UnsupportedOption(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::UnknownOption(option) => write!(f, "Unknown option {}", option),
+            CliError::UnexpectedArgument(arg) => write!(f, "Unexpected argument {}", arg),
+            CliError::MissingValue(option) => write!(f, "{} needs a value", option),
+            CliError::InvalidValue { option, value } => {
+                write!(f, "{} is not a valid value for {}", value, option)
+            }
+            CliError::HeadlessWithoutTicks => {
+                write!(f, "--headless needs --ticks to say how long to run")
+            }
+            CliError::TicksWithoutHeadless => write!(f, "--ticks only applies with --headless"),
+            CliError::UnsupportedOption(option) => {
+                write!(f, "{} isn't supported by this game", option)
+            }
+        }
+    }
+}
+
+impl Error for CliError {}
+
+impl From<CliError> for GameError {
+    fn from(e: CliError) -> Self {
+        GameError::ConfigError(format!("{} (see --help)", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliOptions, CliError> {
+        CliOptions::parse(args)
+    }
+
+    #[test]
+    fn no_options_leave_everything_unset() {
+        assert_eq!(parse(&[]), Ok(CliOptions::default()));
+    }
+
+    #[test]
+    fn every_option_is_read() {
+        let options = parse(&[
+            "--config",
+            "my-config.yaml",
+            "--set",
+            "world.starting_cursor.x=3",
+            "--seed",
+            "42",
+            "--tick-rate",
+            "20",
+            "--scale",
+            "2.5",
+            "--headless",
+            "--ticks",
+            "100",
+            "--midi-port",
+            "nanoKONTROL2",
+            "--midi-ports",
+            "--record-midi",
+            "out.mid",
+            "--play-midi",
+            "in.mid",
+            "--portable",
+            "--help",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            options,
+            CliOptions {
+                config_path: Some(PathBuf::from("my-config.yaml")),
+                seed: Some(42),
+                tick_rate: Some(20),
+                window_scale: Some(2.5),
+                headless_ticks: Some(100),
+                midi_port: Some("nanoKONTROL2".to_string()),
+                list_midi_ports: true,
+                record_midi: Some(PathBuf::from("out.mid")),
+                play_midi: Some(PathBuf::from("in.mid")),
+                portable: true,
+                help: true,
+            }
+        );
+    }
+
+    #[test]
+    fn values_can_follow_an_equals_sign() {
+        let options = parse(&["--seed=7", "--midi-port=Midi Through", "--set=a.b=c"]).unwrap();
+
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.midi_port, Some("Midi Through".to_string()));
+    }
+
+    #[test]
+    fn options_missing_their_value_are_rejected() {
+        for option in &[
+            "--config",
+            "--set",
+            "--seed",
+            "--tick-rate",
+            "--scale",
+            "--ticks",
+            "--midi-port",
+            "--record-midi",
+            "--play-midi",
+        ] {
+            assert_eq!(
+                parse(&[option]),
+                Err(CliError::MissingValue(option.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn another_option_is_not_taken_as_a_value() {
+        assert_eq!(
+            parse(&["--config", "--portable"]),
+            Err(CliError::MissingValue("--config".to_string()))
+        );
+        assert_eq!(
+            parse(&["--midi-port", "--help"]),
+            Err(CliError::MissingValue("--midi-port".to_string()))
+        );
+    }
+
+    #[test]
+    fn values_that_dont_parse_are_rejected() {
+        assert_eq!(
+            parse(&["--seed", "lucky"]),
+            Err(CliError::InvalidValue {
+                option: "--seed".to_string(),
+                value: "lucky".to_string(),
+            })
+        );
+        assert_eq!(
+            parse(&["--tick-rate=-1"]),
+            Err(CliError::InvalidValue {
+                option: "--tick-rate".to_string(),
+                value: "-1".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_options_and_stray_arguments_are_rejected() {
+        assert_eq!(
+            parse(&["--fullscreen"]),
+            Err(CliError::UnknownOption("--fullscreen".to_string()))
+        );
+        assert_eq!(
+            parse(&["--fullscreen=yes"]),
+            Err(CliError::UnknownOption("--fullscreen".to_string()))
+        );
+        assert_eq!(
+            parse(&["config.yaml"]),
+            Err(CliError::UnexpectedArgument("config.yaml".to_string()))
+        );
+    }
+
+    #[test]
+    fn headless_runs_need_ticks_and_ticks_need_headless() {
+        assert_eq!(parse(&["--headless"]), Err(CliError::HeadlessWithoutTicks));
+        assert_eq!(
+            parse(&["--ticks", "10"]),
+            Err(CliError::TicksWithoutHeadless)
+        );
+    }
+}
@@ -0,0 +1,555 @@
+mod format;
+mod layers;
+mod lines;
+mod migrations;
+mod watcher;
+
+use serde::{Deserialize, Serialize};
+use serde_ignored;
+use serde_path_to_error;
+use serde_yaml;
+use serde_yaml::Value;
+
+use std::error::Error;
+use std::fmt;
+
+use crate::sprites::{Coords, GridUnit};
+
+use layers::LayerOrigin;
+use lines::LineMap;
+
+pub use format::ConfigFormat;
+pub use layers::{save_value, ConfigLayers, ENV_PREFIX};
+pub use migrations::{Migration, Migrations, Versioned, FIRST_VERSION, VERSION_KEY};
+pub use watcher::ConfigWatcher;
+
+// Each type error is patched over with the example config's value at the same path
+// so that parsing can carry on and find the rest. This caps how often we try.
+const MAX_TYPE_ERRORS: usize = 32;
+
+/// Domain checks for a game config, run after it has been parsed
+///
+/// Report problems against the path of the offending value so that they can be traced back to a
+/// line in the config file.
+///
+/// ## Examples
+///
+/// ```ignore
+/// impl Validate for CursorConfig {
+///     fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+///         report.check_within_grid(path, (self.x, self.y), (GRID_WIDTH, GRID_HEIGHT));
+///     }
+/// }
+/// ```
+pub trait Validate {
+    fn validate(&self, _path: &ConfigPath, _report: &mut ValidationReport) {}
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        for (i, item) in self.iter().enumerate() {
+            item.validate(&path.index(i), report);
+        }
+    }
+}
+
+impl<T: Validate> Validate for Option<T> {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        if let Some(item) = self {
+            item.validate(path, report);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+/// The location of a value within a config, e.g. `world.starting_villagers[1].x`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ConfigPath {
+    segments: Vec<PathSegment>,
+}
+
+impl ConfigPath {
+    pub fn root() -> Self {
+        Self { segments: vec![] }
+    }
+
+    pub fn field<S>(&self, name: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        let mut segments = self.segments.clone();
+        segments.push(PathSegment::Field(name.as_ref().to_string()));
+
+        Self { segments }
+    }
+
+    pub fn index(&self, index: usize) -> Self {
+        let mut segments = self.segments.clone();
+        segments.push(PathSegment::Index(index));
+
+        Self { segments }
+    }
+
+    fn parent(&self) -> Option<Self> {
+        if self.segments.is_empty() {
+            return None;
+        }
+
+        let mut segments = self.segments.clone();
+        segments.pop();
+
+        Some(Self { segments })
+    }
+
+    fn from_ignored(path: &serde_ignored::Path) -> Self {
+        match path {
+            serde_ignored::Path::Root => Self::root(),
+            serde_ignored::Path::Seq { parent, index } => Self::from_ignored(parent).index(*index),
+            serde_ignored::Path::Map { parent, key } => Self::from_ignored(parent).field(key),
+            serde_ignored::Path::Some { parent }
+            | serde_ignored::Path::NewtypeStruct { parent }
+            | serde_ignored::Path::NewtypeVariant { parent } => Self::from_ignored(parent),
+        }
+    }
+
+    fn from_tracked(path: &serde_path_to_error::Path) -> Self {
+        let mut config_path = Self::root();
+
+        for segment in path.iter() {
+            config_path = match segment {
+                serde_path_to_error::Segment::Seq { index } => config_path.index(*index),
+                serde_path_to_error::Segment::Map { key } => config_path.field(key),
+                serde_path_to_error::Segment::Enum { variant } => config_path.field(variant),
+                serde_path_to_error::Segment::Unknown => config_path,
+            };
+        }
+
+        config_path
+    }
+
+    // Sequence indexes fall back to the first item, since an example config
+    // usually has fewer items than the real thing
+    fn example_value_in<'a>(&self, example: &'a Value) -> Option<&'a Value> {
+        let mut value = example;
+
+        for segment in self.segments.iter() {
+            value = match segment {
+                PathSegment::Field(name) => value.get(name.as_str())?,
+                PathSegment::Index(i) => value.get(*i).or_else(|| value.get(0))?,
+            };
+        }
+
+        Some(value)
+    }
+
+    fn value_in<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        let mut value = value;
+
+        for segment in self.segments.iter() {
+            value = match segment {
+                PathSegment::Field(name) => value.get(name.as_str())?,
+                PathSegment::Index(i) => value.get(*i)?,
+            };
+        }
+
+        Some(value)
+    }
+
+    fn value_in_mut<'a>(&self, value: &'a mut Value) -> Option<&'a mut Value> {
+        let mut value = value;
+
+        for segment in self.segments.iter() {
+            value = match segment {
+                PathSegment::Field(name) => value.get_mut(name.as_str())?,
+                PathSegment::Index(i) => value.get_mut(*i)?,
+            };
+        }
+
+        Some(value)
+    }
+}
+
+impl fmt::Display for ConfigPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.segments.is_empty() {
+            return write!(f, "(root)");
+        }
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                PathSegment::Field(name) if i == 0 => write!(f, "{}", name)?,
+                PathSegment::Field(name) => write!(f, ".{}", name)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A single problem found in a config
+///
+/// `source` names the file, variable or argument the value came from when the config was
+/// resolved from several layers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationError {
+    pub path: ConfigPath,
+    pub source: Option<String>,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(source) = &self.source {
+            write!(f, "{}, ", source)?;
+        }
+
+        if let Some(line) = self.line {
+            write!(f, "line {}, ", line)?;
+        }
+
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Every problem found while parsing and validating a config
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    errors: Vec<ValidationError>,
+    origins: Vec<LayerOrigin>,
+}
+
+impl ValidationReport {
+    pub fn error<S>(&mut self, path: ConfigPath, message: S)
+    where
+        S: AsRef<str>,
+    {
+        let (source, line) = self.locate(&path);
+
+        self.error_from(source, line, path, message);
+    }
+
+    fn error_from<S>(
+        &mut self,
+        source: Option<String>,
+        line: Option<usize>,
+        path: ConfigPath,
+        message: S,
+    ) where
+        S: AsRef<str>,
+    {
+        let error = ValidationError {
+            path,
+            source,
+            line,
+            message: message.as_ref().to_string(),
+        };
+
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+
+    // Finds the last layer that set the value, and the line it's on if that layer was a file.
+    // Missing values are reported against the closest parent that does exist.
+    fn locate(&self, path: &ConfigPath) -> (Option<String>, Option<usize>) {
+        let mut path = Some(path.clone());
+
+        while let Some(p) = path {
+            if let Some(origin) = self.origins.iter().rev().find(|o| o.defines(&p)) {
+                return (origin.name.clone(), origin.line_of(&p));
+            }
+
+            path = p.parent();
+        }
+
+        (None, None)
+    }
+
+    /// Reports `x` and/or `y` under `path` if they fall outside of a grid with the given dimensions
+    pub fn check_within_grid(
+        &mut self,
+        path: &ConfigPath,
+        coords: Coords,
+        grid_dimensions: (GridUnit, GridUnit),
+    ) {
+        let (x, y) = coords;
+        let (width, height) = grid_dimensions;
+
+        if x >= width {
+            self.error(
+                path.field("x"),
+                format!(
+                    "x = {} is outside of the {}x{} grid (0 to {})",
+                    x,
+                    width,
+                    height,
+                    width.saturating_sub(1)
+                ),
+            );
+        }
+
+        if y >= height {
+            self.error(
+                path.field("y"),
+                format!(
+                    "y = {} is outside of the {}x{} grid (0 to {})",
+                    y,
+                    width,
+                    height,
+                    height.saturating_sub(1)
+                ),
+            );
+        }
+    }
+
+    pub fn errors(&self) -> &[ValidationError] {
+        &self.errors
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = self.errors.len();
+
+        write!(
+            f,
+            "Found {} problem{} in config",
+            count,
+            if count == 1 { "" } else { "s" }
+        )?;
+
+        let mut errors: Vec<&ValidationError> = self.errors.iter().collect();
+        errors.sort_by_key(|e| e.line.unwrap_or(usize::MAX));
+
+        for error in errors {
+            write!(f, "\n  {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for ValidationReport {}
+
+/// Parses and validates a YAML config, collecting every problem instead of stopping at the first
+///
+/// Unknown fields, type errors and the config's own `Validate` checks are all reported,
+/// each with its path and line number.
+pub fn parse_config<S, D>(source: &str, example_config: &S) -> Result<D, ValidationReport>
+where
+    S: Serialize,
+    for<'de> D: Deserialize<'de> + Validate,
+{
+    let mut report = ValidationReport::default();
+
+    let value: Value = match serde_yaml::from_str(source) {
+        Ok(value) => value,
+        Err(e) => {
+            let line = e.location().map(|l| l.line());
+            report.error_from(None, line, ConfigPath::root(), e.to_string());
+            return Err(report);
+        }
+    };
+
+    report.origins.push(LayerOrigin::document(
+        None,
+        LineMap::from_source(source),
+        value.clone(),
+    ));
+
+    let example_value = serde_yaml::to_value(example_config).unwrap_or(Value::Null);
+
+    parse_value(value, &example_value, report)
+}
+
+// Deserializes and validates a value whose origins are already in the report
+fn parse_value<D>(
+    mut value: Value,
+    example_value: &Value,
+    mut report: ValidationReport,
+) -> Result<D, ValidationReport>
+where
+    for<'de> D: Deserialize<'de> + Validate,
+{
+    let mut failed_paths: Vec<ConfigPath> = vec![];
+
+    loop {
+        let mut unknown_fields: Vec<ConfigPath> = vec![];
+
+        let result: Result<D, _> = {
+            let mut callback = |path: serde_ignored::Path| {
+                unknown_fields.push(ConfigPath::from_ignored(&path));
+            };
+
+            let deserializer = serde_ignored::Deserializer::new(value.clone(), &mut callback);
+
+            serde_path_to_error::deserialize(deserializer)
+        };
+
+        for path in unknown_fields {
+            report.error(path, "unknown field");
+        }
+
+        match result {
+            Ok(config) => {
+                config.validate(&ConfigPath::root(), &mut report);
+
+                if report.is_empty() {
+                    return Ok(config);
+                } else {
+                    return Err(report);
+                }
+            }
+            Err(e) => {
+                let path = ConfigPath::from_tracked(e.path());
+
+                report.error(path.clone(), e.inner().to_string());
+
+                if failed_paths.contains(&path) || failed_paths.len() >= MAX_TYPE_ERRORS {
+                    return Err(report);
+                }
+
+                let replacement = match path.example_value_in(example_value) {
+                    Some(replacement) => replacement.clone(),
+                    None => return Err(report),
+                };
+
+                match path.value_in_mut(&mut value) {
+                    Some(target) => *target = replacement,
+                    None => return Err(report),
+                }
+
+                failed_paths.push(path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    struct TestConfig {
+        window: TestWindow,
+        enemies: Vec<TestEnemy>,
+    }
+
+    impl Validate for TestConfig {
+        fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+            self.enemies.validate(&path.field("enemies"), report);
+        }
+    }
+
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    struct TestWindow {
+        width: u32,
+        title: String,
+    }
+
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    struct TestEnemy {
+        name: String,
+        speed: f32,
+    }
+
+    impl Validate for TestEnemy {
+        fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+            if self.speed < 0.0 {
+                report.error(path.field("speed"), "must not be negative");
+            }
+        }
+    }
+
+    fn example() -> TestConfig {
+        TestConfig {
+            window: TestWindow::default(),
+            enemies: vec![TestEnemy::default()],
+        }
+    }
+
+    fn problems(report: &ValidationReport) -> Vec<(String, Option<usize>)> {
+        let mut problems: Vec<(String, Option<usize>)> = report
+            .errors()
+            .iter()
+            .map(|e| (e.path.to_string(), e.line))
+            .collect();
+        problems.sort();
+
+        problems
+    }
+
+    #[test]
+    fn every_nested_type_error_is_reported_on_its_line() {
+        let source = "\
+window:
+  width: wide
+  title: Turnips
+enemies:
+  - name: squid
+    speed: 1.5
+  - name: crab
+    speed: fast
+";
+
+        let report = parse_config::<_, TestConfig>(source, &example()).unwrap_err();
+
+        assert_eq!(
+            problems(&report),
+            vec![
+                ("enemies[1].speed".to_string(), Some(8)),
+                ("window.width".to_string(), Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_reported_alongside_other_problems() {
+        let source = "\
+window:
+  width: 640
+  colour: red
+  title: Turnips
+enemies:
+  - name: squid
+    speed: -1
+    sped: 2
+";
+
+        let report = parse_config::<_, TestConfig>(source, &example()).unwrap_err();
+
+        assert_eq!(
+            problems(&report),
+            vec![
+                ("enemies[0].sped".to_string(), Some(8)),
+                ("enemies[0].speed".to_string(), Some(7)),
+                ("window.colour".to_string(), Some(3)),
+            ]
+        );
+        assert!(report
+            .errors()
+            .iter()
+            .filter(|e| e.path.to_string() != "enemies[0].speed")
+            .all(|e| e.message == "unknown field"));
+    }
+
+    #[test]
+    fn valid_configs_parse() {
+        let source = "window: { width: 640, title: Turnips }\nenemies: []\n";
+
+        let config: TestConfig = parse_config(source, &example()).unwrap();
+
+        assert_eq!(config.window.width, 640);
+    }
+}
@@ -0,0 +1,140 @@
+mod ron_reader;
+
+use ron;
+use serde::Serialize;
+use serde_json;
+use serde_yaml;
+use serde_yaml::Value;
+use toml;
+
+use std::fmt;
+use std::path::Path;
+
+use crate::config::lines::LineMap;
+
+/// The file formats a config can be written in
+///
+/// Every format is read into the same shape, so layers, migrations and validation work the
+/// same whichever one a file uses.
+///
+/// RON configs can name enum variants directly, e.g. `direction: Left`. Leave struct names out
+/// (`(x: 1, y: 2)` rather than `Point(x: 1, y: 2)`), since a name followed by parentheses is
+/// read as an enum variant.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    #[default]
+    Yaml,
+    Toml,
+    Json,
+    Ron,
+}
+
+impl ConfigFormat {
+    /// Picks a format by file extension, e.g. `config.ron`
+    pub fn from_path<P>(path: P) -> Option<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+
+        let format = match extension.as_str() {
+            "yaml" | "yml" => ConfigFormat::Yaml,
+            "toml" => ConfigFormat::Toml,
+            "json" => ConfigFormat::Json,
+            "ron" => ConfigFormat::Ron,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Yaml => "yaml",
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Json => "json",
+            ConfigFormat::Ron => "ron",
+        }
+    }
+
+    pub fn serialize<T>(self, value: &T) -> Result<String, String>
+    where
+        T: Serialize,
+    {
+        match self {
+            ConfigFormat::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+            // TOML can't write enum variants with data, so they go through YAML's map form first.
+            // A TOML value then puts plain values ahead of tables, as TOML requires.
+            ConfigFormat::Toml => {
+                let source = serde_yaml::to_value(value)
+                    .map_err(|e| e.to_string())
+                    .and_then(|v| toml::Value::try_from(v).map_err(|e| e.to_string()))
+                    .and_then(|v| toml::to_string_pretty(&v).map_err(|e| e.to_string()))?;
+
+                // Lists that mix plain values with tables come out as something TOML can't read
+                toml::from_str::<toml::Value>(&source).map_err(|_| {
+                    "TOML can't hold a list that mixes plain values with tables".to_string()
+                })?;
+
+                Ok(source)
+            }
+            ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Ron => {
+                ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    // Reads a config along with the line each of its values is on, where the format allows
+    pub(crate) fn parse(self, source: &str) -> Result<(Value, LineMap), FormatError> {
+        match self {
+            ConfigFormat::Yaml => {
+                let value = serde_yaml::from_str(source).map_err(|e| FormatError {
+                    line: e.location().map(|l| l.line()),
+                    message: e.to_string(),
+                })?;
+
+                Ok((value, LineMap::from_source(source)))
+            }
+            ConfigFormat::Toml => {
+                let value = toml::from_str(source).map_err(|e| FormatError {
+                    line: e.line_col().map(|(line, _)| line + 1),
+                    message: e.to_string(),
+                })?;
+
+                Ok((value, LineMap::default()))
+            }
+            ConfigFormat::Json => {
+                let value = serde_json::from_str(source).map_err(|e| FormatError {
+                    line: Some(e.line()),
+                    message: e.to_string(),
+                })?;
+
+                // JSON is close enough to YAML for the line map to read it
+                Ok((value, LineMap::from_source(source)))
+            }
+            ConfigFormat::Ron => ron_reader::read(source),
+        }
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ConfigFormat::Yaml => "YAML",
+            ConfigFormat::Toml => "TOML",
+            ConfigFormat::Json => "JSON",
+            ConfigFormat::Ron => "RON",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Why a config file couldn't be read at all
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FormatError {
+    pub line: Option<usize>,
+    pub message: String,
+}
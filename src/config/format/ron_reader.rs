@@ -0,0 +1,707 @@
+use serde_yaml::{Mapping, Value};
+
+use crate::config::format::FormatError;
+use crate::config::lines::LineMap;
+use crate::config::ConfigPath;
+
+const RAW_IDENTIFIER_PREFIX: &str = "r#";
+
+// The `ron` crate can only tell values apart with the config's types in hand, and reading
+// without them turns enum variants into nulls. This reader keeps them as the strings (or
+// single-key maps, for variants with data) that every other format uses.
+//
+// It reads this much of RON, which is everything a config needs:
+// - `#![enable(...)]` extensions at the top, which are skipped as they change nothing here
+// - `//` comments and `/* */` comments, which can nest
+// - `true`, `false`, `None`, and `Some(x)`, which is read as just `x`
+// - integers, including `0x`, `0o` and `0b` ones, and floats, including `inf`, `-inf` and
+//   `NaN`. Either can have `_` between digits.
+// - strings with escapes (`\n`, `\u{1F980}`, `\u00e9`), raw strings like `r#"..."#`, and
+//   characters like `'a'`, which are read as strings
+// - lists `[1, 2]`, and maps `{"key": 1}` with string or number keys
+// - structs `(x: 1, y: 2)` as maps, tuples `(1, 2)` as lists, and `()` as nothing
+// - enum variants `Left`, `Move(Up)` and `Enemy(kind: Squid)` as strings and single-key maps
+// - raw identifiers like `r#type`, without the `r#`
+//
+// Anything else is an error, including byte strings like `b"..."`. Named structs like
+// `Point(x: 1)` look just like variants, so they're read as them.
+pub fn read(source: &str) -> Result<(Value, LineMap), FormatError> {
+    let mut reader = Reader {
+        source,
+        pos: 0,
+        line: 1,
+        lines: LineMap::default(),
+    };
+
+    reader.skip_extensions()?;
+
+    let value = reader.value(&ConfigPath::root())?;
+
+    reader.skip_whitespace()?;
+
+    if reader.peek().is_some() {
+        return reader.error("expected the end of the file");
+    }
+
+    Ok((value, reader.lines))
+}
+
+struct Reader<'a> {
+    source: &'a str,
+    pos: usize,
+    line: usize,
+    lines: LineMap,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+
+        if c == '\n' {
+            self.line += 1;
+        }
+
+        Some(c)
+    }
+
+    fn error<T, S>(&self, message: S) -> Result<T, FormatError>
+    where
+        S: AsRef<str>,
+    {
+        Err(FormatError {
+            line: Some(self.line),
+            message: message.as_ref().to_string(),
+        })
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), FormatError> {
+        self.skip_whitespace()?;
+
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => self.error(format!("expected '{}', found '{}'", expected, c)),
+            None => self.error(format!(
+                "expected '{}', found the end of the file",
+                expected
+            )),
+        }
+    }
+
+    // Extensions like `#![enable(implicit_some)]` don't change how values are read here
+    fn skip_extensions(&mut self) -> Result<(), FormatError> {
+        loop {
+            self.skip_whitespace()?;
+
+            if !self.rest().starts_with("#![") {
+                return Ok(());
+            }
+
+            while let Some(c) = self.bump() {
+                if c == ']' {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) -> Result<(), FormatError> {
+        loop {
+            let rest = self.rest();
+
+            if rest.starts_with("//") {
+                while let Some(c) = self.bump() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            } else if rest.starts_with("/*") {
+                self.skip_block_comment()?;
+            } else if self.peek().is_some_and(char::is_whitespace) {
+                self.bump();
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_block_comment(&mut self) -> Result<(), FormatError> {
+        let mut depth = 0;
+
+        loop {
+            let rest = self.rest();
+
+            if rest.starts_with("/*") {
+                depth += 1;
+                self.pos += 2;
+            } else if rest.starts_with("*/") {
+                depth -= 1;
+                self.pos += 2;
+
+                if depth == 0 {
+                    return Ok(());
+                }
+            } else if self.bump().is_none() {
+                return self.error("unterminated comment");
+            }
+        }
+    }
+
+    fn value(&mut self, path: &ConfigPath) -> Result<Value, FormatError> {
+        self.skip_whitespace()?;
+
+        self.lines.insert(path.clone(), self.line);
+
+        match self.peek() {
+            None => self.error("expected a value, found the end of the file"),
+            Some('[') => self.list(path),
+            Some('{') => self.map(path),
+            Some('(') => self.parens(path, false),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('r') if self.raw_string_ahead() => Ok(Value::String(self.raw_string()?)),
+            Some('\'') => Ok(Value::String(self.character()?)),
+            Some(c) if c.is_ascii_digit() || c == '+' || c == '-' || c == '.' => self.number(),
+            Some(c) if is_identifier_start(c) => self.identified(path),
+            Some(c) => self.error(format!("unexpected '{}'", c)),
+        }
+    }
+
+    fn list(&mut self, path: &ConfigPath) -> Result<Value, FormatError> {
+        self.expect('[')?;
+
+        let mut items = vec![];
+
+        loop {
+            self.skip_whitespace()?;
+
+            if self.peek() == Some(']') {
+                self.bump();
+                break;
+            }
+
+            items.push(self.value(&path.index(items.len()))?);
+
+            if self.separator(']')? {
+                break;
+            }
+        }
+
+        Ok(Value::Sequence(items))
+    }
+
+    fn map(&mut self, path: &ConfigPath) -> Result<Value, FormatError> {
+        self.expect('{')?;
+
+        let mut mapping = Mapping::new();
+
+        loop {
+            self.skip_whitespace()?;
+
+            if self.peek() == Some('}') {
+                self.bump();
+                break;
+            }
+
+            let key_line = self.line;
+            let key = self.value(path)?;
+
+            let key_path = match &key {
+                Value::String(name) => path.field(name),
+                Value::Number(n) => path.field(n.to_string()),
+                _ => return self.error("map keys must be strings or numbers"),
+            };
+
+            self.lines.insert(key_path.clone(), key_line);
+
+            self.expect(':')?;
+
+            let value = self.value(&key_path)?;
+            mapping.insert(key, value);
+
+            if self.separator('}')? {
+                break;
+            }
+        }
+
+        Ok(Value::Mapping(mapping))
+    }
+
+    // Structs `(x: 1, y: 2)` become maps, tuples `(1, 2)` become lists, and `()` is nothing. A
+    // single value is a tuple of one too, unless it's the content of `Some(...)` or a variant.
+    fn parens(&mut self, path: &ConfigPath, unwrap_single: bool) -> Result<Value, FormatError> {
+        self.expect('(')?;
+        self.skip_whitespace()?;
+
+        if self.peek() == Some(')') {
+            self.bump();
+            return Ok(Value::Null);
+        }
+
+        if self.field_name_ahead() {
+            return self.struct_fields(path);
+        }
+
+        let mut items = vec![];
+
+        loop {
+            self.skip_whitespace()?;
+
+            if self.peek() == Some(')') {
+                self.bump();
+                break;
+            }
+
+            items.push(self.value(&path.index(items.len()))?);
+
+            if self.separator(')')? {
+                break;
+            }
+        }
+
+        if unwrap_single && items.len() == 1 {
+            Ok(items.remove(0))
+        } else {
+            Ok(Value::Sequence(items))
+        }
+    }
+
+    fn struct_fields(&mut self, path: &ConfigPath) -> Result<Value, FormatError> {
+        let mut mapping = Mapping::new();
+
+        loop {
+            self.skip_whitespace()?;
+
+            if self.peek() == Some(')') {
+                self.bump();
+                break;
+            }
+
+            let key_line = self.line;
+            let name = self.identifier();
+
+            if name.is_empty() {
+                return self.error("expected a field name");
+            }
+
+            let field_path = path.field(&name);
+            self.lines.insert(field_path.clone(), key_line);
+
+            self.expect(':')?;
+
+            let value = self.value(&field_path)?;
+            mapping.insert(Value::String(name), value);
+
+            if self.separator(')')? {
+                break;
+            }
+        }
+
+        Ok(Value::Mapping(mapping))
+    }
+
+    // Consumes the comma after an item, or the closing bracket. Returns true once closed.
+    fn separator(&mut self, close: char) -> Result<bool, FormatError> {
+        self.skip_whitespace()?;
+
+        match self.bump() {
+            Some(',') => Ok(false),
+            Some(c) if c == close => Ok(true),
+            Some(c) => self.error(format!("expected ',' or '{}', found '{}'", close, c)),
+            None => self.error(format!("expected '{}', found the end of the file", close)),
+        }
+    }
+
+    // `r"..."` and `r#"..."#` rather than a raw identifier like `r#type`
+    fn raw_string_ahead(&self) -> bool {
+        self.rest()[1..].trim_start_matches('#').starts_with('"')
+    }
+
+    fn field_name_ahead(&self) -> bool {
+        let rest = self.rest();
+        let rest = rest.strip_prefix(RAW_IDENTIFIER_PREFIX).unwrap_or(rest);
+
+        let name_length: usize = rest
+            .chars()
+            .take_while(|c| is_identifier_char(*c))
+            .map(char::len_utf8)
+            .sum();
+
+        if name_length == 0 || !rest.starts_with(is_identifier_start) {
+            return false;
+        }
+
+        let after_name = rest[name_length..].trim_start();
+
+        after_name.starts_with(':') && !after_name.starts_with("::")
+    }
+
+    // Raw identifiers like `r#type` come back without the `r#`
+    fn identifier(&mut self) -> String {
+        let mut name = String::new();
+
+        if self.rest().starts_with(RAW_IDENTIFIER_PREFIX) {
+            self.pos += RAW_IDENTIFIER_PREFIX.len();
+        }
+
+        while let Some(c) = self.peek() {
+            if !is_identifier_char(c) {
+                break;
+            }
+
+            name.push(c);
+            self.bump();
+        }
+
+        name
+    }
+
+    // Keywords, `Some(...)`, and enum variants with or without data
+    fn identified(&mut self, path: &ConfigPath) -> Result<Value, FormatError> {
+        let raw = self.rest().starts_with(RAW_IDENTIFIER_PREFIX);
+        let name = self.identifier();
+
+        match name.as_str() {
+            _ if raw => (),
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            "None" => return Ok(Value::Null),
+            "inf" => return Ok(Value::from(f64::INFINITY)),
+            "NaN" => return Ok(Value::from(f64::NAN)),
+            _ => (),
+        }
+
+        self.skip_whitespace()?;
+
+        if self.peek() != Some('(') {
+            return Ok(Value::String(name));
+        }
+
+        if name == "Some" && !raw {
+            return self.parens(path, true);
+        }
+
+        let mut variant = Mapping::new();
+        let content = self.parens(&path.field(&name), true)?;
+        variant.insert(Value::String(name), content);
+
+        Ok(Value::Mapping(variant))
+    }
+
+    fn number(&mut self) -> Result<Value, FormatError> {
+        let mut text = String::new();
+
+        if let Some(sign) = self.peek().filter(|c| *c == '+' || *c == '-') {
+            text.push(sign);
+            self.bump();
+        }
+
+        if self.rest().starts_with("inf") {
+            self.pos += 3;
+            let infinity = if text == "-" {
+                f64::NEG_INFINITY
+            } else {
+                f64::INFINITY
+            };
+
+            return Ok(Value::from(infinity));
+        }
+
+        while let Some(c) = self.peek() {
+            let exponent_sign =
+                (c == '+' || c == '-') && (text.ends_with('e') || text.ends_with('E'));
+
+            if !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || exponent_sign) {
+                break;
+            }
+
+            if c != '_' {
+                text.push(c);
+            }
+
+            self.bump();
+        }
+
+        let (negative, digits) = match text.chars().next() {
+            Some('-') => (true, &text[1..]),
+            Some('+') => (false, &text[1..]),
+            _ => (false, &text[..]),
+        };
+
+        let radix = match digits.get(..2) {
+            Some("0x") => Some(16),
+            Some("0o") => Some(8),
+            Some("0b") => Some(2),
+            _ => None,
+        };
+
+        let invalid = || FormatError {
+            line: Some(self.line),
+            message: format!("'{}' is not a number", text),
+        };
+
+        if let Some(radix) = radix {
+            let magnitude = i64::from_str_radix(&digits[2..], radix).map_err(|_| invalid())?;
+            let n = if negative { -magnitude } else { magnitude };
+
+            return Ok(Value::from(n));
+        }
+
+        if digits.contains(['.', 'e', 'E']) {
+            return text.parse::<f64>().map(Value::from).map_err(|_| invalid());
+        }
+
+        match text.parse::<i64>() {
+            Ok(n) => Ok(Value::from(n)),
+            Err(_) => digits
+                .parse::<u64>()
+                .map(Value::from)
+                .map_err(|_| invalid()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, FormatError> {
+        self.expect('"')?;
+
+        let mut string = String::new();
+
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(self.escape()?),
+                Some(c) => string.push(c),
+                None => return self.error("unterminated string"),
+            }
+        }
+    }
+
+    fn raw_string(&mut self) -> Result<String, FormatError> {
+        self.bump();
+
+        let mut hashes = 0;
+
+        while self.peek() == Some('#') {
+            hashes += 1;
+            self.bump();
+        }
+
+        self.expect('"')?;
+
+        let terminator = format!("\"{}", "#".repeat(hashes));
+
+        match self.rest().find(&terminator) {
+            Some(length) => {
+                let string = self.rest()[..length].to_string();
+
+                for _ in string.chars() {
+                    self.bump();
+                }
+
+                self.pos += terminator.len();
+
+                Ok(string)
+            }
+            None => self.error("unterminated raw string"),
+        }
+    }
+
+    fn character(&mut self) -> Result<String, FormatError> {
+        self.bump();
+
+        let c = match self.bump() {
+            Some('\\') => self.escape()?,
+            Some(c) => c,
+            None => return self.error("unterminated character"),
+        };
+
+        match self.bump() {
+            Some('\'') => Ok(c.to_string()),
+            _ => self.error("expected ' to end the character"),
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, FormatError> {
+        let c = match self.bump() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('\'') => '\'',
+            Some('u') => return self.unicode_escape(),
+            Some(c) => return self.error(format!("unknown escape '\\{}'", c)),
+            None => return self.error("unterminated escape"),
+        };
+
+        Ok(c)
+    }
+
+    // Both `\u{1F980}` and `\u00e9` are accepted
+    fn unicode_escape(&mut self) -> Result<char, FormatError> {
+        let mut hex = String::new();
+
+        if self.peek() == Some('{') {
+            self.bump();
+
+            while let Some(c) = self.bump() {
+                if c == '}' {
+                    break;
+                }
+
+                hex.push(c);
+            }
+        } else {
+            for _ in 0..4 {
+                hex.extend(self.bump());
+            }
+        }
+
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .map_or_else(
+                || self.error(format!("'{}' is not a unicode escape", hex)),
+                Ok,
+            )
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_value(source: &str) -> Value {
+        read(source).unwrap().0
+    }
+
+    fn yaml(source: &str) -> Value {
+        serde_yaml::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn raw_strings_keep_their_contents() {
+        assert_eq!(read_value(r#"r"C:\turnips""#), yaml(r#"'C:\turnips'"#));
+        assert_eq!(
+            read_value(r###"r##"a "#quoted"# word"##"###),
+            Value::String(r##"a "#quoted"# word"##.to_string())
+        );
+    }
+
+    #[test]
+    fn raw_identifiers_lose_their_prefix() {
+        assert_eq!(
+            read_value("(r#type: r#true, kind: r#Some(1), r#match: Left)"),
+            yaml("{type: 'true', kind: {Some: 1}, match: Left}")
+        );
+    }
+
+    #[test]
+    fn tuples_of_one_stay_lists() {
+        assert_eq!(
+            read_value("(single: (1), pair: (1, 2), trailing: (3,), some: Some(4), unit: ())"),
+            yaml("{single: [1], pair: [1, 2], trailing: [3], some: 4, unit: null}")
+        );
+    }
+
+    #[test]
+    fn nested_enum_variants_read_like_other_formats() {
+        assert_eq!(
+            read_value("[Left, Move(Up), Spawn(Enemy(kind: Squid, at: (1, 2))), Pair(A, (B))]"),
+            yaml(
+                "[Left, {Move: Up}, {Spawn: {Enemy: {kind: Squid, at: [1, 2]}}}, {Pair: [A, [B]]}]"
+            )
+        );
+    }
+
+    #[test]
+    fn comments_are_skipped_and_lines_kept() {
+        let source = "\
+#![enable(implicit_some)]
+// The whole world
+(
+    /* outer /* nested */ still a comment */
+    world: (
+        tick_speed: 40, // frames per tick
+        starting_cursor: (x: 1, y: 2),
+    ),
+)
+";
+
+        let (value, lines) = read(source).unwrap();
+        let world = ConfigPath::root().field("world");
+
+        assert_eq!(
+            value,
+            yaml("{world: {tick_speed: 40, starting_cursor: {x: 1, y: 2}}}")
+        );
+        assert_eq!(lines.line_of(&world.field("tick_speed")), Some(6));
+        assert_eq!(
+            lines.line_of(&world.field("starting_cursor").field("y")),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn unterminated_comments_are_reported() {
+        assert_eq!(
+            read("(x: 1 /* never closed").map(|_| ()),
+            Err(FormatError {
+                line: Some(1),
+                message: "unterminated comment".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn mistakes_are_reported_on_their_line() {
+        let mistakes = vec![
+            ("", 1, "expected a value, found the end of the file"),
+            ("(x: 1)\n(y: 2)", 2, "expected the end of the file"),
+            ("(\n  x: 1\n  y: 2\n)", 3, "expected ',' or ')', found 'y'"),
+            ("[1, 2", 1, "expected ']', found the end of the file"),
+            ("{\n  x 1\n}", 2, "expected ':', found '1'"),
+            ("{[1]: 2}", 1, "map keys must be strings or numbers"),
+            ("(x: @)", 1, "unexpected '@'"),
+            ("(x: 12ab)", 1, "'12ab' is not a number"),
+            ("(x: 0xZZ)", 1, "'0xZZ' is not a number"),
+            ("(x: \"open)", 1, "unterminated string"),
+            ("(x: r#\"open\")", 1, "unterminated raw string"),
+            ("(x: \"\\q\")", 1, "unknown escape '\\q'"),
+            (
+                "(x: \"\\u{110000}\")",
+                1,
+                "'110000' is not a unicode escape",
+            ),
+            ("(x: 'ab')", 1, "expected ' to end the character"),
+            ("(x: b\"bytes\")", 1, "expected ',' or ')', found '\"'"),
+        ];
+
+        for (source, line, message) in mistakes {
+            assert_eq!(
+                read(source).map(|_| ()),
+                Err(FormatError {
+                    line: Some(line),
+                    message: message.to_string()
+                }),
+                "{:?}",
+                source
+            );
+        }
+    }
+}
@@ -0,0 +1,726 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml;
+use serde_yaml::Value;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::lines::LineMap;
+use crate::config::{
+    parse_value, ConfigFormat, ConfigPath, Migrations, PathSegment, Validate, ValidationReport,
+};
+
+/// Environment variables starting with this override single config values
+///
+/// The rest of the name is the value's path, upper-cased, with `__` between segments, e.g.
+/// `SWEET_TURNIPS_WORLD__STARTING_CURSOR__X=3` or `SWEET_TURNIPS_WORLD__STARTING_FARMS__0__Y=2`.
+pub const ENV_PREFIX: &str = "SWEET_TURNIPS_";
+
+const ENV_SEPARATOR: &str = "__";
+
+const SET_FLAG: &str = "--set";
+
+#[derive(Clone, Debug)]
+enum Layer {
+    File(PathBuf),
+    Assignment {
+        name: String,
+        path: String,
+        value: String,
+    },
+    // An argument that couldn't be read as an override, reported when the layers are resolved
+    Malformed {
+        name: String,
+        message: String,
+    },
+}
+
+/// A stack of config sources, each overriding the ones before it
+///
+/// Starts from the compiled-in defaults. Files are merged in key by key, so they only need the
+/// values they change. Lists are replaced as a whole. Single values can then be overridden by
+/// environment variables and `--set path=value` arguments.
+///
+/// Nothing is read until `resolve()`, so the same layers can be resolved again after a file changes.
+///
+/// ## Examples
+///
+/// ```ignore
+/// let game_config: GameConfig = ConfigLayers::new(&example_game_config())
+///     .file(project_config_path)
+///     .file(user_config_path)
+///     .env()
+///     .args(std::env::args())
+///     .resolve()?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConfigLayers {
+    defaults: Value,
+    layers: Vec<Layer>,
+    migrations: Option<Migrations>,
+}
+
+impl ConfigLayers {
+    pub fn new<S>(defaults: &S) -> Self
+    where
+        S: Serialize,
+    {
+        Self {
+            defaults: serde_yaml::to_value(defaults).unwrap_or(Value::Null),
+            layers: vec![],
+            migrations: None,
+        }
+    }
+
+    /// Versions every file layer, upgrading older files as they're read
+    ///
+    /// Files keep their version in a top level `version` key, which is taken out before the
+    /// config is deserialized.
+    pub fn migrations(self, migrations: Migrations) -> Self {
+        Self {
+            migrations: Some(migrations),
+            ..self
+        }
+    }
+
+    /// Adds a file on top, which is skipped if it doesn't exist
+    ///
+    /// Its format comes from its extension, falling back to YAML.
+    pub fn file<P>(self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.with_layer(Layer::File(path.as_ref().to_path_buf()))
+    }
+
+    /// Adds a single override, e.g. `set("world.starting_cursor.x", "3")`
+    ///
+    /// The value is read as YAML, so `3`, `true` and `[1, 2]` keep their types.
+    pub fn set<P, V>(self, path: P, value: V) -> Self
+    where
+        P: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let name = format!("{} {}={}", SET_FLAG, path.as_ref(), value.as_ref());
+
+        self.with_assignment(name, path.as_ref(), value.as_ref())
+    }
+
+    /// Adds an override for every `SWEET_TURNIPS_*` variable in the environment
+    pub fn env(self) -> Self {
+        self.env_vars(env::vars())
+    }
+
+    /// Like `env`, but reading from the given variables instead of the real environment
+    pub fn env_vars<I>(self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut vars: Vec<(String, String)> = vars
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(ENV_PREFIX))
+            .collect();
+
+        // The environment comes in no particular order
+        vars.sort();
+
+        vars.into_iter().fold(self, |layers, (key, value)| {
+            let path = key[ENV_PREFIX.len()..]
+                .split(ENV_SEPARATOR)
+                .map(|segment| segment.to_lowercase())
+                .collect::<Vec<String>>()
+                .join(".");
+
+            layers.with_assignment(key, &path, &value)
+        })
+    }
+
+    /// Adds an override for every `--set path=value` (or `--set=path=value`) in the arguments
+    ///
+    /// Other arguments are left alone. A `--set` without a `path=value` is reported when the
+    /// layers are resolved.
+    pub fn args<I, S>(self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let args: Vec<String> = args.into_iter().map(|a| a.as_ref().to_string()).collect();
+
+        let mut assignments: Vec<Option<String>> = vec![];
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            if arg == SET_FLAG {
+                // As with `CliOptions`, another option isn't taken as the value
+                assignments.push(args.next().filter(|a| !a.starts_with("--")).cloned());
+            } else if let Some(assignment) = arg.strip_prefix("--set=") {
+                assignments.push(Some(assignment.to_string()));
+            }
+        }
+
+        assignments
+            .into_iter()
+            .fold(self, |layers, assignment| match assignment {
+                Some(assignment) => {
+                    let name = format!("{} {}", SET_FLAG, assignment);
+                    let mut parts = assignment.splitn(2, '=');
+                    let path = parts.next().unwrap_or("").to_string();
+
+                    match parts.next() {
+                        Some(value) => layers.with_assignment(name, &path, value),
+                        None => layers.with_layer(Layer::Malformed {
+                            name,
+                            message: format!(
+                                "'{}' should be path=value, e.g. world.starting_cursor.x=3",
+                                assignment
+                            ),
+                        }),
+                    }
+                }
+                None => layers.with_layer(Layer::Malformed {
+                    name: SET_FLAG.to_string(),
+                    message: "needs a path=value after it".to_string(),
+                }),
+            })
+    }
+
+    /// The files in this stack, in the order they're applied
+    pub fn files(&self) -> Vec<&Path> {
+        self.layers
+            .iter()
+            .filter_map(|layer| match layer {
+                Layer::File(path) => Some(path.as_path()),
+                Layer::Assignment { .. } | Layer::Malformed { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Reads every layer and parses the result, collecting every problem along the way
+    ///
+    /// Each problem names the layer its value came from.
+    pub fn resolve<D>(&self) -> Result<D, ValidationReport>
+    where
+        for<'de> D: Deserialize<'de> + Validate,
+    {
+        self.resolve_upgrading(FileUpgrades::Rewrite)
+    }
+
+    // Like `resolve`, but older files are only upgraded in memory, so that reloading while the
+    // game's running never backs up or rewrites the file the player's editing
+    pub(crate) fn reload<D>(&self) -> Result<D, ValidationReport>
+    where
+        for<'de> D: Deserialize<'de> + Validate,
+    {
+        self.resolve_upgrading(FileUpgrades::InMemory)
+    }
+
+    fn resolve_upgrading<D>(&self, upgrades: FileUpgrades) -> Result<D, ValidationReport>
+    where
+        for<'de> D: Deserialize<'de> + Validate,
+    {
+        let mut report = ValidationReport::default();
+        let mut value = self.defaults.clone();
+
+        report.origins.push(LayerOrigin::document(
+            Some("defaults".to_string()),
+            LineMap::default(),
+            self.defaults.clone(),
+        ));
+
+        for layer in self.layers.iter() {
+            match layer {
+                Layer::File(path) => {
+                    let migrations = self.migrations.as_ref();
+
+                    if let Some(document) = read_file_layer(path, migrations, upgrades, &mut report)
+                    {
+                        merge(&mut value, document);
+                    }
+                }
+                Layer::Assignment {
+                    name,
+                    path,
+                    value: raw,
+                } => {
+                    apply_assignment(&mut value, name, path, raw, &mut report);
+                }
+                Layer::Malformed { name, message } => {
+                    report.error_from(Some(name.clone()), None, ConfigPath::root(), message);
+                }
+            }
+        }
+
+        if !report.is_empty() {
+            return Err(report);
+        }
+
+        parse_value(value, &self.defaults, report)
+    }
+
+    fn with_layer(self, layer: Layer) -> Self {
+        let mut layers = self.layers.clone();
+        layers.push(layer);
+
+        Self { layers, ..self }
+    }
+
+    fn with_assignment(self, name: String, path: &str, value: &str) -> Self {
+        self.with_layer(Layer::Assignment {
+            name,
+            path: path.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Sets a single value in a config file, leaving the rest of the file as it was
+///
+/// Meant for settings changed in game, like a control bound with MIDI learn. The file is upgraded
+/// to the current version first, and created if it doesn't exist yet. Comments are lost, since
+/// the file is rewritten.
+///
+/// ## Examples
+///
+/// ```ignore
+/// let path = ConfigPath::root().field("world").field("starting_cursor");
+///
+/// save_value(user_config_path(&config_path), &path, &CursorConfig { x: 3, y: 4 }, &migrations())?;
+/// ```
+pub fn save_value<P, V>(
+    file: P,
+    path: &ConfigPath,
+    value: &V,
+    migrations: &Migrations,
+) -> Result<(), String>
+where
+    P: AsRef<Path>,
+    V: Serialize,
+{
+    let file = file.as_ref();
+    let format = ConfigFormat::from_path(file).unwrap_or_default();
+
+    let mut document = if file.exists() {
+        let source = fs::read_to_string(file).map_err(|e| e.to_string())?;
+        let (document, _) = format.parse(&source).map_err(|e| e.message)?;
+        document
+    } else {
+        Value::Null
+    };
+
+    if document == Value::Null {
+        document = Value::Mapping(Default::default());
+    } else {
+        migrations.upgrade(&mut document)?;
+    }
+
+    let new_value = serde_yaml::to_value(value).map_err(|e| e.to_string())?;
+    set_value_in(&mut document, path, new_value)?;
+
+    let source = format.serialize(&migrations.versioned(&document))?;
+
+    fs::write(file, source).map_err(|e| format!("could not write {}: {}", file.display(), e))
+}
+
+// What happens to files older than the current version as they're read
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum FileUpgrades {
+    Rewrite,
+    InMemory,
+}
+
+fn read_file_layer(
+    path: &Path,
+    migrations: Option<&Migrations>,
+    upgrades: FileUpgrades,
+    report: &mut ValidationReport,
+) -> Option<Value> {
+    let name = path.display().to_string();
+
+    if !path.exists() {
+        return None;
+    }
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            report.error_from(Some(name), None, ConfigPath::root(), e.to_string());
+            return None;
+        }
+    };
+
+    let format = ConfigFormat::from_path(path).unwrap_or_default();
+
+    let (mut document, lines) = match format.parse(&source) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            report.error_from(Some(name), e.line, ConfigPath::root(), e.message);
+            return None;
+        }
+    };
+
+    // An empty file means there's nothing to override
+    if document == Value::Null {
+        return None;
+    }
+
+    let upgraded = migrations.map(|m| match upgrades {
+        FileUpgrades::Rewrite => m.upgrade_file(path, format, &mut document),
+        // The file's left as it is, so its own line numbers still apply
+        FileUpgrades::InMemory => m.upgrade(&mut document).map(|_| None),
+    });
+
+    let lines = match upgraded {
+        Some(Ok(Some(upgraded_source))) => match format.parse(&upgraded_source) {
+            Ok((_, upgraded_lines)) => upgraded_lines,
+            Err(_) => LineMap::default(),
+        },
+        Some(Err(message)) => {
+            report.error_from(Some(name), None, ConfigPath::root(), message);
+            return None;
+        }
+        Some(Ok(None)) | None => lines,
+    };
+
+    report
+        .origins
+        .push(LayerOrigin::document(Some(name), lines, document.clone()));
+
+    Some(document)
+}
+
+fn apply_assignment(
+    value: &mut Value,
+    name: &str,
+    path: &str,
+    raw: &str,
+    report: &mut ValidationReport,
+) {
+    let path = match ConfigPath::parse(path) {
+        Some(path) => path,
+        None => {
+            let message = format!(
+                "'{}' is not a config path like world.starting_cursor.x",
+                path
+            );
+            report.error_from(Some(name.to_string()), None, ConfigPath::root(), message);
+            return;
+        }
+    };
+
+    // Anything that isn't valid YAML on its own is taken as a plain string
+    let new_value = serde_yaml::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()));
+
+    match set_value_in(value, &path, new_value) {
+        Ok(()) => report
+            .origins
+            .push(LayerOrigin::assignment(name.to_string(), path)),
+        Err(message) => report.error_from(Some(name.to_string()), None, path, message),
+    }
+}
+
+// Mappings are merged key by key, anything else is replaced outright
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, overlay_value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, overlay_value),
+                    None => {
+                        base.insert(key, overlay_value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// Missing fields are added along the way, but list items have to exist already
+fn set_value_in(root: &mut Value, path: &ConfigPath, new_value: Value) -> Result<(), String> {
+    let mut value = root;
+    let mut walked = ConfigPath::root();
+
+    for segment in path.segments.iter() {
+        value = match segment {
+            PathSegment::Field(name) => {
+                if *value == Value::Null {
+                    *value = Value::Mapping(Default::default());
+                }
+
+                let mapping = match value {
+                    Value::Mapping(mapping) => mapping,
+                    _ => return Err(format!("{} has no fields to set", walked)),
+                };
+
+                let key = Value::String(name.clone());
+
+                if !mapping.contains_key(&key) {
+                    mapping.insert(key.clone(), Value::Null);
+                }
+
+                walked = walked.field(name);
+
+                mapping.get_mut(&key).expect("Field was just inserted")
+            }
+            PathSegment::Index(i) => {
+                let len = value.as_sequence().map(|s| s.len()).unwrap_or(0);
+
+                let item = value
+                    .get_mut(*i)
+                    .ok_or_else(|| format!("{} has no item {} (it has {})", walked, i, len))?;
+
+                walked = walked.index(*i);
+
+                item
+            }
+        };
+    }
+
+    *value = new_value;
+
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+enum OriginContent {
+    Document(Value),
+    Assignment(ConfigPath),
+}
+
+/// A layer as it was actually read, so that problems can be traced back to where their value
+/// came from
+#[derive(Clone, Debug)]
+pub(crate) struct LayerOrigin {
+    pub name: Option<String>,
+    lines: LineMap,
+    content: OriginContent,
+}
+
+impl LayerOrigin {
+    pub fn document(name: Option<String>, lines: LineMap, document: Value) -> Self {
+        Self {
+            name,
+            lines,
+            content: OriginContent::Document(document),
+        }
+    }
+
+    fn assignment(name: String, path: ConfigPath) -> Self {
+        Self {
+            name: Some(name),
+            lines: LineMap::default(),
+            content: OriginContent::Assignment(path),
+        }
+    }
+
+    pub fn defines(&self, path: &ConfigPath) -> bool {
+        match &self.content {
+            OriginContent::Document(document) => path.value_in(document).is_some(),
+            OriginContent::Assignment(assigned) => path.starts_with(assigned),
+        }
+    }
+
+    pub fn line_of(&self, path: &ConfigPath) -> Option<usize> {
+        self.lines.line_of(path)
+    }
+}
+
+impl ConfigPath {
+    // Accepts both `world.starting_villagers[1].x` and `world.starting_villagers.1.x`
+    fn parse(s: &str) -> Option<Self> {
+        let mut path = Self::root();
+
+        for part in s.split('.') {
+            let mut pieces = part.split('[');
+
+            let name = pieces.next()?;
+
+            path = match name.parse::<usize>() {
+                Ok(i) => path.index(i),
+                Err(_) if !name.is_empty() => path.field(name),
+                Err(_) => return None,
+            };
+
+            for piece in pieces {
+                let i = piece.strip_suffix(']')?.parse::<usize>().ok()?;
+                path = path.index(i);
+            }
+        }
+
+        Some(path)
+    }
+
+    fn starts_with(&self, other: &ConfigPath) -> bool {
+        self.segments.starts_with(&other.segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_support::scratch_dir;
+
+    #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct TestConfig {
+        from_project: u32,
+        from_user: u32,
+        from_env: u32,
+        from_args: u32,
+        untouched: u32,
+        items: Vec<TestItem>,
+    }
+
+    impl Validate for TestConfig {}
+
+    #[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct TestItem {
+        size: u32,
+    }
+
+    fn defaults() -> TestConfig {
+        TestConfig {
+            items: vec![TestItem { size: 1 }, TestItem { size: 2 }],
+            ..TestConfig::default()
+        }
+    }
+
+    fn sizes(config: &TestConfig) -> Vec<u32> {
+        config.items.iter().map(|item| item.size).collect()
+    }
+
+    // Where each problem came from and what it was about
+    fn problems(report: &ValidationReport) -> Vec<(Option<String>, String)> {
+        report
+            .errors()
+            .iter()
+            .map(|e| (e.source.clone(), e.path.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn each_layer_overrides_the_ones_before_it() {
+        let dir = scratch_dir("layer-precedence");
+        let project_path = dir.join("config.yaml");
+        let user_path = dir.join("config.user.yaml");
+
+        fs::write(
+            &project_path,
+            "from_project: 1\nfrom_user: 1\nfrom_env: 1\nfrom_args: 1\n",
+        )
+        .unwrap();
+        fs::write(&user_path, "from_user: 2\nfrom_env: 2\nfrom_args: 2\n").unwrap();
+
+        let env_vars = vec![
+            ("SWEET_TURNIPS_FROM_ENV".to_string(), "3".to_string()),
+            ("SWEET_TURNIPS_FROM_ARGS".to_string(), "3".to_string()),
+            ("OTHER_FROM_ARGS".to_string(), "9".to_string()),
+        ];
+
+        let config: TestConfig = ConfigLayers::new(&defaults())
+            .file(&project_path)
+            .file(&user_path)
+            .env_vars(env_vars)
+            .args(vec!["--seed", "1", "--set", "from_args=4"])
+            .resolve()
+            .unwrap();
+
+        assert_eq!(
+            config,
+            TestConfig {
+                from_project: 1,
+                from_user: 2,
+                from_env: 3,
+                from_args: 4,
+                ..defaults()
+            }
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn list_items_can_be_picked_out_either_way() {
+        let config: TestConfig = ConfigLayers::new(&defaults())
+            .set("items.0.size", "10")
+            .set("items[1].size", "20")
+            .resolve()
+            .unwrap();
+
+        assert_eq!(sizes(&config), vec![10, 20]);
+
+        let config: TestConfig = ConfigLayers::new(&defaults())
+            .env_vars(vec![(
+                "SWEET_TURNIPS_ITEMS__1__SIZE".to_string(),
+                "30".to_string(),
+            )])
+            .args(vec!["--set=items[0].size=40"])
+            .resolve()
+            .unwrap();
+
+        assert_eq!(sizes(&config), vec![40, 30]);
+    }
+
+    #[test]
+    fn sets_without_an_assignment_are_reported() {
+        let report = ConfigLayers::new(&defaults())
+            .args(vec!["--set", "from_args", "--set=untouched", "--set"])
+            .resolve::<TestConfig>()
+            .unwrap_err();
+
+        assert_eq!(
+            problems(&report),
+            vec![
+                (Some("--set from_args".to_string()), "(root)".to_string()),
+                (Some("--set untouched".to_string()), "(root)".to_string()),
+                (Some("--set".to_string()), "(root)".to_string()),
+            ]
+        );
+
+        // Nor is another option taken as the assignment
+        let report = ConfigLayers::new(&defaults())
+            .args(vec!["--set", "--portable"])
+            .resolve::<TestConfig>()
+            .unwrap_err();
+
+        assert_eq!(report.errors().len(), 1);
+    }
+
+    #[test]
+    fn bad_paths_are_reported() {
+        for path in &["", "items..size", "items[x].size", "items[1.size"] {
+            let report = ConfigLayers::new(&defaults())
+                .set(path, "3")
+                .resolve::<TestConfig>()
+                .unwrap_err();
+
+            assert_eq!(report.errors().len(), 1, "{:?}", path);
+            assert!(
+                report.errors()[0].message.contains("is not a config path"),
+                "{}",
+                report
+            );
+        }
+
+        let report = ConfigLayers::new(&defaults())
+            .set("items[5].size", "3")
+            .set("from_env.size", "3")
+            .resolve::<TestConfig>()
+            .unwrap_err();
+
+        assert_eq!(
+            problems(&report),
+            vec![
+                (
+                    Some("--set items[5].size=3".to_string()),
+                    "items[5].size".to_string()
+                ),
+                (
+                    Some("--set from_env.size=3".to_string()),
+                    "from_env.size".to_string()
+                ),
+            ]
+        );
+    }
+}
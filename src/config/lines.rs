@@ -0,0 +1,119 @@
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::Marker;
+
+use std::collections::HashMap;
+
+use crate::config::ConfigPath;
+
+/// Remembers which line of a YAML source each value starts on
+///
+/// Mapping values are placed on the line of their key.
+#[derive(Clone, Debug, Default)]
+pub struct LineMap {
+    lines: HashMap<ConfigPath, usize>,
+}
+
+impl LineMap {
+    /// Maps as much of the source as can be parsed, since we want line numbers for broken configs too
+    pub fn from_source(source: &str) -> Self {
+        let mut receiver = LineReceiver {
+            lines: HashMap::new(),
+            stack: vec![],
+        };
+
+        let mut parser = Parser::new(source.chars());
+        let _ = parser.load(&mut receiver, false);
+
+        Self {
+            lines: receiver.lines,
+        }
+    }
+
+    pub fn line_of(&self, path: &ConfigPath) -> Option<usize> {
+        self.lines.get(path).copied()
+    }
+
+    /// Records where a value starts, unless it's already known (e.g. from its key)
+    pub fn insert(&mut self, path: ConfigPath, line: usize) {
+        self.lines.entry(path).or_insert(line);
+    }
+}
+
+enum Container {
+    Mapping {
+        path: ConfigPath,
+        key: Option<String>,
+    },
+    Sequence {
+        path: ConfigPath,
+        next_index: usize,
+    },
+}
+
+struct LineReceiver {
+    lines: HashMap<ConfigPath, usize>,
+    stack: Vec<Container>,
+}
+
+impl LineReceiver {
+    // Works out the path of the node that's starting, or `None` if it's a mapping key
+    fn node_path(&mut self, event: &Event, mark: &Marker) -> Option<ConfigPath> {
+        let path = match self.stack.last_mut() {
+            None => ConfigPath::root(),
+            Some(Container::Mapping { path, key }) => match key.take() {
+                Some(k) => path.field(k),
+                None => {
+                    let name = match event {
+                        Event::Scalar(name, ..) => name.clone(),
+                        // Complex keys aren't used in configs, so they just hold the spot
+                        _ => String::new(),
+                    };
+
+                    self.lines.insert(path.field(&name), mark.line());
+                    *key = Some(name);
+
+                    return None;
+                }
+            },
+            Some(Container::Sequence { path, next_index }) => {
+                let item_path = path.index(*next_index);
+                *next_index += 1;
+
+                self.lines.insert(item_path.clone(), mark.line());
+
+                item_path
+            }
+        };
+
+        self.lines.entry(path.clone()).or_insert(mark.line());
+
+        Some(path)
+    }
+}
+
+impl MarkedEventReceiver for LineReceiver {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(..) | Event::Alias(..) => {
+                self.node_path(&event, &mark);
+            }
+            Event::MappingStart(..) => {
+                let path = self.node_path(&event, &mark).unwrap_or_default();
+
+                self.stack.push(Container::Mapping { path, key: None });
+            }
+            Event::SequenceStart(..) => {
+                let path = self.node_path(&event, &mark).unwrap_or_default();
+
+                self.stack.push(Container::Sequence {
+                    path,
+                    next_index: 0,
+                });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            }
+            _ => (),
+        }
+    }
+}
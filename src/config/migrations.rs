@@ -0,0 +1,290 @@
+use serde::Serialize;
+use serde_yaml::Value;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::ConfigFormat;
+
+/// The key every config file keeps its schema version under
+pub const VERSION_KEY: &str = "version";
+
+/// Configs written before versioning was added count as this version
+pub const FIRST_VERSION: u32 = 1;
+
+/// Upgrades a raw config from one schema version to the next
+///
+/// Works on the raw value before it's deserialized, whatever format the file was in, so it can
+/// rename, move or fill in fields freely.
+///
+/// Each file is migrated by itself, and override files like `config.user.yaml` usually only set
+/// a few values. So a migration should pass over whatever isn't in the file rather than fail,
+/// and only fill in fields on things the file does have, or they'd hide the project's values.
+pub type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// A game's config schema version, and how to get older configs up to it
+///
+/// Config files older than the current version are upgraded when they're loaded. The original
+/// is backed up beside the file (e.g. `config.yaml.v1.bak`) and the file is rewritten at the
+/// current version.
+///
+/// ## Examples
+///
+/// ```ignore
+/// fn migrations() -> Migrations {
+///     Migrations::new(2).migration(1, add_farm_yields)
+/// }
+///
+/// // Version 2 gave each farm a yield. Files that don't list farms are left alone.
+/// fn add_farm_yields(config: &mut Value) -> Result<(), String> {
+///     let farms = match config.get_mut("world").and_then(|w| w.get_mut("starting_farms")) {
+///         Some(farms) => farms.as_sequence_mut().ok_or("world.starting_farms is not a list")?,
+///         None => return Ok(()),
+///     };
+///
+///     for farm in farms {
+///         farm["yield"] = 1.into();
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Migrations {
+    current_version: u32,
+    steps: BTreeMap<u32, Migration>,
+}
+
+impl Migrations {
+    pub fn new(current_version: u32) -> Self {
+        Self {
+            current_version,
+            steps: BTreeMap::new(),
+        }
+    }
+
+    pub fn current_version(&self) -> u32 {
+        self.current_version
+    }
+
+    /// Registers the migration that takes a config from `from_version` to `from_version + 1`
+    pub fn migration(self, from_version: u32, migration: Migration) -> Self {
+        let mut steps = self.steps.clone();
+        steps.insert(from_version, migration);
+
+        Self { steps, ..self }
+    }
+
+    /// Runs every migration needed to bring a config from `version` up to the current version
+    pub fn migrate(&self, config: &mut Value, version: u32) -> Result<(), String> {
+        if version > self.current_version {
+            return Err(format!(
+                "config is version {}, but this game only understands up to version {}",
+                version, self.current_version
+            ));
+        }
+
+        for from_version in version..self.current_version {
+            let migration = self.steps.get(&from_version).ok_or_else(|| {
+                format!(
+                    "there's no migration from version {} to {}",
+                    from_version,
+                    from_version + 1
+                )
+            })?;
+
+            migration(config).map_err(|e| {
+                format!(
+                    "migrating from version {} to {} failed: {}",
+                    from_version,
+                    from_version + 1,
+                    e
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Takes the version out of a raw config and brings the rest up to date, returning the
+    /// version it started at
+    pub fn upgrade(&self, config: &mut Value) -> Result<u32, String> {
+        let version = take_version(config)?;
+
+        self.migrate(config, version)?;
+
+        Ok(version)
+    }
+
+    /// Pairs `config` with the current version, ready to be written out
+    pub fn versioned<'a, S>(&self, config: &'a S) -> Versioned<'a, S>
+    where
+        S: Serialize,
+    {
+        Versioned {
+            version: self.current_version,
+            config,
+        }
+    }
+
+    // Takes the version out of a file's config and brings the rest up to date. If it was out of
+    // date, the file is backed up and rewritten, and the new source is returned.
+    pub(crate) fn upgrade_file(
+        &self,
+        path: &Path,
+        format: ConfigFormat,
+        config: &mut Value,
+    ) -> Result<Option<String>, String> {
+        let version = self.upgrade(config)?;
+
+        if version == self.current_version {
+            return Ok(None);
+        }
+
+        let backup_path = backup_path(path, version);
+
+        fs::copy(path, &backup_path)
+            .map_err(|e| format!("could not back up to {}: {}", backup_path.display(), e))?;
+
+        let source = format.serialize(&self.versioned(config))?;
+
+        fs::write(path, &source).map_err(|e| format!("could not rewrite: {}", e))?;
+
+        Ok(Some(source))
+    }
+}
+
+/// A config with its version, which serializes as the config's own fields plus `version`
+#[derive(Serialize)]
+pub struct Versioned<'a, S> {
+    pub version: u32,
+    #[serde(flatten)]
+    pub config: &'a S,
+}
+
+impl fmt::Debug for Migrations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Migrations")
+            .field("current_version", &self.current_version)
+            .field("steps", &self.steps.keys().collect::<Vec<&u32>>())
+            .finish()
+    }
+}
+
+fn take_version(config: &mut Value) -> Result<u32, String> {
+    let mapping = match config {
+        Value::Mapping(mapping) => mapping,
+        _ => return Ok(FIRST_VERSION),
+    };
+
+    let version = match mapping.remove(&VERSION_KEY.into()) {
+        Some(version) => version,
+        None => return Ok(FIRST_VERSION),
+    };
+
+    match version.as_u64() {
+        Some(v) if v >= u64::from(FIRST_VERSION) && v <= u64::from(u32::MAX) => Ok(v as u32),
+        _ => Err(format!(
+            "{} should be a whole number from {} up, not {:?}",
+            VERSION_KEY, FIRST_VERSION, version
+        )),
+    }
+}
+
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".v{}.bak", version));
+
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde::Deserialize;
+
+    use crate::config::{ConfigLayers, Validate};
+    use crate::test_support::scratch_dir;
+
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    struct TestConfig {
+        world: TestWorld,
+    }
+
+    impl Validate for TestConfig {}
+
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    struct TestWorld {
+        starting_farms: Vec<TestFarm>,
+        tick_speed: u32,
+    }
+
+    #[derive(Debug, Default, Deserialize, Serialize)]
+    struct TestFarm {
+        x: u16,
+        #[serde(rename = "yield")]
+        farm_yield: u32,
+    }
+
+    fn add_farm_yields(config: &mut Value) -> Result<(), String> {
+        let farms = match config
+            .get_mut("world")
+            .and_then(|w| w.get_mut("starting_farms"))
+        {
+            Some(farms) => farms
+                .as_sequence_mut()
+                .ok_or("world.starting_farms is not a list")?,
+            None => return Ok(()),
+        };
+
+        for farm in farms {
+            farm["yield"] = 1.into();
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn partial_override_files_are_migrated_alongside_full_ones() {
+        let dir = scratch_dir("partial-migration");
+        let project_path = dir.join("config.yaml");
+        let user_path = dir.join("config.user.yaml");
+
+        fs::write(
+            &project_path,
+            "world:\n  starting_farms:\n    - x: 1\n    - x: 2\n  tick_speed: 40\n",
+        )
+        .unwrap();
+        fs::write(&user_path, "world:\n  tick_speed: 20\n").unwrap();
+
+        let migrations = Migrations::new(2).migration(1, add_farm_yields);
+
+        let config: TestConfig = ConfigLayers::new(&TestConfig::default())
+            .migrations(migrations)
+            .file(&project_path)
+            .file(&user_path)
+            .resolve()
+            .unwrap();
+
+        assert_eq!(config.world.tick_speed, 20);
+        assert_eq!(config.world.starting_farms.len(), 2);
+        assert!(config
+            .world
+            .starting_farms
+            .iter()
+            .all(|f| f.farm_yield == 1));
+
+        // The override file is upgraded without picking up fields it didn't set
+        let user_source = fs::read_to_string(&user_path).unwrap();
+        let user_config: Value = serde_yaml::from_str(&user_source).unwrap();
+
+        assert_eq!(user_config["version"], Value::from(2));
+        assert_eq!(user_config["world"]["starting_farms"], Value::Null);
+        assert!(dir.join("config.user.yaml.v1.bak").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,203 @@
+use serde::Deserialize;
+
+use std::fs;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::config::{ConfigLayers, Validate, ValidationReport};
+
+// Checking the file every frame would be wasteful, and edits don't need to land any faster
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Notices when any of a config's files change on disk and resolves it again
+///
+/// Call `poll()` once per update. It only returns something when a file has changed since the
+/// last poll: the freshly resolved config, or the report of everything wrong with it.
+///
+/// Files older than the current version are upgraded in memory, but never backed up or rewritten
+/// while the game's running. That's left to the next time the config is resolved at startup.
+///
+/// ## Examples
+///
+/// ```ignore
+/// match self.config_watcher.poll() {
+///     Some(Ok(game_config)) => self.world = game_config.world.into(),
+///     Some(Err(report)) => self.sprite_context.show_overlay(report.to_string()),
+///     None => (),
+/// }
+/// ```
+pub struct ConfigWatcher<D> {
+    config_layers: ConfigLayers,
+    last_seen: Vec<Option<(SystemTime, u64)>>,
+    last_polled: Instant,
+    poll_interval: Duration,
+    config_type: PhantomData<fn() -> D>,
+}
+
+impl<D> ConfigWatcher<D>
+where
+    for<'de> D: Deserialize<'de> + Validate,
+{
+    /// Starts watching the files in `config_layers`, treating their current contents as already
+    /// loaded
+    pub fn new(config_layers: ConfigLayers) -> Self {
+        let last_seen = signatures(&config_layers);
+
+        Self {
+            config_layers,
+            last_seen,
+            last_polled: Instant::now(),
+            poll_interval: POLL_INTERVAL,
+            config_type: PhantomData,
+        }
+    }
+
+    pub fn config_layers(&self) -> &ConfigLayers {
+        &self.config_layers
+    }
+
+    pub fn poll(&mut self) -> Option<Result<D, ValidationReport>> {
+        if self.last_polled.elapsed() < self.poll_interval {
+            return None;
+        }
+
+        self.last_polled = Instant::now();
+
+        let last_seen = signatures(&self.config_layers);
+
+        if last_seen == self.last_seen {
+            return None;
+        }
+
+        self.last_seen = last_seen;
+
+        Some(self.config_layers.reload())
+    }
+}
+
+fn signatures(config_layers: &ConfigLayers) -> Vec<Option<(SystemTime, u64)>> {
+    config_layers
+        .files()
+        .into_iter()
+        .map(file_signature)
+        .collect()
+}
+
+// Modification times can be coarse, so the length helps catch quick successive saves
+fn file_signature(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde::Serialize;
+    use serde_yaml::Value;
+
+    use std::path::PathBuf;
+
+    use crate::config::Migrations;
+    use crate::test_support::scratch_dir;
+
+    #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct TestConfig {
+        speed: u32,
+    }
+
+    impl Validate for TestConfig {}
+
+    // Watches a config made of just `config.yaml` in a fresh directory, polling every time
+    fn watch(
+        name: &str,
+        source: &str,
+        migrations: Migrations,
+    ) -> (ConfigWatcher<TestConfig>, PathBuf) {
+        let dir = scratch_dir(name);
+        let path = dir.join("config.yaml");
+        fs::write(&path, source).unwrap();
+
+        let config_layers = ConfigLayers::new(&TestConfig::default())
+            .migrations(migrations)
+            .file(&path);
+
+        let mut watcher = ConfigWatcher::new(config_layers);
+        watcher.poll_interval = Duration::from_millis(0);
+
+        (watcher, path)
+    }
+
+    #[test]
+    fn an_edit_is_reloaded_once() {
+        let (mut watcher, path) = watch("watch-edit", "version: 1\nspeed: 1\n", Migrations::new(1));
+
+        assert!(watcher.poll().is_none());
+
+        // A different length, as modification times can be too coarse to tell apart
+        fs::write(&path, "version: 1\nspeed: 20\n").unwrap();
+
+        assert_eq!(watcher.poll().unwrap().unwrap(), TestConfig { speed: 20 });
+        assert!(watcher.poll().is_none());
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn a_bad_edit_is_reported_until_its_fixed() {
+        let (mut watcher, path) = watch(
+            "watch-bad-edit",
+            "version: 1\nspeed: 1\n",
+            Migrations::new(1),
+        );
+
+        fs::write(&path, "version: 1\nspeed: fast\n").unwrap();
+
+        let report = watcher.poll().unwrap().unwrap_err();
+        assert_eq!(report.errors()[0].path.to_string(), "speed");
+        assert_eq!(report.errors()[0].line, Some(2));
+
+        // The game carries on with what it had, and hears nothing more until the file changes
+        assert!(watcher.poll().is_none());
+
+        fs::write(&path, "version: 1\nspeed: 300\n").unwrap();
+
+        assert_eq!(watcher.poll().unwrap().unwrap(), TestConfig { speed: 300 });
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn old_files_are_upgraded_without_being_rewritten() {
+        // Version 2 counted speed in tenths
+        fn speed_in_tenths(config: &mut Value) -> Result<(), String> {
+            if let Some(speed) = config.get("speed").and_then(Value::as_u64) {
+                config["speed"] = (speed * 10).into();
+            }
+
+            Ok(())
+        }
+
+        let migrations = Migrations::new(2).migration(1, speed_in_tenths);
+        let (mut watcher, path) = watch("watch-upgrade", "version: 1\nspeed: 1\n", migrations);
+
+        let edited = "version: 1\nspeed: 30\n";
+        fs::write(&path, edited).unwrap();
+
+        assert_eq!(watcher.poll().unwrap().unwrap(), TestConfig { speed: 300 });
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), edited);
+
+        let backups: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name != "config.yaml")
+            .collect();
+
+        assert_eq!(backups, Vec::<std::ffi::OsString>::new());
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
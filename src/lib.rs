@@ -1,116 +1,220 @@
+pub mod actions;
+mod app_config;
+mod app_dirs;
+mod cli;
+pub mod config;
 pub mod midi;
+pub mod saves;
 pub mod sprites;
 
+#[cfg(test)]
+mod test_support;
+
 pub use ggez::conf;
 pub use ggez::event;
 pub use ggez::event::EventsLoop;
 pub use ggez::{Context, ContextBuilder, GameResult};
 
-use ggez::conf::WindowMode;
+pub use app_config::{AppConfig, AppConfigError, UnfinalizedAppConfig, DEFAULT_TICK_RATE};
+pub use app_dirs::{AppDirs, StorageMode};
+pub use cli::{CliError, CliOptions};
+
+use ggez::conf::{WindowMode, WindowSetup};
 
 use serde::{Deserialize, Serialize};
-use serde_yaml;
 
+use std::env;
 use std::fs;
-use std::fs::File;
 use std::path;
-use std::path::PathBuf;
-
-use sprites::{SPRITE_SCALE, SPRITE_SIZE};
-
-// TODO:
-// * Add an UnfinalizedAppConfig struct and have AppConfig::new return that
-// * Move config methods to UnfinalizedAppConfig
-// * Add a finalize() method that returns a vetted AppConfig
-pub struct AppConfig {
-    game_name: String,
-    author_name: String,
-    grid_dimensions: (u8, u8),
-}
+use std::path::{Path, PathBuf};
 
-impl AppConfig {
-    pub fn new(grid_dimensions: (u8, u8)) -> Self {
-        Self {
-            game_name: "A Sweet Turnips Game".to_string(),
-            author_name: "Your Name Goes Here".to_string(),
-            grid_dimensions,
-        }
+use config::{ConfigFormat, ConfigLayers, Migrations, Validate};
+use sprites::{GridUnit, SPRITE_SCALE, SPRITE_SIZE};
+
+pub fn build_context_and_event_loop(app_config: &AppConfig) -> GameResult<(Context, EventsLoop)> {
+    let (width, height) = app_config.grid_dimensions();
+
+    let mut cb = ContextBuilder::new(app_config.game_name(), app_config.author_name())
+        .window_setup(WindowSetup::default().vsync(app_config.vsync()))
+        .window_mode(window_mode(width, height, app_config.window_scale()));
+
+    for resource_path in app_config.resource_paths() {
+        cb = cb.add_resource_path(resource_path);
     }
 
-    pub fn game_name<S>(self, game_name: S) -> Self
-    where
-        S: AsRef<str>,
-    {
-        Self {
-            game_name: game_name.as_ref().to_string(),
-            ..self
+    // Mounted after the loose directories so that local edits win over anything packed in a zip
+    for resource_path in app_config.resource_paths() {
+        for archive_path in resource_archives(resource_path)? {
+            cb = cb.add_zipfile_bytes(fs::read(archive_path)?);
         }
     }
 
-    pub fn author_name<S>(self, author_name: S) -> Self
-    where
-        S: AsRef<str>,
-    {
-        Self {
-            author_name: author_name.as_ref().to_string(),
-            ..self
-        }
+    let cb = with_embedded_resources(cb);
+
+    Ok(cb.build()?)
+}
+
+// The default sprite set, compiled in so that games run from any directory.
+// Mounted last so that anything on disk overrides it. (Text uses ggez's built-in default font,
+// which is already compiled into ggez itself.)
+#[cfg(feature = "embedded-resources")]
+const EMBEDDED_RESOURCES: &[u8] = include_bytes!("../resources/BITTER_JAM_01_TILES.zip");
+
+#[cfg(feature = "embedded-resources")]
+fn with_embedded_resources(cb: ContextBuilder) -> ContextBuilder {
+    cb.add_zipfile_bytes(EMBEDDED_RESOURCES)
+}
+
+#[cfg(not(feature = "embedded-resources"))]
+fn with_embedded_resources(cb: ContextBuilder) -> ContextBuilder {
+    cb
+}
+
+/// Lists the zip archives in `dir` that get mounted as asset sources, in alphabetical order
+///
+/// Each archive's root is treated like the resource directory itself, e.g. a tile pack with
+/// `separate/1.png` at its root provides `/separate/1.png`.
+pub fn resource_archives(dir: &Path) -> GameResult<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(vec![]);
     }
 
-    pub fn grid_dimensions(self, grid_dimensions: (u8, u8)) -> Self {
-        Self {
-            grid_dimensions,
-            ..self
+    let mut archives: Vec<PathBuf> = vec![];
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        let is_zip = match path.extension() {
+            Some(ext) => ext.eq_ignore_ascii_case("zip"),
+            None => false,
+        };
+
+        if is_zip && path.is_file() {
+            archives.push(path);
         }
     }
-}
-
-pub fn build_context_and_event_loop(app_config: &AppConfig) -> GameResult<(Context, EventsLoop)> {
-    let (width, height) = app_config.grid_dimensions;
 
-    let cb = ContextBuilder::new(&app_config.game_name, &app_config.author_name)
-        .add_resource_path(resource_dir())
-        .window_mode(default_window_mode(width, height));
+    archives.sort();
 
-    Ok(cb.build()?)
+    Ok(archives)
 }
 
+/// Where the project config is shipped, in `resources/<game_name>/`
+///
+/// A config path picked with `AppConfig::config_path` (or `--config`) is used as is. The
+/// directories for it and for the user config (see `AppConfig::user_config_path`) are created if
+/// need be.
 pub fn prep_config_path(app_config: &AppConfig) -> GameResult<PathBuf> {
-    let config_dir = resource_dir().join(&app_config.game_name);
-    let config_path = config_dir.join("config.yaml");
+    let config_path = match app_config.config_path() {
+        Some(config_path) => config_path.to_path_buf(),
+        None => resource_dir()
+            .join(app_config.game_name())
+            .join(format!("config.{}", app_config.config_format().extension())),
+    };
+
+    // A shipped config on a read-only install is fine, as long as it's already there
+    if !config_path.exists() {
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
 
-    fs::create_dir_all(config_dir)?;
+    if let Some(parent) = app_config.user_config_path().parent() {
+        fs::create_dir_all(parent)?;
+    }
 
     Ok(config_path)
 }
 
-fn resource_dir() -> PathBuf {
+pub(crate) fn resource_dir() -> PathBuf {
     path::PathBuf::from("./resources")
 }
 
-pub fn default_window_mode(grid_width: u8, grid_height: u8) -> WindowMode {
+pub fn default_window_mode(grid_width: GridUnit, grid_height: GridUnit) -> WindowMode {
+    window_mode(grid_width, grid_height, SPRITE_SCALE)
+}
+
+pub fn window_mode(grid_width: GridUnit, grid_height: GridUnit, window_scale: f32) -> WindowMode {
     conf::WindowMode::default().dimensions(
-        grid_width as f32 * SPRITE_SIZE * SPRITE_SCALE,
-        grid_height as f32 * SPRITE_SIZE * SPRITE_SCALE,
+        grid_width as f32 * SPRITE_SIZE * window_scale,
+        grid_height as f32 * SPRITE_SIZE * window_scale,
     )
 }
 
-pub fn default_game_config_setup<'a, S, D>(config_path: PathBuf, example_game_config: S) -> D
+/// The name a player's own overrides for a config go by, in the same format, e.g.
+/// `config.user.yaml` for `config.yaml`
+///
+/// This is next to the config itself. `AppConfig::user_config_path` puts it in the per-user
+/// config directory instead, unless the config was picked with `--config`.
+pub fn user_config_path(config_path: &Path) -> PathBuf {
+    let stem = config_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("config");
+
+    let extension = config_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_else(|| ConfigFormat::default().extension());
+
+    config_path.with_file_name(format!("{}.user.{}", stem, extension))
+}
+
+/// The usual stack of config layers for a game, lowest priority first:
+///
+/// * `example_game_config`, compiled in
+/// * the project config at `config_path` (see `prep_config_path`)
+/// * the user config at `user_config_path` (see `AppConfig::user_config_path`)
+/// * `SWEET_TURNIPS_*` environment variables
+/// * `--set path=value` command line arguments
+///
+/// Both files are upgraded to the current version with `migrations` as they're read.
+pub fn default_config_layers<S>(
+    config_path: &Path,
+    user_config_path: &Path,
+    example_game_config: &S,
+    migrations: &Migrations,
+) -> ConfigLayers
+where
+    S: Serialize,
+{
+    ConfigLayers::new(example_game_config)
+        .migrations(migrations.clone())
+        .file(config_path)
+        .file(user_config_path)
+        .env()
+        .args(env::args().skip(1))
+}
+
+pub fn default_game_config_setup<'a, S, D>(
+    config_path: PathBuf,
+    user_config_path: &Path,
+    example_game_config: S,
+    migrations: &Migrations,
+) -> D
 where
     S: Serialize,
-    for<'de> D: Deserialize<'de> + 'a,
+    for<'de> D: Deserialize<'de> + Validate + 'a,
 {
     if !config_path.exists() {
-        let new_file =
-            &File::create(config_path.clone()).expect("Could not create new config file");
+        let format = ConfigFormat::from_path(&config_path).unwrap_or_default();
 
-        serde_yaml::to_writer(new_file, &example_game_config)
-            .expect("Could not write to new config file");
-    }
+        let example_source = format
+            .serialize(&migrations.versioned(&example_game_config))
+            .unwrap_or_else(|e| panic!("Could not write example config as {}: {}", format, e));
 
-    let config_string = fs::read_to_string(config_path).expect("Could not read config file");
+        fs::write(&config_path, example_source).expect("Could not write to new config file");
+    }
 
-    let config: D = serde_yaml::from_str(&config_string).expect("Could not parse config file");
+    let config_layers = default_config_layers(
+        &config_path,
+        user_config_path,
+        &example_game_config,
+        migrations,
+    );
 
-    config
+    match config_layers.resolve() {
+        Ok(config) => config,
+        Err(report) => panic!("Could not load {}\n{}", config_path.display(), report),
+    }
 }
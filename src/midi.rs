@@ -1,55 +1,276 @@
+mod bindings;
+mod clock;
+mod message;
+mod output;
+mod ports;
+mod profiles;
+mod recording;
+mod smf;
+mod source;
+mod watcher;
+
 use midir;
+use midir::MidiInputConnection;
 pub use midir::{Ignore, MidiInput};
+use serde::{Deserialize, Serialize};
 
-use std::io::stdin;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
 use std::sync::mpsc;
-use std::thread;
-
-pub type MidiReceiver = mpsc::Receiver<(u8, u8)>;
-type MidiSender = mpsc::Sender<(u8, u8)>;
-
-pub fn connect_to_midi(tx: MidiSender) {
-    thread::spawn(move || {
-        let mut midi_in =
-            MidiInput::new("midir reading input").expect("Unable to read MIDI inputs");
-        midi_in.ignore(midir::Ignore::None);
-
-        let in_ports = midi_in.ports();
-        let in_port = match in_ports.len() {
-            0 => None,
-            1 => Some(&in_ports[0]),
-            _ => Some(&in_ports[1]),
-        };
-
-        if in_port.is_none() {
-            return;
+
+use crate::config::{ConfigPath, Validate, ValidationReport};
+
+pub use bindings::{MidiBinding, MidiBindings, MidiControl, MidiLearn};
+pub use clock::{MidiClock, PULSES_PER_QUARTER_NOTE};
+pub use message::{MidiMessage, PITCH_BEND_CENTER};
+pub use output::{connect_to_midi_output, MidiOutput, MidiOutputConnection, RecordingOutput};
+pub use ports::{input_port_names, output_port_names, select_port};
+pub use profiles::{profile_dirs, MidiProfile};
+pub use recording::{play_midi_file, MidiPlayback, MidiRecorder};
+pub use smf::{read_midi_file, write_midi_file};
+pub use source::{MidiSource, VirtualMidiSource};
+pub use watcher::{watch_midi, MidiDeviceEvent, MidiWatcher};
+
+// Messages travel with the time they arrived, in microseconds (see `MidiSource::poll_stamped`)
+pub type MidiReceiver = mpsc::Receiver<(u64, MidiMessage)>;
+pub type MidiSender = mpsc::Sender<(u64, MidiMessage)>;
+
+/// The MIDI settings a game config can embed, for a game with actions `A`
+///
+/// ## Examples
+///
+/// ```yaml
+/// midi:
+///   port: nanoKONTROL2
+///   output_port: ~
+///   clock_pulses_per_tick: 6
+///   bindings:
+///     move_left: rewind
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiConfig<A: Ord> {
+    /// The input to listen to, by its name or part of it. Left out, one is picked automatically.
+    pub port: Option<String>,
+    /// The output to send feedback to, such as button lights. Left out, the output with the same
+    /// name as the input is used, if there is one.
+    pub output_port: Option<String>,
+    /// How many clock pulses make a tick when following a sequencer's MIDI clock. There are 24
+    /// to a quarter note, so 6 ticks every 16th note.
+    pub clock_pulses_per_tick: u32,
+    pub bindings: MidiBindings<A>,
+}
+
+impl<A: Ord + Copy> Default for MidiConfig<A> {
+    fn default() -> Self {
+        Self {
+            port: None,
+            output_port: None,
+            clock_pulses_per_tick: PULSES_PER_QUARTER_NOTE / 4,
+            bindings: MidiBindings::new(),
+        }
+    }
+}
+
+impl<A: Ord + Copy + Serialize> Validate for MidiConfig<A> {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        if self.clock_pulses_per_tick == 0 {
+            report.error(
+                path.field("clock_pulses_per_tick"),
+                "a tick needs at least one clock pulse",
+            );
+        }
+
+        self.bindings.validate(&path.field("bindings"), report);
+    }
+}
+
+/// Listens to a MIDI input in the background, as a `MidiSource`
+///
+/// The input is chosen by `select_port` from `port_name`, which fails if nothing matches rather
+/// than quietly listening to some other device. Messages keep arriving until the returned
+/// connection is closed or dropped.
+pub fn connect_to_midi(port_name: Option<&str>) -> Result<MidiConnection, MidiError> {
+    let (tx, rx) = mpsc::channel();
+
+    let (connection, port_name) = open_input(port_name, select_port, tx)?;
+
+    Ok(MidiConnection {
+        connection: Some(connection),
+        port_name,
+        rx,
+    })
+}
+
+// Connects to the input `select` picks, sending each message it reads to `tx`
+pub(crate) fn open_input(
+    port_name: Option<&str>,
+    select: fn(&[String], Option<&str>) -> Result<usize, MidiError>,
+    tx: MidiSender,
+) -> Result<(MidiInputConnection<()>, String), MidiError> {
+    let mut midi_in =
+        MidiInput::new("sweet-turnips input").map_err(|e| MidiError::Unavailable(e.to_string()))?;
+    midi_in.ignore(midir::Ignore::None);
+
+    let (ports, names): (Vec<_>, Vec<_>) = ports::named_ports(&midi_in).into_iter().unzip();
+
+    let selected = select(&names, port_name)?;
+    let in_port_name = names[selected].clone();
+
+    let connection = midi_in
+        .connect(
+            &ports[selected],
+            "sweet-turnips-input",
+            move |stamp, bytes, _| {
+                // Malformed messages are dropped, as is everything once the game stops listening
+                if let Some(message) = MidiMessage::parse(bytes) {
+                    let _ = tx.send((stamp, message));
+                }
+            },
+            (),
+        )
+        .map_err(|e| MidiError::ConnectFailed {
+            port: in_port_name.clone(),
+            reason: e.to_string(),
+        })?;
+
+    Ok((connection, in_port_name))
+}
+
+/// An open MIDI input, which stops listening when closed or dropped
+///
+/// Keep it alive for as long as the game wants messages, e.g. as a field of its `MainState`.
+pub struct MidiConnection {
+    // Only taken when closing
+    connection: Option<MidiInputConnection<()>>,
+    port_name: String,
+    rx: MidiReceiver,
+}
+
+impl MidiConnection {
+    /// The name of the input being listened to
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    pub fn close(mut self) {
+        self.shut_down();
+    }
+
+    fn shut_down(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            connection.close();
         }
+    }
+}
+
+impl MidiSource for MidiConnection {
+    fn poll_stamped(&mut self) -> Vec<(u64, MidiMessage)> {
+        self.rx.try_iter().collect()
+    }
+
+    fn port_name(&self) -> Option<&str> {
+        Some(&self.port_name)
+    }
+}
+
+impl Drop for MidiConnection {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+impl fmt::Debug for MidiConnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MidiConnection")
+            .field("port_name", &self.port_name)
+            .field("open", &self.connection.is_some())
+            .finish()
+    }
+}
+
+/// Why a MIDI port couldn't be listed, listened to or sent to, or a MIDI file read or written
+#[derive(Clone, Debug, PartialEq)]
+pub enum MidiError {
+    /// The system's MIDI backend couldn't be started
+    Unavailable(String),
+    NoPorts,
+    PortNotFound {
+        wanted: String,
+        available: Vec<String>,
+    },
+    /// More than one port's name contains the one asked for
+    AmbiguousPort {
+        wanted: String,
+        matches: Vec<String>,
+    },
+    ConnectFailed {
+        port: String,
+        reason: String,
+    },
+    SendFailed(String),
+    /// A channel above 15, or a note, controller or value above 127
+    OutOfRange {
+        field: &'static str,
+        value: u8,
+        max: u8,
+    },
+    UnreadableProfile(PathBuf, String),
+    UnreadableMidiFile(PathBuf, String),
+    UnwritableMidiFile(PathBuf, String),
+}
+
+impl fmt::Display for MidiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MidiError::Unavailable(reason) => write!(f, "MIDI is unavailable: {}", reason),
+            MidiError::NoPorts => write!(f, "There are no MIDI ports"),
+            MidiError::PortNotFound { wanted, available } => write!(
+                f,
+                "No MIDI port matches {:?}; the ports are {}",
+                wanted,
+                quoted_list(available)
+            ),
+            MidiError::AmbiguousPort { wanted, matches } => write!(
+                f,
+                "More than one MIDI port matches {:?}: {}",
+                wanted,
+                quoted_list(matches)
+            ),
+            MidiError::ConnectFailed { port, reason } => {
+                write!(f, "Could not connect to MIDI port {:?}: {}", port, reason)
+            }
+            MidiError::SendFailed(reason) => write!(f, "Could not send MIDI: {}", reason),
+            MidiError::OutOfRange { field, value, max } => {
+                write!(f, "MIDI {} {} is out of range (0 to {})", field, value, max)
+            }
+            MidiError::UnreadableProfile(path, reason) => write!(
+                f,
+                "Could not read MIDI profile {}: {}",
+                path.display(),
+                reason
+            ),
+            MidiError::UnreadableMidiFile(path, reason) => {
+                write!(f, "Could not read MIDI file {}: {}", path.display(), reason)
+            }
+            MidiError::UnwritableMidiFile(path, reason) => {
+                write!(
+                    f,
+                    "Could not write MIDI file {}: {}",
+                    path.display(),
+                    reason
+                )
+            }
+        }
+    }
+}
+
+impl Error for MidiError {}
 
-        let in_port = in_port.expect("Unable to select a MIDI input");
-
-        let in_port_name = midi_in
-            .port_name(in_port)
-            .expect("Unable to fetch fetch MIDI port name");
-
-        println!("\nFound {} MIDI connections", in_ports.len());
-        println!("\nOpening connection to {}", in_port_name);
-
-        let _connection = midi_in
-            .connect(
-                in_port,
-                "midir-read-input",
-                move |_, message, _| {
-                    let (key, value) = (message[1], message[2]);
-                    tx.send((key, value)).unwrap();
-                },
-                (),
-            )
-            .expect("Unable to open connection to MIDI input");
-
-        // TODO: Find a better way to keep this thread alive
-        let mut input = String::new();
-        stdin()
-            .read_line(&mut input)
-            .expect("Unable to read input from STDIN");
-    });
+fn quoted_list(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("{:?}", name))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
@@ -0,0 +1,561 @@
+use serde::de;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_yaml::Value;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::actions::action_name;
+use crate::config::{save_value, ConfigPath, Migrations, Validate, ValidationReport};
+use crate::midi::{MidiMessage, MidiProfile};
+
+const CHANNELS: u8 = 16;
+const DATA_VALUES: u8 = 128;
+
+/// A button, key, knob or fader on a MIDI controller
+///
+/// In a YAML config this reads as `{ channel: 0, controller: 16 }` for a knob or fader, or
+/// `{ channel: 0, note: 36 }` for a key or pad.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(untagged)]
+pub enum MidiControl {
+    Note { channel: u8, note: u8 },
+    Controller { channel: u8, controller: u8 },
+}
+
+// Controls are read through this so that one with neither number, or both, gets a clearer error
+// than an untagged enum gives
+#[derive(Deserialize)]
+struct ControlFields {
+    channel: u8,
+    note: Option<u8>,
+    controller: Option<u8>,
+}
+
+impl<'de> Deserialize<'de> for MidiControl {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let ControlFields {
+            channel,
+            note,
+            controller,
+        } = ControlFields::deserialize(deserializer)?;
+
+        match (note, controller) {
+            (Some(note), None) => Ok(MidiControl::Note { channel, note }),
+            (None, Some(controller)) => Ok(MidiControl::Controller {
+                channel,
+                controller,
+            }),
+            _ => Err(de::Error::custom(
+                "a MIDI control needs either a note or a controller number",
+            )),
+        }
+    }
+}
+
+impl MidiControl {
+    /// The control a note or control change came from
+    pub fn of(message: &MidiMessage) -> Option<Self> {
+        let control = match *message {
+            MidiMessage::NoteOn { channel, note, .. }
+            | MidiMessage::NoteOff { channel, note, .. } => MidiControl::Note { channel, note },
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                ..
+            } => MidiControl::Controller {
+                channel,
+                controller,
+            },
+            _ => return None,
+        };
+
+        Some(control)
+    }
+
+    /// The value `message` sets this control to, if it came from this control
+    ///
+    /// For notes that's the velocity, or 0 once released.
+    pub fn value_in(self, message: &MidiMessage) -> Option<u8> {
+        if MidiControl::of(message) != Some(self) {
+            return None;
+        }
+
+        match *message {
+            MidiMessage::NoteOn { velocity, .. } => Some(velocity),
+            MidiMessage::ControlChange { value, .. } => Some(value),
+            _ => Some(0),
+        }
+    }
+
+    pub(crate) fn validate(self, path: &ConfigPath, report: &mut ValidationReport) {
+        let (channel, number, number_field) = match self {
+            MidiControl::Note { channel, note } => (channel, note, "note"),
+            MidiControl::Controller {
+                channel,
+                controller,
+            } => (channel, controller, "controller"),
+        };
+
+        if channel >= CHANNELS {
+            let message = format!(
+                "MIDI channels run from 0 to {}, not {}",
+                CHANNELS - 1,
+                channel
+            );
+            report.error(path.field("channel"), message);
+        }
+
+        if number >= DATA_VALUES {
+            let message = format!(
+                "MIDI {}s run from 0 to {}, not {}",
+                number_field,
+                DATA_VALUES - 1,
+                number
+            );
+            report.error(path.field(number_field), message);
+        }
+    }
+}
+
+impl fmt::Display for MidiControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MidiControl::Note { channel, note } => {
+                write!(f, "note {} on channel {}", note, channel)
+            }
+            MidiControl::Controller {
+                channel,
+                controller,
+            } => write!(f, "controller {} on channel {}", controller, channel),
+        }
+    }
+}
+
+/// A control as a binding refers to it, either by the name a `MidiProfile` gives it or by number
+///
+/// In a YAML config this reads as `rewind`, or as `{ channel: 0, controller: 43 }`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(untagged)]
+pub enum MidiBinding {
+    Named(String),
+    Control(MidiControl),
+}
+
+impl MidiBinding {
+    /// The control this refers to, looking names up in `profile`
+    pub fn resolve(&self, profile: Option<&MidiProfile>) -> Option<MidiControl> {
+        match self {
+            MidiBinding::Named(name) => profile?.control(name),
+            MidiBinding::Control(control) => Some(*control),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MidiBinding {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Value::deserialize(deserializer)? {
+            Value::String(name) => Ok(MidiBinding::Named(name)),
+            value @ Value::Mapping(_) => MidiControl::deserialize(value)
+                .map(MidiBinding::Control)
+                .map_err(de::Error::custom),
+            _ => Err(de::Error::custom(
+                "expected a control name like rewind, or a control like \
+                 { channel: 0, controller: 43 }",
+            )),
+        }
+    }
+}
+
+impl From<MidiControl> for MidiBinding {
+    fn from(control: MidiControl) -> Self {
+        MidiBinding::Control(control)
+    }
+}
+
+impl From<&str> for MidiBinding {
+    fn from(name: &str) -> Self {
+        MidiBinding::Named(name.to_string())
+    }
+}
+
+impl fmt::Display for MidiBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MidiBinding::Named(name) => write!(f, "{}", name),
+            MidiBinding::Control(control) => write!(f, "{}", control),
+        }
+    }
+}
+
+/// Which MIDI controls drive which of a game's actions
+///
+/// Works like `ActionMap` does for keys, with one control per action. Actions can be left
+/// unbound with `~`, which is how a config layer takes away a binding from the ones below it.
+///
+/// Controls named in bindings are looked up in the `MidiProfile` for the device being used, so
+/// lookups take that profile if there is one.
+///
+/// ## Examples
+///
+/// ```ignore
+/// let bindings = MidiBindings::new()
+///     .bind(MidiAction::MoveLeft, "rewind")
+///     .bind(MidiAction::TickSpeed, MidiControl::Controller { channel: 0, controller: 16 });
+/// ```
+///
+/// In a YAML config this reads as:
+///
+/// ```yaml
+/// bindings:
+///   move_left: rewind
+///   tick_speed: { channel: 0, controller: 16 }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct MidiBindings<A: Ord> {
+    bindings: BTreeMap<A, MidiBinding>,
+}
+
+impl<A: Ord + Copy> MidiBindings<A> {
+    pub fn new() -> Self {
+        Self {
+            bindings: BTreeMap::new(),
+        }
+    }
+
+    /// Makes a control, named or numbered, drive `action`
+    pub fn bind<B>(self, action: A, binding: B) -> Self
+    where
+        B: Into<MidiBinding>,
+    {
+        let mut bindings = self.bindings.clone();
+        bindings.insert(action, binding.into());
+
+        Self { bindings }
+    }
+
+    /// Binds `control` to `action` in place, taking it away from whichever action had it before
+    ///
+    /// The control is bound by name if `profile` names it. Returns the action that lost it,
+    /// whose binding has now gone.
+    pub fn rebind(
+        &mut self,
+        action: A,
+        control: MidiControl,
+        profile: Option<&MidiProfile>,
+    ) -> Option<A> {
+        let displaced = self
+            .bindings
+            .iter()
+            .find(|(other, binding)| **other != action && binding.resolve(profile) == Some(control))
+            .map(|(other, _)| *other);
+
+        if let Some(displaced) = displaced {
+            self.bindings.remove(&displaced);
+        }
+
+        let binding = match profile.and_then(|profile| profile.name_of(control)) {
+            Some(name) => MidiBinding::from(name),
+            None => MidiBinding::from(control),
+        };
+
+        self.bindings.insert(action, binding);
+
+        displaced
+    }
+
+    pub fn binding_for(&self, action: A) -> Option<&MidiBinding> {
+        self.bindings.get(&action)
+    }
+
+    pub fn control_for(&self, action: A, profile: Option<&MidiProfile>) -> Option<MidiControl> {
+        self.bindings.get(&action)?.resolve(profile)
+    }
+
+    /// The action a message drives, if any, along with the value it sets
+    pub fn action_for(
+        &self,
+        message: &MidiMessage,
+        profile: Option<&MidiProfile>,
+    ) -> Option<(A, u8)> {
+        self.bindings.iter().find_map(|(action, binding)| {
+            let control = binding.resolve(profile)?;
+            Some((*action, control.value_in(message)?))
+        })
+    }
+
+    /// Pairs of actions bound to the same control, each with the action that had it first
+    ///
+    /// Bindings are compared by the control they resolve to through `profile`, so that a name and
+    /// the number it stands for count as the same. Names `profile` doesn't know are compared as
+    /// names.
+    pub fn duplicates(&self, profile: Option<&MidiProfile>) -> Vec<(A, A)> {
+        let mut bound: Vec<(A, &MidiBinding, Option<MidiControl>)> = vec![];
+        let mut duplicates = vec![];
+
+        for (action, binding) in &self.bindings {
+            let control = binding.resolve(profile);
+
+            let other = bound.iter().find(|(_, other_binding, other_control)| {
+                match (control, other_control) {
+                    (Some(control), Some(other_control)) => control == *other_control,
+                    _ => binding == *other_binding,
+                }
+            });
+
+            match other {
+                Some((other_action, _, _)) => duplicates.push((*action, *other_action)),
+                None => bound.push((*action, binding, control)),
+            }
+        }
+
+        duplicates
+    }
+
+    /// The control names in these bindings that `profile` doesn't know, or all of them without one
+    pub fn unknown_names(&self, profile: Option<&MidiProfile>) -> Vec<&str> {
+        self.bindings
+            .values()
+            .filter_map(|binding| match binding {
+                MidiBinding::Named(name) if binding.resolve(profile).is_none() => {
+                    Some(name.as_str())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl<A: Ord + Copy + Serialize> MidiBindings<A> {
+    /// Writes `action`'s binding into a config file, or `~` if it has none
+    ///
+    /// `path` is where the bindings sit in the config, e.g. `midi.bindings`.
+    pub fn save_binding<P>(
+        &self,
+        file: P,
+        path: &ConfigPath,
+        action: A,
+        migrations: &Migrations,
+    ) -> Result<(), String>
+    where
+        P: AsRef<Path>,
+    {
+        let action_path = path.field(action_name(&action));
+
+        save_value(file, &action_path, &self.binding_for(action), migrations)
+    }
+}
+
+impl<A: Ord + Copy> Default for MidiBindings<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Only numbered controls can be told apart here, since names need the device's profile. Games
+// check `duplicates` again once they have one.
+impl<A: Ord + Copy + Serialize> Validate for MidiBindings<A> {
+    fn validate(&self, path: &ConfigPath, report: &mut ValidationReport) {
+        for (action, binding) in &self.bindings {
+            if let MidiBinding::Control(control) = binding {
+                control.validate(&path.field(action_name(action)), report);
+            }
+        }
+
+        for (action, other_action) in self.duplicates(None) {
+            let message = format!(
+                "{} is already bound to {}",
+                self.bindings[&action],
+                action_name(&other_action)
+            );
+            report.error(path.field(action_name(&action)), message);
+        }
+    }
+}
+
+// Action names are read as plain strings first, as in ActionMap, and unbound actions are dropped
+impl<'de, A: Ord + Deserialize<'de>> Deserialize<'de> for MidiBindings<A> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let named_bindings = BTreeMap::<String, Option<MidiBinding>>::deserialize(deserializer)?;
+        let mut bindings = BTreeMap::new();
+
+        for (name, binding) in named_bindings {
+            let name_deserializer: de::value::StrDeserializer<de::value::Error> =
+                name.as_str().into_deserializer();
+            let action = A::deserialize(name_deserializer).map_err(de::Error::custom)?;
+
+            if let Some(binding) = binding {
+                bindings.insert(action, binding);
+            }
+        }
+
+        Ok(Self { bindings })
+    }
+}
+
+/// Catches the next control a player touches, so that it can be bound to an action
+///
+/// The player steps through `actions` to pick which one to bind, then presses, turns or slides
+/// whatever they want to use for it.
+///
+/// ## Examples
+///
+/// ```ignore
+/// let learn = MidiLearn::new(vec![MidiAction::MoveLeft, MidiAction::MoveRight])
+///     .expect("there are actions to learn");
+///
+/// for message in receiver.try_iter() {
+///     if let Some((action, control)) = learn.capture(&message) {
+///         bindings.rebind(action, control, profile.as_ref());
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiLearn<A> {
+    actions: Vec<A>,
+    current: usize,
+}
+
+impl<A: Copy> MidiLearn<A> {
+    /// Starts out learning the first of `actions`, or gives `None` if there aren't any to learn
+    pub fn new(actions: Vec<A>) -> Option<Self> {
+        if actions.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            actions,
+            current: 0,
+        })
+    }
+
+    /// The action the next control touched will be bound to
+    pub fn action(&self) -> A {
+        self.actions[self.current]
+    }
+
+    /// Moves on to the next action, going back to the first after the last
+    pub fn next_action(&mut self) {
+        self.current = (self.current + 1) % self.actions.len();
+    }
+
+    /// The binding `message` makes for the current action, if it's a control being touched
+    ///
+    /// Releasing a note doesn't count, so that letting go of a key can't bind it elsewhere.
+    pub fn capture(&self, message: &MidiMessage) -> Option<(A, MidiControl)> {
+        match message {
+            MidiMessage::NoteOn { .. } | MidiMessage::ControlChange { .. } => {
+                Some((self.action(), MidiControl::of(message)?))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Action {
+        Fire,
+        Jump,
+        Pause,
+    }
+
+    const RECORD: MidiControl = MidiControl::Controller {
+        channel: 0,
+        controller: 45,
+    };
+
+    fn test_profile() -> MidiProfile {
+        let mut controls = BTreeMap::new();
+        controls.insert("record".to_string(), RECORD);
+
+        MidiProfile {
+            name: "Test Pad".to_string(),
+            ports: vec!["test pad".to_string()],
+            controls,
+        }
+    }
+
+    #[test]
+    fn a_name_and_its_number_are_the_same_control_with_a_profile() {
+        let bindings = MidiBindings::new()
+            .bind(Action::Fire, "record")
+            .bind(Action::Jump, RECORD)
+            .bind(
+                Action::Pause,
+                MidiControl::Note {
+                    channel: 0,
+                    note: 1,
+                },
+            );
+
+        assert_eq!(bindings.duplicates(None), vec![]);
+        assert_eq!(
+            bindings.duplicates(Some(&test_profile())),
+            vec![(Action::Jump, Action::Fire)]
+        );
+    }
+
+    #[test]
+    fn validation_reports_controls_bound_twice() {
+        let bindings = MidiBindings::new()
+            .bind(Action::Fire, RECORD)
+            .bind(Action::Jump, "record")
+            .bind(Action::Pause, RECORD);
+
+        let mut report = ValidationReport::default();
+        bindings.validate(&ConfigPath::root().field("bindings"), &mut report);
+
+        let errors = report.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path.to_string(), "bindings.pause");
+        assert!(errors[0].message.ends_with("is already bound to fire"));
+    }
+
+    #[test]
+    fn rebinding_takes_the_control_from_whichever_action_had_it() {
+        let profile = test_profile();
+        let mut bindings = MidiBindings::new().bind(Action::Fire, "record");
+
+        let displaced = bindings.rebind(Action::Jump, RECORD, Some(&profile));
+
+        assert_eq!(displaced, Some(Action::Fire));
+        assert_eq!(bindings.binding_for(Action::Fire), None);
+        assert_eq!(
+            bindings.binding_for(Action::Jump),
+            Some(&MidiBinding::from("record"))
+        );
+    }
+
+    #[test]
+    fn learning_needs_something_to_learn() {
+        assert_eq!(MidiLearn::<Action>::new(vec![]), None);
+
+        let mut learn = MidiLearn::new(vec![Action::Fire, Action::Jump]).unwrap();
+        learn.next_action();
+
+        let press = MidiMessage::ControlChange {
+            channel: 0,
+            controller: 45,
+            value: 127,
+        };
+
+        assert_eq!(learn.capture(&press), Some((Action::Jump, RECORD)));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::midi::MidiMessage;
+
+/// How many clock pulses a MIDI sequencer sends per quarter note
+pub const PULSES_PER_QUARTER_NOTE: u32 = 24;
+
+/// Turns the MIDI clock from a sequencer, drum machine or DAW into game ticks
+///
+/// Ticks fire every `pulses_per_tick` clock pulses while the sequencer is playing, starting on
+/// the downbeat, so a game that ticks its world on them stays in time with the music. Start
+/// goes back to the top, Stop pauses and Continue picks up where it left off.
+///
+/// ## Examples
+///
+/// ```ignore
+/// let mut clock = MidiClock::new(PULSES_PER_QUARTER_NOTE / 4); // Every 16th note
+///
+/// for message in source.poll() {
+///     if clock.handle(&message) {
+///         world = world.ticked();
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MidiClock {
+    pulses_per_tick: u32,
+    // Pulses since the last tick
+    pulses: u32,
+    running: bool,
+}
+
+impl MidiClock {
+    /// A clock that ticks every `pulses_per_tick` pulses
+    ///
+    /// 0 is taken as 1. `MidiConfig` reports it as a mistake, so that it's caught in the config
+    /// rather than here.
+    pub fn new(pulses_per_tick: u32) -> Self {
+        Self {
+            pulses_per_tick: pulses_per_tick.max(1),
+            pulses: 0,
+            running: false,
+        }
+    }
+
+    pub fn pulses_per_tick(&self) -> u32 {
+        self.pulses_per_tick
+    }
+
+    /// Changes how often ticks fire, keeping to the sequencer's transport
+    pub fn set_pulses_per_tick(&mut self, pulses_per_tick: u32) {
+        self.pulses_per_tick = pulses_per_tick.max(1);
+        self.pulses %= self.pulses_per_tick;
+    }
+
+    /// Whether a sequencer is playing, i.e. it's been started or continued and not stopped since
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Follows the transport and counts clock pulses, returning whether `message` fired a tick
+    ///
+    /// Anything that isn't clock or transport is ignored.
+    pub fn handle(&mut self, message: &MidiMessage) -> bool {
+        match message {
+            MidiMessage::Start => {
+                self.pulses = 0;
+                self.running = true;
+            }
+            MidiMessage::Continue => self.running = true,
+            MidiMessage::Stop => self.running = false,
+            // Counted in 16th notes, which are 6 pulses each
+            MidiMessage::SongPosition(position) => {
+                self.pulses =
+                    u32::from(*position) * (PULSES_PER_QUARTER_NOTE / 4) % self.pulses_per_tick;
+            }
+            MidiMessage::Clock if self.running => {
+                let ticked = self.pulses == 0;
+                self.pulses = (self.pulses + 1) % self.pulses_per_tick;
+
+                return ticked;
+            }
+            _ => (),
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Which of `count` clock pulses fired a tick
+    fn pulse(clock: &mut MidiClock, count: usize) -> Vec<bool> {
+        (0..count)
+            .map(|_| clock.handle(&MidiMessage::Clock))
+            .collect()
+    }
+
+    #[test]
+    fn start_goes_back_to_the_downbeat() {
+        let mut clock = MidiClock::new(6);
+
+        clock.handle(&MidiMessage::Start);
+        assert_eq!(pulse(&mut clock, 4), vec![true, false, false, false]);
+
+        clock.handle(&MidiMessage::Start);
+        assert_eq!(pulse(&mut clock, 2), vec![true, false]);
+    }
+
+    #[test]
+    fn stop_and_continue_keep_the_count() {
+        let mut clock = MidiClock::new(6);
+
+        // Nothing counts before the sequencer starts
+        assert_eq!(pulse(&mut clock, 2), vec![false, false]);
+
+        clock.handle(&MidiMessage::Start);
+        assert_eq!(pulse(&mut clock, 3), vec![true, false, false]);
+
+        clock.handle(&MidiMessage::Stop);
+        assert!(!clock.is_running());
+        assert_eq!(pulse(&mut clock, 5), vec![false; 5]);
+
+        clock.handle(&MidiMessage::Continue);
+        assert!(clock.is_running());
+        assert_eq!(pulse(&mut clock, 4), vec![false, false, false, true]);
+    }
+
+    #[test]
+    fn song_position_sets_the_phase() {
+        // A tick every quarter note, with the song picked up halfway through one
+        let mut clock = MidiClock::new(PULSES_PER_QUARTER_NOTE);
+
+        clock.handle(&MidiMessage::SongPosition(2));
+        clock.handle(&MidiMessage::Continue);
+
+        let ticks = pulse(&mut clock, 13);
+        assert_eq!(ticks.iter().position(|ticked| *ticked), Some(12));
+        assert_eq!(ticks.iter().filter(|ticked| **ticked).count(), 1);
+    }
+
+    #[test]
+    fn changing_the_pulses_per_tick_keeps_the_phase() {
+        let mut clock = MidiClock::new(12);
+
+        clock.handle(&MidiMessage::Start);
+        pulse(&mut clock, 8);
+
+        // 8 pulses past the downbeat, so 4 short of the next tick at 6 pulses a tick
+        clock.set_pulses_per_tick(6);
+
+        assert_eq!(clock.pulses_per_tick(), 6);
+        assert_eq!(pulse(&mut clock, 5), vec![false, false, false, false, true]);
+    }
+
+    #[test]
+    fn zero_pulses_per_tick_is_taken_as_one() {
+        let mut clock = MidiClock::new(0);
+        assert_eq!(clock.pulses_per_tick(), 1);
+
+        clock.set_pulses_per_tick(0);
+        assert_eq!(clock.pulses_per_tick(), 1);
+
+        clock.handle(&MidiMessage::Start);
+        assert_eq!(pulse(&mut clock, 2), vec![true, true]);
+    }
+}
@@ -0,0 +1,364 @@
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const POLY_PRESSURE: u8 = 0xA0;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PROGRAM_CHANGE: u8 = 0xC0;
+const CHANNEL_PRESSURE: u8 = 0xD0;
+const PITCH_BEND: u8 = 0xE0;
+
+const SYSEX_START: u8 = 0xF0;
+const SONG_POSITION: u8 = 0xF2;
+const SONG_SELECT: u8 = 0xF3;
+const SYSEX_END: u8 = 0xF7;
+const CLOCK: u8 = 0xF8;
+const START: u8 = 0xFA;
+const CONTINUE: u8 = 0xFB;
+const STOP: u8 = 0xFC;
+const ACTIVE_SENSING: u8 = 0xFE;
+const RESET: u8 = 0xFF;
+
+/// Where pitch bend rests when the wheel is untouched
+pub const PITCH_BEND_CENTER: u16 = 0x2000;
+
+/// A single MIDI message, as sent by a controller
+///
+/// Channels are numbered from 0 to 15, so channel 0 is what most gear calls channel 1. A NoteOn
+/// with a velocity of 0 is read as a NoteOff, since that's how many devices send them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MidiMessage {
+    NoteOff {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    PolyPressure {
+        channel: u8,
+        note: u8,
+        pressure: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure: u8,
+    },
+    /// From 0 to 16383, resting at `PITCH_BEND_CENTER`
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+    /// The bytes between the opening 0xF0 and the closing 0xF7
+    SysEx(Vec<u8>),
+    /// Sent 24 times per quarter note while a sequencer is playing
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    /// How many 16th notes into the song a sequencer is about to play from
+    SongPosition(u16),
+    SongSelect(u8),
+    ActiveSensing,
+    Reset,
+    /// Anything else, kept as the raw bytes
+    Other(Vec<u8>),
+}
+
+impl MidiMessage {
+    /// Reads a complete message, or `None` if `bytes` is empty or cut short
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let status = *bytes.first()?;
+
+        if status < 0x80 {
+            // Running status isn't used by the backends, which always send the status byte
+            return None;
+        }
+
+        if status >= SYSEX_START {
+            return parse_system(status, bytes);
+        }
+
+        let channel = status & 0x0F;
+        let data = |i: usize| bytes.get(i).map(|b| b & 0x7F);
+
+        let message = match status & 0xF0 {
+            NOTE_OFF => MidiMessage::NoteOff {
+                channel,
+                note: data(1)?,
+                velocity: data(2)?,
+            },
+            NOTE_ON => match data(2)? {
+                0 => MidiMessage::NoteOff {
+                    channel,
+                    note: data(1)?,
+                    velocity: 0,
+                },
+                velocity => MidiMessage::NoteOn {
+                    channel,
+                    note: data(1)?,
+                    velocity,
+                },
+            },
+            POLY_PRESSURE => MidiMessage::PolyPressure {
+                channel,
+                note: data(1)?,
+                pressure: data(2)?,
+            },
+            CONTROL_CHANGE => MidiMessage::ControlChange {
+                channel,
+                controller: data(1)?,
+                value: data(2)?,
+            },
+            PROGRAM_CHANGE => MidiMessage::ProgramChange {
+                channel,
+                program: data(1)?,
+            },
+            CHANNEL_PRESSURE => MidiMessage::ChannelPressure {
+                channel,
+                pressure: data(1)?,
+            },
+            _ => MidiMessage::PitchBend {
+                channel,
+                value: fourteen_bit(data(1)?, data(2)?),
+            },
+        };
+
+        Some(message)
+    }
+
+    /// The message as it's sent over the wire
+    ///
+    /// Channels above 15 and data above 127 are cut down to their low bits, so that a bad value
+    /// can't turn into a different message on the way out.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            MidiMessage::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => vec![status(NOTE_OFF, *channel), data(*note), data(*velocity)],
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => vec![status(NOTE_ON, *channel), data(*note), data(*velocity)],
+            MidiMessage::PolyPressure {
+                channel,
+                note,
+                pressure,
+            } => vec![
+                status(POLY_PRESSURE, *channel),
+                data(*note),
+                data(*pressure),
+            ],
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            } => vec![
+                status(CONTROL_CHANGE, *channel),
+                data(*controller),
+                data(*value),
+            ],
+            MidiMessage::ProgramChange { channel, program } => {
+                vec![status(PROGRAM_CHANGE, *channel), data(*program)]
+            }
+            MidiMessage::ChannelPressure { channel, pressure } => {
+                vec![status(CHANNEL_PRESSURE, *channel), data(*pressure)]
+            }
+            MidiMessage::PitchBend { channel, value } => {
+                let (lsb, msb) = split_fourteen_bit(*value);
+                vec![status(PITCH_BEND, *channel), lsb, msb]
+            }
+            MidiMessage::SysEx(bytes) => {
+                let mut sysex = vec![SYSEX_START];
+                sysex.extend(bytes.iter().map(|b| data(*b)));
+                sysex.push(SYSEX_END);
+                sysex
+            }
+            MidiMessage::Clock => vec![CLOCK],
+            MidiMessage::Start => vec![START],
+            MidiMessage::Continue => vec![CONTINUE],
+            MidiMessage::Stop => vec![STOP],
+            MidiMessage::SongPosition(position) => {
+                let (lsb, msb) = split_fourteen_bit(*position);
+                vec![SONG_POSITION, lsb, msb]
+            }
+            MidiMessage::SongSelect(song) => vec![SONG_SELECT, data(*song)],
+            MidiMessage::ActiveSensing => vec![ACTIVE_SENSING],
+            MidiMessage::Reset => vec![RESET],
+            MidiMessage::Other(bytes) => bytes.clone(),
+        }
+    }
+
+    /// The channel of a channel message, or `None` for system messages like Clock
+    pub fn channel(&self) -> Option<u8> {
+        match self {
+            MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::PolyPressure { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelPressure { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(*channel),
+            _ => None,
+        }
+    }
+}
+
+fn parse_system(status: u8, bytes: &[u8]) -> Option<MidiMessage> {
+    let message = match status {
+        SYSEX_START => {
+            let end = bytes.iter().position(|b| *b == SYSEX_END)?;
+            MidiMessage::SysEx(bytes[1..end].to_vec())
+        }
+        SONG_POSITION => MidiMessage::SongPosition(fourteen_bit(*bytes.get(1)?, *bytes.get(2)?)),
+        SONG_SELECT => MidiMessage::SongSelect(*bytes.get(1)? & 0x7F),
+        CLOCK => MidiMessage::Clock,
+        START => MidiMessage::Start,
+        CONTINUE => MidiMessage::Continue,
+        STOP => MidiMessage::Stop,
+        ACTIVE_SENSING => MidiMessage::ActiveSensing,
+        RESET => MidiMessage::Reset,
+        _ => MidiMessage::Other(bytes.to_vec()),
+    };
+
+    Some(message)
+}
+
+// Two 7-bit data bytes, least significant first
+fn fourteen_bit(lsb: u8, msb: u8) -> u16 {
+    (u16::from(msb & 0x7F) << 7) | u16::from(lsb & 0x7F)
+}
+
+// The inverse of `fourteen_bit`, dropping anything above 14 bits
+fn split_fourteen_bit(value: u16) -> (u8, u8) {
+    (data(value as u8), data((value >> 7) as u8))
+}
+
+// A channel message's status byte, which only has room for channels 0 to 15
+fn status(kind: u8, channel: u8) -> u8 {
+    kind | (channel & 0x0F)
+}
+
+// A data byte, whose top bit would otherwise make it a status byte
+fn data(byte: u8) -> u8 {
+    byte & 0x7F
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_and_cut_short_messages_are_not_read() {
+        assert_eq!(MidiMessage::parse(&[]), None);
+        assert_eq!(MidiMessage::parse(&[0x90, 60]), None);
+        assert_eq!(MidiMessage::parse(&[0xB0]), None);
+        assert_eq!(MidiMessage::parse(&[0xC0]), None);
+        assert_eq!(MidiMessage::parse(&[SONG_POSITION, 0x10]), None);
+        assert_eq!(MidiMessage::parse(&[SYSEX_START, 0x7E, 0x01]), None);
+        // A data byte on its own, with no status before it
+        assert_eq!(MidiMessage::parse(&[0x40, 0x40]), None);
+    }
+
+    #[test]
+    fn one_and_two_byte_messages_are_read() {
+        assert_eq!(MidiMessage::parse(&[CLOCK]), Some(MidiMessage::Clock));
+        assert_eq!(MidiMessage::parse(&[START]), Some(MidiMessage::Start));
+        assert_eq!(MidiMessage::parse(&[STOP]), Some(MidiMessage::Stop));
+        assert_eq!(
+            MidiMessage::parse(&[0xC3, 12]),
+            Some(MidiMessage::ProgramChange {
+                channel: 3,
+                program: 12
+            })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0xD0, 99]),
+            Some(MidiMessage::ChannelPressure {
+                channel: 0,
+                pressure: 99
+            })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[SONG_SELECT, 4]),
+            Some(MidiMessage::SongSelect(4))
+        );
+    }
+
+    #[test]
+    fn sysex_is_read_up_to_its_end() {
+        assert_eq!(
+            MidiMessage::parse(&[SYSEX_START, 0x7E, 0x7F, 0x06, 0x01, SYSEX_END]),
+            Some(MidiMessage::SysEx(vec![0x7E, 0x7F, 0x06, 0x01]))
+        );
+    }
+
+    #[test]
+    fn note_on_without_velocity_is_a_note_off() {
+        assert_eq!(
+            MidiMessage::parse(&[0x92, 60, 0]),
+            Some(MidiMessage::NoteOff {
+                channel: 2,
+                note: 60,
+                velocity: 0
+            })
+        );
+    }
+
+    #[test]
+    fn messages_read_back_as_the_bytes_they_came_from() {
+        let messages: &[&[u8]] = &[
+            &[0x80, 60, 64],
+            &[0x9F, 60, 100],
+            &[0xA1, 60, 30],
+            &[0xB2, 7, 127],
+            &[0xC3, 5],
+            &[0xD4, 90],
+            &[0xE5, 0x00, 0x40],
+            &[SYSEX_START, 0x41, 0x10, SYSEX_END],
+            &[SONG_POSITION, 0x7F, 0x01],
+            &[SONG_SELECT, 3],
+            &[CLOCK],
+            &[CONTINUE],
+            &[ACTIVE_SENSING],
+            &[RESET],
+        ];
+
+        for bytes in messages {
+            let message = MidiMessage::parse(bytes).unwrap();
+
+            assert_eq!(message.to_bytes(), bytes.to_vec(), "{:?}", message);
+        }
+    }
+
+    #[test]
+    fn out_of_range_values_stay_the_same_kind_of_message() {
+        let message = MidiMessage::NoteOn {
+            channel: 32,
+            note: 60,
+            velocity: 200,
+        };
+
+        assert_eq!(message.to_bytes(), vec![0x90, 60, 72]);
+
+        let bend = MidiMessage::PitchBend {
+            channel: 1,
+            value: 0xFFFF,
+        };
+
+        assert_eq!(bend.to_bytes(), vec![0xE1, 0x7F, 0x7F]);
+    }
+}
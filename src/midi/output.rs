@@ -0,0 +1,304 @@
+use midir;
+
+use std::fmt;
+
+use crate::midi::ports::named_output_ports;
+use crate::midi::{select_port, MidiError, MidiMessage};
+
+/// Somewhere to send MIDI, such as a controller's button lights
+///
+/// Games hold a `Box<dyn MidiOutput>` so that a `RecordingOutput` can stand in for the hardware
+/// when there's none plugged in, or in tests.
+pub trait MidiOutput {
+    fn send(&mut self, message: &MidiMessage) -> Result<(), MidiError>;
+
+    /// Sends a NoteOn, unless the channel's above 15 or the note or velocity above 127
+    fn note_on(&mut self, channel: u8, note: u8, velocity: u8) -> Result<(), MidiError> {
+        check_range("channel", channel, MAX_CHANNEL)?;
+        check_range("note", note, MAX_DATA)?;
+        check_range("velocity", velocity, MAX_DATA)?;
+
+        self.send(&MidiMessage::NoteOn {
+            channel,
+            note,
+            velocity,
+        })
+    }
+
+    /// Sends a ControlChange, unless the channel's above 15 or the controller or value above 127
+    fn control_change(&mut self, channel: u8, controller: u8, value: u8) -> Result<(), MidiError> {
+        check_range("channel", channel, MAX_CHANNEL)?;
+        check_range("controller", controller, MAX_DATA)?;
+        check_range("value", value, MAX_DATA)?;
+
+        self.send(&MidiMessage::ControlChange {
+            channel,
+            controller,
+            value,
+        })
+    }
+}
+
+const MAX_CHANNEL: u8 = 15;
+const MAX_DATA: u8 = 127;
+
+fn check_range(field: &'static str, value: u8, max: u8) -> Result<(), MidiError> {
+    if value > max {
+        return Err(MidiError::OutOfRange { field, value, max });
+    }
+
+    Ok(())
+}
+
+/// Opens a MIDI output, chosen by `select_port` from `port_name` as `connect_to_midi` does
+pub fn connect_to_midi_output(port_name: Option<&str>) -> Result<MidiOutputConnection, MidiError> {
+    let midi_out = midir::MidiOutput::new("sweet-turnips output")
+        .map_err(|e| MidiError::Unavailable(e.to_string()))?;
+
+    let (ports, names): (Vec<_>, Vec<_>) = named_output_ports(&midi_out).into_iter().unzip();
+
+    let selected = select_port(&names, port_name)?;
+    let out_port_name = names[selected].clone();
+
+    let connection = midi_out
+        .connect(&ports[selected], "sweet-turnips-output")
+        .map_err(|e| MidiError::ConnectFailed {
+            port: out_port_name.clone(),
+            reason: e.to_string(),
+        })?;
+
+    Ok(MidiOutputConnection {
+        connection: Some(connection),
+        port_name: out_port_name,
+    })
+}
+
+/// An open MIDI output, which lets go of the port when closed or dropped
+pub struct MidiOutputConnection {
+    // Only taken when closing
+    connection: Option<midir::MidiOutputConnection>,
+    port_name: String,
+}
+
+impl MidiOutputConnection {
+    /// The name of the output being sent to
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    pub fn close(mut self) {
+        self.shut_down();
+    }
+
+    fn shut_down(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            connection.close();
+        }
+    }
+}
+
+impl MidiOutput for MidiOutputConnection {
+    fn send(&mut self, message: &MidiMessage) -> Result<(), MidiError> {
+        let connection = self
+            .connection
+            .as_mut()
+            .ok_or_else(|| MidiError::SendFailed("the output is closed".to_string()))?;
+
+        connection
+            .send(&message.to_bytes())
+            .map_err(|e| MidiError::SendFailed(e.to_string()))
+    }
+}
+
+impl Drop for MidiOutputConnection {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+impl fmt::Debug for MidiOutputConnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MidiOutputConnection")
+            .field("port_name", &self.port_name)
+            .field("open", &self.connection.is_some())
+            .finish()
+    }
+}
+
+/// A `MidiOutput` that keeps everything sent to it, for checking what a game would have sent
+///
+/// ## Examples
+///
+/// ```ignore
+/// let mut output = RecordingOutput::new();
+///
+/// light_fire_button(&mut output)?;
+///
+/// assert_eq!(output.sent(), &[MidiMessage::ControlChange { channel: 0, controller: 41, value: 127 }]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordingOutput {
+    sent: Vec<MidiMessage>,
+}
+
+impl RecordingOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything sent so far, oldest first
+    pub fn sent(&self) -> &[MidiMessage] {
+        &self.sent
+    }
+
+    /// Hands over everything sent so far, leaving the recording empty
+    pub fn take(&mut self) -> Vec<MidiMessage> {
+        std::mem::take(&mut self.sent)
+    }
+}
+
+impl MidiOutput for RecordingOutput {
+    fn send(&mut self, message: &MidiMessage) -> Result<(), MidiError> {
+        self.sent.push(message.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::midi::{MidiBindings, MidiControl, MidiProfile};
+
+    use std::collections::BTreeMap;
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    enum Action {
+        Fire,
+        Jump,
+    }
+
+    // Lights the button bound to `action` the way a game's feedback does, whichever kind of
+    // control it is
+    fn set_light(
+        output: &mut dyn MidiOutput,
+        bindings: &MidiBindings<Action>,
+        profile: Option<&MidiProfile>,
+        action: Action,
+        value: u8,
+    ) -> Result<(), MidiError> {
+        match bindings.control_for(action, profile) {
+            Some(MidiControl::Controller {
+                channel,
+                controller,
+            }) => output.control_change(channel, controller, value),
+            Some(MidiControl::Note { channel, note }) => output.note_on(channel, note, value),
+            None => Ok(()),
+        }
+    }
+
+    fn test_profile() -> MidiProfile {
+        let mut controls = BTreeMap::new();
+        controls.insert(
+            "record".to_string(),
+            MidiControl::Controller {
+                channel: 0,
+                controller: 45,
+            },
+        );
+
+        MidiProfile {
+            name: "Test Pad".to_string(),
+            ports: vec!["test pad".to_string()],
+            controls,
+        }
+    }
+
+    #[test]
+    fn feedback_is_sent_to_the_bound_controls() {
+        let profile = test_profile();
+        let bindings = MidiBindings::new().bind(Action::Fire, "record").bind(
+            Action::Jump,
+            MidiControl::Note {
+                channel: 9,
+                note: 36,
+            },
+        );
+
+        let mut output = RecordingOutput::new();
+
+        set_light(&mut output, &bindings, Some(&profile), Action::Fire, 127).unwrap();
+        set_light(&mut output, &bindings, Some(&profile), Action::Jump, 64).unwrap();
+        set_light(&mut output, &bindings, Some(&profile), Action::Fire, 0).unwrap();
+
+        assert_eq!(
+            output.sent(),
+            &[
+                MidiMessage::ControlChange {
+                    channel: 0,
+                    controller: 45,
+                    value: 127,
+                },
+                MidiMessage::NoteOn {
+                    channel: 9,
+                    note: 36,
+                    velocity: 64,
+                },
+                MidiMessage::ControlChange {
+                    channel: 0,
+                    controller: 45,
+                    value: 0,
+                },
+            ]
+        );
+
+        assert_eq!(output.take().len(), 3);
+        assert!(output.sent().is_empty());
+    }
+
+    #[test]
+    fn named_controls_send_nothing_without_a_profile() {
+        let bindings = MidiBindings::new().bind(Action::Fire, "record");
+
+        let mut output = RecordingOutput::new();
+
+        set_light(&mut output, &bindings, None, Action::Fire, 127).unwrap();
+
+        assert!(output.sent().is_empty());
+    }
+
+    #[test]
+    fn out_of_range_values_are_not_sent() {
+        let mut output = RecordingOutput::new();
+
+        assert_eq!(
+            output.note_on(16, 36, 127),
+            Err(MidiError::OutOfRange {
+                field: "channel",
+                value: 16,
+                max: 15,
+            })
+        );
+        assert_eq!(
+            output.note_on(0, 128, 127),
+            Err(MidiError::OutOfRange {
+                field: "note",
+                value: 128,
+                max: 127,
+            })
+        );
+        assert_eq!(
+            output.control_change(0, 45, 200),
+            Err(MidiError::OutOfRange {
+                field: "value",
+                value: 200,
+                max: 127,
+            })
+        );
+
+        assert!(output.sent().is_empty());
+
+        output.control_change(15, 127, 127).unwrap();
+        assert_eq!(output.sent().len(), 1);
+    }
+}
@@ -0,0 +1,169 @@
+use midir::{MidiInput, MidiInputPort, MidiOutput, MidiOutputPort};
+
+use crate::midi::MidiError;
+
+// Inputs with these in their names loop other software back in rather than being devices,
+// e.g. ALSA's "Midi Through"
+const LOOPBACK_NAMES: &[&str] = &["through"];
+
+/// The names of the MIDI inputs available right now, in the order the system lists them
+pub fn input_port_names() -> Result<Vec<String>, MidiError> {
+    let midi_in = MidiInput::new("sweet-turnips port list")
+        .map_err(|e| MidiError::Unavailable(e.to_string()))?;
+
+    let names = named_ports(&midi_in)
+        .into_iter()
+        .map(|(_, name)| name)
+        .collect();
+
+    Ok(names)
+}
+
+/// The names of the MIDI outputs available right now, in the order the system lists them
+pub fn output_port_names() -> Result<Vec<String>, MidiError> {
+    let midi_out = MidiOutput::new("sweet-turnips port list")
+        .map_err(|e| MidiError::Unavailable(e.to_string()))?;
+
+    let names = named_output_ports(&midi_out)
+        .into_iter()
+        .map(|(_, name)| name)
+        .collect();
+
+    Ok(names)
+}
+
+/// Picks which of the ports in `names` to use, returning its index
+///
+/// A `wanted` name matches a port with exactly that name, or else the only port whose name
+/// contains it (ignoring case). Without one, the first port that isn't a loopback is picked.
+pub fn select_port(names: &[String], wanted: Option<&str>) -> Result<usize, MidiError> {
+    if names.is_empty() {
+        return Err(MidiError::NoPorts);
+    }
+
+    let wanted = match wanted {
+        Some(wanted) => wanted,
+        None => {
+            let device = names.iter().position(|name| !is_loopback(name));
+            return Ok(device.unwrap_or(0));
+        }
+    };
+
+    if let Some(exact) = names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(wanted))
+    {
+        return Ok(exact);
+    }
+
+    let wanted_lowercase = wanted.to_lowercase();
+
+    let matches: Vec<usize> = names
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| name.to_lowercase().contains(&wanted_lowercase))
+        .map(|(i, _)| i)
+        .collect();
+
+    match matches.as_slice() {
+        [only] => Ok(*only),
+        [] => Err(MidiError::PortNotFound {
+            wanted: wanted.to_string(),
+            available: names.to_vec(),
+        }),
+        _ => Err(MidiError::AmbiguousPort {
+            wanted: wanted.to_string(),
+            matches: matches.iter().map(|i| names[*i].clone()).collect(),
+        }),
+    }
+}
+
+// Like `select_port`, but without `wanted` only a device will do. Loopbacks count as no ports at
+// all, so that a watcher keeps waiting for a device rather than settling for "Midi Through".
+pub(crate) fn select_device_port(
+    names: &[String],
+    wanted: Option<&str>,
+) -> Result<usize, MidiError> {
+    match wanted {
+        Some(_) => select_port(names, wanted),
+        None => names
+            .iter()
+            .position(|name| !is_loopback(name))
+            .ok_or(MidiError::NoPorts),
+    }
+}
+
+// Ports whose names can't be read are left out, since there'd be no way to pick them
+pub(crate) fn named_ports(midi_in: &MidiInput) -> Vec<(MidiInputPort, String)> {
+    midi_in
+        .ports()
+        .into_iter()
+        .filter_map(|port| {
+            let name = midi_in.port_name(&port).ok()?;
+            Some((port, name))
+        })
+        .collect()
+}
+
+pub(crate) fn named_output_ports(midi_out: &MidiOutput) -> Vec<(MidiOutputPort, String)> {
+    midi_out
+        .ports()
+        .into_iter()
+        .filter_map(|port| {
+            let name = midi_out.port_name(&port).ok()?;
+            Some((port, name))
+        })
+        .collect()
+}
+
+fn is_loopback(name: &str) -> bool {
+    let name = name.to_lowercase();
+
+    LOOPBACK_NAMES
+        .iter()
+        .any(|loopback| name.contains(loopback))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn devices_are_picked_over_loopbacks() {
+        let names = names(&[
+            "Midi Through:Midi Through Port-0 14:0",
+            "nanoKONTROL2 MIDI 1",
+        ]);
+
+        assert_eq!(select_port(&names, None), Ok(1));
+        assert_eq!(select_device_port(&names, None), Ok(1));
+    }
+
+    #[test]
+    fn only_loopbacks_are_no_devices_at_all() {
+        let names = names(&["Midi Through:Midi Through Port-0 14:0"]);
+
+        assert_eq!(select_port(&names, None), Ok(0));
+        assert_eq!(select_device_port(&names, None), Err(MidiError::NoPorts));
+        assert_eq!(select_device_port(&names, Some("through")), Ok(0));
+    }
+
+    #[test]
+    fn wanted_names_must_match_one_port() {
+        let names = names(&["nanoKONTROL2 MIDI 1", "nanoKEY2 MIDI 1"]);
+
+        assert_eq!(select_device_port(&names, Some("kontrol")), Ok(0));
+        assert!(matches!(
+            select_device_port(&names, Some("nano")),
+            Err(MidiError::AmbiguousPort { .. })
+        ));
+        assert!(matches!(
+            select_device_port(&names, Some("launchpad")),
+            Err(MidiError::PortNotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use serde_path_to_error;
+use serde_yaml;
+use serde_yaml::Value;
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{ConfigPath, ValidationReport};
+use crate::midi::{MidiControl, MidiError};
+use crate::AppConfig;
+
+const PROFILE_DIR: &str = "midi-profiles";
+const PROFILE_EXTENSION: &str = "yaml";
+
+/// Where controller profiles are kept: `midi-profiles/` in each of the app's resource paths, in
+/// the same order
+pub fn profile_dirs(app_config: &AppConfig) -> Vec<PathBuf> {
+    app_config
+        .resource_paths()
+        .iter()
+        .map(|resource_path| resource_path.join(PROFILE_DIR))
+        .collect()
+}
+
+/// Names for the controls on a MIDI device, so that bindings can say `rewind` or `knob_1`
+/// rather than a channel and controller number
+///
+/// ## Examples
+///
+/// ```yaml
+/// name: nanoKONTROL2
+/// ports: [nanoKONTROL2]
+/// controls:
+///   rewind: { channel: 0, controller: 43 }
+///   knob_1: { channel: 0, controller: 16 }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiProfile {
+    pub name: String,
+    /// The ports this profile is for, each matching any port whose name contains it (ignoring case)
+    pub ports: Vec<String>,
+    pub controls: BTreeMap<String, MidiControl>,
+}
+
+impl MidiProfile {
+    pub fn load<P>(path: P) -> Result<Self, MidiError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let unreadable = |reason: String| MidiError::UnreadableProfile(path.to_path_buf(), reason);
+
+        let source = fs::read_to_string(path).map_err(|e| unreadable(e.to_string()))?;
+
+        let profile: Value =
+            serde_yaml::from_str(&source).map_err(|e| unreadable(e.to_string()))?;
+
+        let profile: Self = serde_path_to_error::deserialize(profile)
+            .map_err(|e| unreadable(format!("{}: {}", e.path(), e.inner())))?;
+
+        let mut report = ValidationReport::default();
+        let controls_path = ConfigPath::root().field("controls");
+
+        for (name, control) in &profile.controls {
+            control.validate(&controls_path.field(name), &mut report);
+        }
+
+        if !report.is_empty() {
+            let problems: Vec<String> = report.errors().iter().map(|e| e.to_string()).collect();
+            return Err(unreadable(problems.join("; ")));
+        }
+
+        Ok(profile)
+    }
+
+    /// The first profile in `dirs` that's for `port_name`, going by directory and then file name
+    /// order, along with the problems with any profiles that couldn't be read
+    ///
+    /// Profiles are `.yaml` files. A bad one is skipped rather than hiding the rest, and a missing
+    /// directory just has no profiles in it.
+    pub fn find_for_port<I, P>(dirs: I, port_name: &str) -> (Option<Self>, Vec<MidiError>)
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut errors = vec![];
+
+        for dir in dirs {
+            let dir = dir.as_ref();
+
+            if !dir.is_dir() {
+                continue;
+            }
+
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    errors.push(MidiError::UnreadableProfile(
+                        dir.to_path_buf(),
+                        e.to_string(),
+                    ));
+                    continue;
+                }
+            };
+
+            let mut paths: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension() == Some(OsStr::new(PROFILE_EXTENSION)))
+                .collect();
+            paths.sort();
+
+            for path in paths {
+                match Self::load(&path) {
+                    Ok(profile) if profile.matches_port(port_name) => {
+                        return (Some(profile), errors)
+                    }
+                    Ok(_) => (),
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+
+        (None, errors)
+    }
+
+    pub fn matches_port(&self, port_name: &str) -> bool {
+        let port_name = port_name.to_lowercase();
+
+        self.ports
+            .iter()
+            .any(|port| port_name.contains(&port.to_lowercase()))
+    }
+
+    pub fn control(&self, name: &str) -> Option<MidiControl> {
+        self.controls.get(name).copied()
+    }
+
+    /// What this profile calls `control`, if it names it
+    pub fn name_of(&self, control: MidiControl) -> Option<&str> {
+        self.controls
+            .iter()
+            .find(|(_, named)| **named == control)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_support::scratch_dir;
+
+    fn write_profile(dir: &Path, file_name: &str, port: &str, control: &str) {
+        let source = format!(
+            "name: {}\nports: [{}]\ncontrols:\n  rewind: {}\n",
+            file_name, port, control
+        );
+
+        fs::write(dir.join(file_name), source).unwrap();
+    }
+
+    #[test]
+    fn bad_profiles_are_skipped_and_reported() {
+        let dir = scratch_dir("bad-profiles");
+
+        write_profile(&dir, "a-broken.yaml", "pad", "{ channel: 0 }");
+        write_profile(
+            &dir,
+            "b-channel.yaml",
+            "pad",
+            "{ channel: 16, controller: 43 }",
+        );
+        write_profile(&dir, "c-number.yaml", "pad", "{ channel: 0, note: 128 }");
+        write_profile(&dir, "d-good.yaml", "pad", "{ channel: 0, controller: 43 }");
+
+        let (profile, errors) = MidiProfile::find_for_port(vec![&dir], "Test Pad MIDI 1");
+
+        assert_eq!(profile.map(|p| p.name), Some("d-good.yaml".to_string()));
+        assert_eq!(errors.len(), 3);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert!(messages[0].contains("a-broken.yaml"));
+        assert!(messages[1].contains("controls.rewind.channel"));
+        assert!(messages[2].contains("controls.rewind.note"));
+    }
+
+    #[test]
+    fn earlier_directories_come_first() {
+        let first = scratch_dir("profiles-first");
+        let second = scratch_dir("profiles-second");
+        let missing = first.join("missing");
+
+        write_profile(&first, "mine.yaml", "pad", "{ channel: 1, controller: 43 }");
+        write_profile(
+            &second,
+            "a-shipped.yaml",
+            "pad",
+            "{ channel: 0, controller: 43 }",
+        );
+
+        let (profile, errors) = MidiProfile::find_for_port(&[missing, first, second], "pad");
+
+        assert!(errors.is_empty());
+        assert_eq!(profile.map(|p| p.name), Some("mine.yaml".to_string()));
+    }
+}
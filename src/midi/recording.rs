@@ -0,0 +1,239 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::midi::{
+    read_midi_file, write_midi_file, MidiError, MidiMessage, MidiReceiver, MidiSource,
+};
+
+// How long playback sleeps at most before checking whether it's been stopped
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+// How far ahead of the clock a stamp can put a message before it's taken as a new starting point
+const MAX_STAMP_DRIFT: Duration = Duration::from_secs(1);
+
+/// Keeps MIDI messages with the time each arrived, to be saved as a Standard MIDI File
+///
+/// ## Examples
+///
+/// ```ignore
+/// let mut recorder = MidiRecorder::new();
+///
+/// for (stamp, message) in source.poll_stamped() {
+///     recorder.record_stamped(stamp, message.clone());
+///     handle(message);
+/// }
+///
+/// recorder.save("session.mid")?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct MidiRecorder {
+    started: Instant,
+    events: Vec<(Duration, MidiMessage)>,
+    // A source's stamp paired with when it was recorded, which later stamps are measured from
+    anchor: Option<(u64, Duration)>,
+}
+
+impl MidiRecorder {
+    /// Starts recording now, so the first message is at however long it takes to arrive
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            events: vec![],
+            anchor: None,
+        }
+    }
+
+    /// Records `message` at the time it's handed over
+    pub fn record(&mut self, message: MidiMessage) {
+        self.record_at(self.started.elapsed(), message);
+    }
+
+    /// Records `message` at the time its source stamped it with (see `MidiSource::poll_stamped`)
+    ///
+    /// This keeps the gaps between messages that arrived together in one poll. Stamps that go
+    /// backwards or run well ahead of the clock, as when a device is reconnected, start measuring
+    /// afresh from now.
+    pub fn record_stamped(&mut self, stamp: u64, message: MidiMessage) {
+        let now = self.started.elapsed();
+
+        let at = match self.anchor {
+            Some((anchor_stamp, anchor_at)) if stamp >= anchor_stamp => {
+                Some(anchor_at + Duration::from_micros(stamp - anchor_stamp))
+                    .filter(|at| *at <= now + MAX_STAMP_DRIFT)
+            }
+            _ => None,
+        };
+
+        let at = match at {
+            Some(at) => at,
+            None => {
+                self.anchor = Some((stamp, now));
+                now
+            }
+        };
+
+        self.record_at(at, message);
+    }
+
+    /// Records `message` at `at` since the recording started, keeping everything in time order
+    pub fn record_at(&mut self, at: Duration, message: MidiMessage) {
+        let index = self
+            .events
+            .iter()
+            .rposition(|(time, _)| *time <= at)
+            .map_or(0, |i| i + 1);
+        self.events.insert(index, (at, message));
+    }
+
+    /// Everything recorded so far, oldest first
+    pub fn events(&self) -> &[(Duration, MidiMessage)] {
+        &self.events
+    }
+
+    pub fn save<P>(&self, path: P) -> Result<(), MidiError>
+    where
+        P: AsRef<Path>,
+    {
+        write_midi_file(path, &self.events)
+    }
+}
+
+impl Default for MidiRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Plays a Standard MIDI File in real time, as a `MidiSource` standing in for a device
+///
+/// The file is read up front, so a bad file fails here rather than partway through. Messages
+/// arrive just as `connect_to_midi`'s do, until the file ends or the returned playback is
+/// stopped or dropped.
+pub fn play_midi_file<P>(path: P) -> Result<MidiPlayback, MidiError>
+where
+    P: AsRef<Path>,
+{
+    let events = read_midi_file(path)?;
+
+    Ok(MidiPlayback::start(events))
+}
+
+/// MIDI being played from a file, which stops when stopped or dropped
+#[derive(Debug)]
+pub struct MidiPlayback {
+    rx: MidiReceiver,
+    stopped: Arc<AtomicBool>,
+    finished: Arc<AtomicBool>,
+}
+
+impl MidiPlayback {
+    /// Plays each message at its time from now, on a thread of its own
+    pub fn start(events: Vec<(Duration, MidiMessage)>) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let playback = Self {
+            rx,
+            stopped: stopped.clone(),
+            finished: finished.clone(),
+        };
+
+        thread::spawn(move || {
+            let started = Instant::now();
+
+            'events: for (at, message) in events {
+                let stamp = at.as_micros() as u64;
+
+                loop {
+                    if stopped.load(Ordering::Relaxed) {
+                        break 'events;
+                    }
+
+                    let elapsed = started.elapsed();
+
+                    if elapsed >= at {
+                        break;
+                    }
+
+                    thread::sleep((at - elapsed).min(STOP_CHECK_INTERVAL));
+                }
+
+                // The playback's been dropped
+                if tx.send((stamp, message)).is_err() {
+                    break;
+                }
+            }
+
+            finished.store(true, Ordering::Relaxed);
+        });
+
+        playback
+    }
+
+    pub fn stop(self) {}
+}
+
+impl MidiSource for MidiPlayback {
+    fn poll_stamped(&mut self) -> Vec<(u64, MidiMessage)> {
+        self.rx.try_iter().collect()
+    }
+
+    /// Whether every message has been played, or playback was cut short
+    fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MidiPlayback {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(note: u8) -> MidiMessage {
+        MidiMessage::NoteOn {
+            channel: 0,
+            note,
+            velocity: 100,
+        }
+    }
+
+    #[test]
+    fn stamps_keep_the_gaps_between_messages() {
+        let mut recorder = MidiRecorder::new();
+
+        recorder.record_stamped(5_000_000, note_on(60));
+        recorder.record_stamped(5_002_500, note_on(62));
+        recorder.record_stamped(5_010_000, note_on(64));
+
+        let times: Vec<Duration> = recorder.events().iter().map(|(at, _)| *at).collect();
+
+        assert_eq!(times[1] - times[0], Duration::from_micros(2_500));
+        assert_eq!(times[2] - times[0], Duration::from_micros(10_000));
+    }
+
+    #[test]
+    fn stamps_going_backwards_start_afresh() {
+        let mut recorder = MidiRecorder::new();
+
+        recorder.record_stamped(5_000_000, note_on(60));
+        // As from a device that's been plugged back in
+        recorder.record_stamped(1_000, note_on(62));
+        recorder.record_stamped(3_000, note_on(64));
+
+        let events = recorder.events();
+
+        assert_eq!(events[1].1, note_on(62));
+        assert!(events[1].0 >= events[0].0);
+        assert_eq!(events[2].0 - events[1].0, Duration::from_micros(2_000));
+    }
+}
@@ -0,0 +1,536 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::midi::{MidiError, MidiMessage};
+
+// Files are written at 120 BPM with this many ticks to a quarter note
+const TICKS_PER_QUARTER_NOTE: u16 = 480;
+const MICROS_PER_QUARTER_NOTE: u32 = 500_000;
+
+const HEADER_CHUNK: &[u8; 4] = b"MThd";
+const TRACK_CHUNK: &[u8; 4] = b"MTrk";
+
+const META_EVENT: u8 = 0xFF;
+const META_TEXT: u8 = 0x01;
+const META_TEMPO: u8 = 0x51;
+const META_END_OF_TRACK: u8 = 0x2F;
+const SYSEX_EVENT: u8 = 0xF0;
+// Carries any bytes at all, which is how messages with no event of their own are stored
+const ESCAPE_EVENT: u8 = 0xF7;
+
+// The most a variable-length number can hold in the four bytes it's allowed
+const MAX_VARIABLE_LENGTH: u32 = 0x0FFF_FFFF;
+
+/// Writes messages to a Standard MIDI File (format 0), each at its time from the start
+///
+/// Channel messages and SysEx are stored as themselves. Clock and other system messages, which
+/// files have no events for, are stored as escaped bytes, and come back as they were when read
+/// with `read_midi_file`.
+pub fn write_midi_file<P>(path: P, events: &[(Duration, MidiMessage)]) -> Result<(), MidiError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    let mut track = vec![];
+
+    write_variable_length(&mut track, 0);
+    track.extend(&[META_EVENT, META_TEMPO, 3]);
+    track.extend(&MICROS_PER_QUARTER_NOTE.to_be_bytes()[1..]);
+
+    let mut last_tick = 0;
+
+    for (at, message) in events {
+        let tick = ticks_at(*at).max(last_tick);
+        write_delta(&mut track, tick - last_tick);
+        last_tick = tick;
+
+        let bytes = message.to_bytes();
+
+        match message {
+            MidiMessage::SysEx(_) => {
+                track.push(SYSEX_EVENT);
+                write_variable_length(&mut track, bytes.len() as u32 - 1);
+                track.extend(&bytes[1..]);
+            }
+            _ if message.channel().is_some() => track.extend(&bytes),
+            _ => {
+                track.push(ESCAPE_EVENT);
+                write_variable_length(&mut track, bytes.len() as u32);
+                track.extend(&bytes);
+            }
+        }
+    }
+
+    write_variable_length(&mut track, 0);
+    track.extend(&[META_EVENT, META_END_OF_TRACK, 0]);
+
+    let mut file = vec![];
+
+    file.extend(HEADER_CHUNK);
+    file.extend(&6u32.to_be_bytes());
+    file.extend(&0u16.to_be_bytes()); // Format 0, a single track
+    file.extend(&1u16.to_be_bytes());
+    file.extend(&TICKS_PER_QUARTER_NOTE.to_be_bytes());
+
+    file.extend(TRACK_CHUNK);
+    file.extend(&(track.len() as u32).to_be_bytes());
+    file.extend(track);
+
+    fs::write(path, file)
+        .map_err(|e| MidiError::UnwritableMidiFile(path.to_path_buf(), e.to_string()))
+}
+
+/// Reads every message in a Standard MIDI File, with its time from the start
+///
+/// Tracks are merged, and tempo changes are followed. Meta events like track names are skipped.
+pub fn read_midi_file<P>(path: P) -> Result<Vec<(Duration, MidiMessage)>, MidiError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let unreadable = |reason: String| MidiError::UnreadableMidiFile(path.to_path_buf(), reason);
+
+    let bytes = fs::read(path).map_err(|e| unreadable(e.to_string()))?;
+
+    parse_midi_file(&bytes).map_err(unreadable)
+}
+
+#[derive(Clone, Debug)]
+enum TrackEvent {
+    Tempo(u32),
+    Message(MidiMessage),
+}
+
+fn parse_midi_file(bytes: &[u8]) -> Result<Vec<(Duration, MidiMessage)>, String> {
+    if !bytes.starts_with(HEADER_CHUNK) {
+        return Err("it's not a Standard MIDI File".to_string());
+    }
+
+    let mut reader = Reader { bytes, position: 0 };
+
+    let (_, header) = reader.chunk()?;
+
+    let mut header = Reader {
+        bytes: header,
+        position: 0,
+    };
+    let _format = header.u16()?;
+    let _track_count = header.u16()?;
+    let division = header.u16()?;
+
+    let mut events: Vec<(u64, TrackEvent)> = vec![];
+
+    while !reader.is_done() {
+        let (id, track) = reader.chunk()?;
+
+        // Unknown chunks are allowed, and meant to be skipped
+        if id == TRACK_CHUNK {
+            events.extend(parse_track(track)?);
+        }
+    }
+
+    // Stable, so events at the same tick keep their order within and across tracks
+    events.sort_by_key(|(tick, _)| *tick);
+
+    Ok(timed_messages(events, division))
+}
+
+fn parse_track(track: &[u8]) -> Result<Vec<(u64, TrackEvent)>, String> {
+    let mut reader = Reader {
+        bytes: track,
+        position: 0,
+    };
+
+    let mut events = vec![];
+    let mut tick: u64 = 0;
+    // Set by channel messages, and cancelled by meta and SysEx events
+    let mut running_status: Option<u8> = None;
+
+    while !reader.is_done() {
+        tick += u64::from(reader.variable_length()?);
+
+        let mut status = reader.byte()?;
+
+        // Running status: a data byte where the status should be repeats the last channel status
+        if status < 0x80 {
+            reader.position -= 1;
+            status = running_status.ok_or("a track starts with a data byte")?;
+        }
+
+        match status {
+            META_EVENT => {
+                running_status = None;
+
+                let kind = reader.byte()?;
+                let length = reader.variable_length()? as usize;
+                let data = reader.take(length)?;
+
+                match kind {
+                    META_TEMPO if length == 3 => {
+                        let tempo = u32::from_be_bytes([0, data[0], data[1], data[2]]);
+                        events.push((tick, TrackEvent::Tempo(tempo)));
+                    }
+                    META_END_OF_TRACK => break,
+                    _ => (),
+                }
+            }
+            SYSEX_EVENT => {
+                running_status = None;
+
+                let length = reader.variable_length()? as usize;
+
+                let mut bytes = vec![SYSEX_EVENT];
+                bytes.extend(reader.take(length)?);
+
+                events.push((tick, TrackEvent::Message(parse_message(&bytes))));
+            }
+            ESCAPE_EVENT => {
+                running_status = None;
+
+                let length = reader.variable_length()? as usize;
+                let bytes = reader.take(length)?;
+
+                if !bytes.is_empty() {
+                    events.push((tick, TrackEvent::Message(parse_message(bytes))));
+                }
+            }
+            _ if status >= SYSEX_EVENT => {
+                return Err(format!("unexpected status byte {:#04X}", status));
+            }
+            _ => {
+                running_status = Some(status);
+
+                let data_length = match status & 0xF0 {
+                    0xC0 | 0xD0 => 1,
+                    _ => 2,
+                };
+
+                let mut bytes = vec![status];
+                bytes.extend(reader.take(data_length)?);
+
+                events.push((tick, TrackEvent::Message(parse_message(&bytes))));
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+// Split SysEx and the like don't parse on their own, but are still worth passing along
+fn parse_message(bytes: &[u8]) -> MidiMessage {
+    MidiMessage::parse(bytes).unwrap_or_else(|| MidiMessage::Other(bytes.to_vec()))
+}
+
+// Puts times on events sorted by tick, following the tempo as it changes
+fn timed_messages(events: Vec<(u64, TrackEvent)>, division: u16) -> Vec<(Duration, MidiMessage)> {
+    let mut messages = vec![];
+
+    let mut tempo = u64::from(MICROS_PER_QUARTER_NOTE);
+    let mut tempo_start_tick: u64 = 0;
+    let mut tempo_start_micros: u64 = 0;
+
+    let micros_at = |tick: u64, tempo: u64, start_tick: u64, start_micros: u64| -> u64 {
+        if division & 0x8000 == 0 {
+            start_micros + (tick - start_tick) * tempo / u64::from(division.max(1))
+        } else {
+            // SMPTE timing: frames per second (stored negated) and ticks per frame
+            let frames_per_second = u64::from((-((division >> 8) as i8)) as u8);
+            let ticks_per_frame = u64::from(division & 0xFF);
+            let ticks_per_second = (frames_per_second * ticks_per_frame).max(1);
+
+            tick * 1_000_000 / ticks_per_second
+        }
+    };
+
+    for (tick, event) in events {
+        let micros = micros_at(tick, tempo, tempo_start_tick, tempo_start_micros);
+
+        match event {
+            TrackEvent::Tempo(new_tempo) => {
+                tempo_start_micros = micros;
+                tempo_start_tick = tick;
+                tempo = u64::from(new_tempo);
+            }
+            TrackEvent::Message(message) => {
+                messages.push((Duration::from_micros(micros), message));
+            }
+        }
+    }
+
+    messages
+}
+
+fn ticks_at(at: Duration) -> u32 {
+    let ticks =
+        at.as_micros() * u128::from(TICKS_PER_QUARTER_NOTE) / u128::from(MICROS_PER_QUARTER_NOTE);
+
+    ticks.min(u128::from(u32::MAX)) as u32
+}
+
+// Gaps too long for one delta are bridged by empty text events, which readers pass over
+fn write_delta(track: &mut Vec<u8>, mut delta: u32) {
+    while delta > MAX_VARIABLE_LENGTH {
+        write_variable_length(track, MAX_VARIABLE_LENGTH);
+        track.extend(&[META_EVENT, META_TEXT, 0]);
+        delta -= MAX_VARIABLE_LENGTH;
+    }
+
+    write_variable_length(track, delta);
+}
+
+// Seven bits per byte, most significant first, with the top bit set on all but the last. Values
+// above `MAX_VARIABLE_LENGTH` take a fifth byte, which files don't allow.
+fn write_variable_length(bytes: &mut Vec<u8>, value: u32) {
+    let mut groups = vec![(value & 0x7F) as u8];
+    let mut rest = value >> 7;
+
+    while rest > 0 {
+        groups.push((rest & 0x7F) as u8 | 0x80);
+        rest >>= 7;
+    }
+
+    bytes.extend(groups.iter().rev());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_done(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self.position + length;
+
+        let taken = self
+            .bytes
+            .get(self.position..end)
+            .ok_or("the file ends too soon")?;
+        self.position = end;
+
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // At most four bytes, as the format allows
+    fn variable_length(&mut self) -> Result<u32, String> {
+        let mut value: u32 = 0;
+
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | u32::from(byte & 0x7F);
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err("a variable-length number runs past four bytes".to_string())
+    }
+
+    fn chunk(&mut self) -> Result<(&'a [u8], &'a [u8]), String> {
+        let id = self.take(4)?;
+        let length = self.u32()? as usize;
+
+        Ok((id, self.take(length)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_support::scratch_dir;
+
+    fn note_on(note: u8, velocity: u8) -> MidiMessage {
+        MidiMessage::NoteOn {
+            channel: 0,
+            note,
+            velocity,
+        }
+    }
+
+    // A format 0 file around `track`, at 480 ticks to a quarter note
+    fn file_with_track(track: &[u8]) -> Vec<u8> {
+        let mut file = vec![];
+
+        file.extend(HEADER_CHUNK);
+        file.extend(&6u32.to_be_bytes());
+        file.extend(&[0, 0, 0, 1]);
+        file.extend(&TICKS_PER_QUARTER_NOTE.to_be_bytes());
+
+        file.extend(TRACK_CHUNK);
+        file.extend(&(track.len() as u32).to_be_bytes());
+        file.extend(track);
+
+        file
+    }
+
+    #[test]
+    fn written_files_read_back_the_same() {
+        let dir = scratch_dir("smf-round-trip");
+        let path = dir.join("round-trip.mid");
+
+        // Whole ticks, so that nothing is rounded
+        let events = vec![
+            (Duration::from_micros(0), note_on(60, 100)),
+            (Duration::from_micros(0), note_on(64, 100)),
+            (Duration::from_micros(500_000), MidiMessage::Clock),
+            (
+                Duration::from_micros(500_000),
+                MidiMessage::SysEx(vec![0x7E, 0x7F, 0x06, 0x01]),
+            ),
+            (Duration::from_secs(1), MidiMessage::SongPosition(16)),
+            (Duration::from_secs(1), MidiMessage::Start),
+            (
+                Duration::from_secs(2),
+                MidiMessage::ControlChange {
+                    channel: 3,
+                    controller: 43,
+                    value: 127,
+                },
+            ),
+            (
+                Duration::from_secs(3),
+                MidiMessage::NoteOff {
+                    channel: 0,
+                    note: 60,
+                    velocity: 64,
+                },
+            ),
+        ];
+
+        write_midi_file(&path, &events).unwrap();
+        let read = read_midi_file(&path).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(read, events);
+    }
+
+    #[test]
+    fn long_gaps_are_split_into_deltas_that_fit() {
+        let mut bytes = vec![];
+        write_variable_length(&mut bytes, MAX_VARIABLE_LENGTH);
+        assert_eq!(bytes, vec![0xFF, 0xFF, 0xFF, 0x7F]);
+
+        let mut track = vec![];
+        write_delta(&mut track, 2 * MAX_VARIABLE_LENGTH + 5);
+        assert_eq!(
+            track,
+            vec![
+                0xFF, 0xFF, 0xFF, 0x7F, META_EVENT, META_TEXT, 0, //
+                0xFF, 0xFF, 0xFF, 0x7F, META_EVENT, META_TEXT, 0, //
+                0x05,
+            ]
+        );
+
+        let dir = scratch_dir("smf-long-gap");
+        let path = dir.join("long-gap.mid");
+
+        // A gap of 400 hours, over twice what a single delta holds at this tempo
+        let events = vec![
+            (Duration::from_secs(0), note_on(60, 100)),
+            (Duration::from_secs(400 * 60 * 60), note_on(64, 100)),
+        ];
+
+        write_midi_file(&path, &events).unwrap();
+        let read = read_midi_file(&path).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(read, events);
+    }
+
+    #[test]
+    fn running_status_and_tempo_changes_are_followed() {
+        let track = [
+            // A note on, then two more by running status, a tick apart
+            0x00,
+            0x90,
+            60,
+            100, //
+            0x60,
+            62,
+            100, // 96 ticks on: a fifth of a quarter note at 120 BPM
+            0x00,
+            64,
+            100, //
+            // Half the tempo, which also cancels running status
+            0x00,
+            META_EVENT,
+            META_TEMPO,
+            3,
+            0x0F,
+            0x42,
+            0x40, //
+            0x83,
+            0x60,
+            0x80,
+            60,
+            0, // 480 ticks on, now a whole second
+            0x00,
+            ESCAPE_EVENT,
+            1,
+            0xF8, //
+            0x00,
+            META_EVENT,
+            META_END_OF_TRACK,
+            0,
+        ];
+
+        let dir = scratch_dir("smf-running-status");
+        let path = dir.join("running-status.mid");
+        fs::write(&path, file_with_track(&track)).unwrap();
+
+        let read = read_midi_file(&path).unwrap();
+
+        assert_eq!(
+            read,
+            vec![
+                (Duration::from_micros(0), note_on(60, 100)),
+                (Duration::from_micros(100_000), note_on(62, 100)),
+                (Duration::from_micros(100_000), note_on(64, 100)),
+                (
+                    Duration::from_micros(1_100_000),
+                    MidiMessage::NoteOff {
+                        channel: 0,
+                        note: 60,
+                        velocity: 0,
+                    }
+                ),
+                (Duration::from_micros(1_100_000), MidiMessage::Clock),
+            ]
+        );
+
+        // Written back out at the standard tempo, the times survive even though the ticks don't
+        write_midi_file(&path, &read).unwrap();
+        let reread = read_midi_file(&path).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(reread, read);
+    }
+
+    #[test]
+    fn data_bytes_without_a_status_are_rejected() {
+        let track = [0x00, 60, 100, 0x00, META_EVENT, META_END_OF_TRACK, 0];
+
+        assert!(parse_midi_file(&file_with_track(&track)).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use std::mem;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use crate::midi::{MidiDeviceEvent, MidiMessage, MidiReceiver, MidiSender};
+
+/// Somewhere MIDI comes from, be it a device, a recording or a test
+///
+/// Games hold a `Box<dyn MidiSource>` and poll it every frame, so that a `VirtualMidiSource` can
+/// stand in for the hardware wherever MIDI handling needs checking.
+///
+/// ## Examples
+///
+/// ```
+/// use sweet_turnips::midi::{MidiMessage, MidiSource, VirtualMidiSource};
+///
+/// let press = MidiMessage::NoteOn { channel: 0, note: 41, velocity: 127 };
+///
+/// let source = VirtualMidiSource::new();
+/// source.push(press.clone());
+///
+/// // Whatever the game holds, it polls the same way
+/// let mut source: Box<dyn MidiSource> = Box::new(source);
+/// assert_eq!(source.poll(), vec![press]);
+/// assert_eq!(source.poll(), vec![]);
+/// ```
+///
+/// Tangy Turnips keeps its MIDI handling apart from the window in its `Game`, whose tests play
+/// MIDI into it this way.
+pub trait MidiSource {
+    /// Everything that's arrived since the last poll, oldest first, each with the time it
+    /// arrived in microseconds
+    ///
+    /// Times count from whenever the source likes, such as when a device was connected, so only
+    /// the gaps between them mean anything.
+    fn poll_stamped(&mut self) -> Vec<(u64, MidiMessage)>;
+
+    /// Everything that's arrived since the last poll, oldest first
+    fn poll(&mut self) -> Vec<MidiMessage> {
+        self.poll_stamped()
+            .into_iter()
+            .map(|(_, message)| message)
+            .collect()
+    }
+
+    /// The name of the port this is, or stands in for, if any
+    fn port_name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether nothing more will arrive after what's waiting to be polled, as at the end of a
+    /// recording
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// Devices plugged in or pulled out since the last poll, for sources like `MidiWatcher`
+    /// that follow them
+    fn poll_events(&mut self) -> Vec<MidiDeviceEvent> {
+        vec![]
+    }
+}
+
+/// A `MidiSource` that gives back whatever's pushed into it, for tests and for games that make
+/// their own MIDI
+#[derive(Debug)]
+pub struct VirtualMidiSource {
+    tx: MidiSender,
+    rx: MidiReceiver,
+    events_tx: mpsc::Sender<MidiDeviceEvent>,
+    events_rx: mpsc::Receiver<MidiDeviceEvent>,
+    // Taken from events_rx, but not yet polled
+    pending_events: Vec<MidiDeviceEvent>,
+    port_name: Option<String>,
+    // What pushed messages are stamped from
+    created: Instant,
+}
+
+impl VirtualMidiSource {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        let (events_tx, events_rx) = mpsc::channel();
+
+        Self {
+            tx,
+            rx,
+            events_tx,
+            events_rx,
+            pending_events: vec![],
+            port_name: None,
+            created: Instant::now(),
+        }
+    }
+
+    /// Stands in for the device on `port_name`, so that its profile applies
+    pub fn with_port_name(mut self, port_name: &str) -> Self {
+        self.port_name = Some(port_name.to_string());
+        self
+    }
+
+    /// Queues `message` up for the next poll, stamped with how long the source has existed
+    pub fn push(&self, message: MidiMessage) {
+        self.push_at(self.created.elapsed().as_micros() as u64, message);
+    }
+
+    /// Queues `message` up for the next poll with a stamp of its own, in microseconds
+    pub fn push_at(&self, stamp: u64, message: MidiMessage) {
+        // Can't fail, as the receiving end is right here
+        let _ = self.tx.send((stamp, message));
+    }
+
+    /// Queues up a device coming or going, which changes `port_name` as of the next poll, as a
+    /// `MidiWatcher` would
+    pub fn push_event(&self, event: MidiDeviceEvent) {
+        let _ = self.events_tx.send(event);
+    }
+
+    /// Somewhere to push stamped messages from once the source has been handed over, such as
+    /// another thread
+    pub fn sender(&self) -> MidiSender {
+        self.tx.clone()
+    }
+
+    fn take_events(&mut self) {
+        for event in self.events_rx.try_iter() {
+            match &event {
+                MidiDeviceEvent::DeviceConnected(port_name) => {
+                    self.port_name = Some(port_name.clone())
+                }
+                MidiDeviceEvent::DeviceDisconnected(_) => self.port_name = None,
+                MidiDeviceEvent::NotFound(_) => (),
+            }
+
+            self.pending_events.push(event);
+        }
+    }
+}
+
+impl Default for VirtualMidiSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiSource for VirtualMidiSource {
+    fn poll_stamped(&mut self) -> Vec<(u64, MidiMessage)> {
+        self.take_events();
+
+        self.rx.try_iter().collect()
+    }
+
+    fn port_name(&self) -> Option<&str> {
+        self.port_name.as_deref()
+    }
+
+    fn poll_events(&mut self) -> Vec<MidiDeviceEvent> {
+        self.take_events();
+
+        mem::take(&mut self.pending_events)
+    }
+}
@@ -0,0 +1,165 @@
+use midir::MidiInputConnection;
+
+use std::mem;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::Duration;
+
+use crate::midi::ports::select_device_port;
+use crate::midi::{
+    input_port_names, open_input, MidiError, MidiMessage, MidiReceiver, MidiSender, MidiSource,
+};
+
+// How often the port list is checked for the device coming and going
+const PORT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A MIDI device turning up or going away, by the name of its port
+#[derive(Clone, Debug, PartialEq)]
+pub enum MidiDeviceEvent {
+    DeviceConnected(String),
+    DeviceDisconnected(String),
+    /// The port asked for can't be picked out, e.g. nothing matches its name, though watching
+    /// goes on in case it's plugged in. Only sent once until a device is connected.
+    NotFound(MidiError),
+}
+
+/// Listens to a MIDI input like `connect_to_midi`, but follows the device as it's unplugged and
+/// plugged back in
+///
+/// The input is chosen by `select_port` from `port_name`, except that without a name only a
+/// device will do, never a loopback like "Midi Through". If there's nothing to connect to yet,
+/// that's not an error: the watcher keeps looking, and connects once the device turns up. A name
+/// that doesn't pick out a port is reported through `poll_events`, but still watched for. This
+/// only fails if the system's MIDI backend can't be started.
+pub fn watch_midi(port_name: Option<&str>) -> Result<MidiWatcher, MidiError> {
+    input_port_names()?;
+
+    let (tx, rx) = mpsc::channel();
+    let (events_tx, events_rx) = mpsc::channel();
+    let (stop_tx, stop_rx) = mpsc::channel();
+    let (ready_tx, ready_rx) = mpsc::channel();
+
+    let wanted = port_name.map(|port_name| port_name.to_string());
+
+    thread::spawn(move || watch(wanted, tx, events_tx, ready_tx, stop_rx));
+
+    // Waits for the first attempt, so that a device plugged in at launch is ready straight away
+    let _ = ready_rx.recv();
+
+    let mut watcher = MidiWatcher {
+        rx,
+        events: events_rx,
+        pending_events: vec![],
+        port_name: None,
+        _stop: stop_tx,
+    };
+    watcher.take_events();
+
+    Ok(watcher)
+}
+
+/// A MIDI input that reconnects by itself, which stops watching when dropped
+///
+/// Besides the messages from whichever device is connected, its `poll_events` says when the
+/// device comes and goes, so that the game can show a notice or pause.
+#[derive(Debug)]
+pub struct MidiWatcher {
+    rx: MidiReceiver,
+    events: mpsc::Receiver<MidiDeviceEvent>,
+    // Taken from events, but not yet polled
+    pending_events: Vec<MidiDeviceEvent>,
+    // As of the last poll
+    port_name: Option<String>,
+    // Dropping this stops the watching thread, which lets go of the device
+    _stop: mpsc::Sender<()>,
+}
+
+impl MidiWatcher {
+    fn take_events(&mut self) {
+        for event in self.events.try_iter() {
+            match &event {
+                MidiDeviceEvent::DeviceConnected(port_name) => {
+                    self.port_name = Some(port_name.clone())
+                }
+                MidiDeviceEvent::DeviceDisconnected(_) => self.port_name = None,
+                MidiDeviceEvent::NotFound(_) => (),
+            }
+
+            self.pending_events.push(event);
+        }
+    }
+}
+
+impl MidiSource for MidiWatcher {
+    fn poll_stamped(&mut self) -> Vec<(u64, MidiMessage)> {
+        self.take_events();
+
+        self.rx.try_iter().collect()
+    }
+
+    fn port_name(&self) -> Option<&str> {
+        self.port_name.as_deref()
+    }
+
+    fn poll_events(&mut self) -> Vec<MidiDeviceEvent> {
+        self.take_events();
+
+        mem::take(&mut self.pending_events)
+    }
+}
+
+// Runs on the watcher's thread until the watcher's dropped, holding the connection
+fn watch(
+    wanted: Option<String>,
+    tx: MidiSender,
+    events: mpsc::Sender<MidiDeviceEvent>,
+    ready: mpsc::Sender<()>,
+    stop: mpsc::Receiver<()>,
+) {
+    let mut connected: Option<(MidiInputConnection<()>, String)> = None;
+    // Whether a port that can't be picked out has been reported, so that it isn't repeated every
+    // time the ports are checked
+    let mut reported = false;
+
+    loop {
+        // A backend that stops working partway through looks the same as every device going away
+        let names = input_port_names().unwrap_or_default();
+
+        connected = match connected.take() {
+            Some((connection, port_name)) if !names.contains(&port_name) => {
+                connection.close();
+                let _ = events.send(MidiDeviceEvent::DeviceDisconnected(port_name));
+
+                None
+            }
+            Some(connected) => Some(connected),
+            None => match open_input(wanted.as_deref(), select_device_port, tx.clone()) {
+                Ok((connection, port_name)) => {
+                    reported = false;
+                    let _ = events.send(MidiDeviceEvent::DeviceConnected(port_name.clone()));
+
+                    Some((connection, port_name))
+                }
+                // Nothing to connect to is expected, so it's just tried again later
+                Err(MidiError::NoPorts) => None,
+                Err(e) => {
+                    if !reported {
+                        reported = true;
+                        let _ = events.send(MidiDeviceEvent::NotFound(e));
+                    }
+
+                    None
+                }
+            },
+        };
+
+        let _ = ready.send(());
+
+        match stop.recv_timeout(PORT_POLL_INTERVAL) {
+            Err(RecvTimeoutError::Timeout) => (),
+            // Disconnected, since nothing is ever sent: the watcher's been dropped
+            _ => break,
+        }
+    }
+}
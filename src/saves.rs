@@ -0,0 +1,244 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_path_to_error;
+use serde_yaml;
+use serde_yaml::Value;
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::Migrations;
+
+const SAVE_EXTENSION: &str = "yaml";
+
+/// A game's save files, kept as named slots in its save directory
+///
+/// Saves carry a version just like config files, and older saves are upgraded by `migrations`
+/// as they're loaded. Unlike config files, they're never rewritten in place.
+///
+/// Values kept in a `SlotMap` shouldn't be unit structs, since those save as null and read back
+/// as empty slots.
+///
+/// ## Examples
+///
+/// ```ignore
+/// let saves = Saves::new(app_config.save_dir(), Migrations::new(SAVE_VERSION));
+///
+/// saves.save("quicksave", &world)?;
+/// let world: World = saves.load("quicksave")?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Saves {
+    dir: PathBuf,
+    migrations: Migrations,
+}
+
+impl Saves {
+    pub fn new<P>(dir: P, migrations: Migrations) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            migrations,
+        }
+    }
+
+    /// Where the save named `slot` lives, e.g. `<save dir>/quicksave.yaml`
+    pub fn path(&self, slot: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", slot, SAVE_EXTENSION))
+    }
+
+    pub fn exists(&self, slot: &str) -> bool {
+        self.path(slot).is_file()
+    }
+
+    /// Writes `state` to the slot, replacing whatever was there
+    pub fn save<S>(&self, slot: &str, state: &S) -> Result<PathBuf, SaveError>
+    where
+        S: Serialize,
+    {
+        let path = self.path(slot);
+
+        let source = serde_yaml::to_string(&self.migrations.versioned(state))
+            .map_err(|e| SaveError::Unwritable(path.clone(), e.to_string()))?;
+
+        fs::create_dir_all(&self.dir).map_err(|e| SaveError::Io(self.dir.clone(), e))?;
+
+        // Written beside the real file first, so a crash mid-save can't wreck the last good one
+        let partial_path = path.with_extension(format!("{}.partial", SAVE_EXTENSION));
+
+        fs::write(&partial_path, source).map_err(|e| SaveError::Io(partial_path.clone(), e))?;
+        fs::rename(&partial_path, &path).map_err(|e| SaveError::Io(path.clone(), e))?;
+
+        Ok(path)
+    }
+
+    pub fn load<D>(&self, slot: &str) -> Result<D, SaveError>
+    where
+        D: DeserializeOwned,
+    {
+        let path = self.path(slot);
+
+        if !path.is_file() {
+            return Err(SaveError::Missing(path));
+        }
+
+        let source = fs::read_to_string(&path).map_err(|e| SaveError::Io(path.clone(), e))?;
+
+        let mut state: Value = serde_yaml::from_str(&source)
+            .map_err(|e| SaveError::Unreadable(path.clone(), e.to_string()))?;
+
+        self.migrations
+            .upgrade(&mut state)
+            .map_err(|e| SaveError::Unreadable(path.clone(), e))?;
+
+        serde_path_to_error::deserialize(state).map_err(|e| {
+            let message = format!("{}: {}", e.path(), e.inner());
+            SaveError::Unreadable(path.clone(), message)
+        })
+    }
+}
+
+/// Why a save couldn't be written or read back
+#[derive(Debug)]
+pub enum SaveError {
+    Missing(PathBuf),
+    Io(PathBuf, io::Error),
+    Unwritable(PathBuf, String),
+    Unreadable(PathBuf, String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Missing(path) => write!(f, "There's no save at {}", path.display()),
+            SaveError::Io(path, e) => write!(f, "Could not access {}: {}", path.display(), e),
+            SaveError::Unwritable(path, reason) => {
+                write!(f, "Could not save to {}: {}", path.display(), reason)
+            }
+            SaveError::Unreadable(path, reason) => {
+                write!(f, "Could not load {}: {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+impl Error for SaveError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde::Deserialize;
+    use slotmap::{new_key_type, SecondaryMap, SlotMap};
+
+    use crate::test_support::scratch_dir;
+
+    new_key_type! {
+        struct ThingKey;
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Thing {
+        health: u8,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    enum TestEvent {
+        Moved(ThingKey),
+        Removed(ThingKey),
+    }
+
+    // Shaped like the example games' worlds, with keys held in events and secondary maps
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct TestWorld {
+        events: Vec<TestEvent>,
+        things: SlotMap<ThingKey, Thing>,
+        names: SecondaryMap<ThingKey, String>,
+        ticks: usize,
+    }
+
+    // SlotMap can't be compared directly, so its keys and values are compared in order
+    impl PartialEq for TestWorld {
+        fn eq(&self, other: &Self) -> bool {
+            self.events == other.events
+                && self.things.iter().eq(other.things.iter())
+                && self.names == other.names
+                && self.ticks == other.ticks
+        }
+    }
+
+    #[test]
+    fn worlds_come_back_with_their_keys_events_and_ticks() {
+        let dir = scratch_dir("save-round-trip");
+        let saves = Saves::new(&dir, Migrations::new(1));
+
+        let mut things = SlotMap::with_key();
+        let mut names = SecondaryMap::new();
+
+        let first = things.insert(Thing { health: 3 });
+        let removed = things.insert(Thing { health: 1 });
+        let last = things.insert(Thing { health: 2 });
+        names.insert(first, "first".to_string());
+        names.insert(last, "last".to_string());
+        things.remove(removed);
+
+        let mut world = TestWorld {
+            events: vec![TestEvent::Moved(last), TestEvent::Removed(first)],
+            things,
+            names,
+            ticks: 42,
+        };
+
+        saves.save("quicksave", &world).unwrap();
+        let mut loaded: TestWorld = saves.load("quicksave").unwrap();
+
+        assert_eq!(loaded, world);
+        assert_eq!(loaded.things.get(removed), None);
+
+        // The freed slot is handed out again just as it would have been before saving
+        let added = world.things.insert(Thing { health: 4 });
+        let added_after_loading = loaded.things.insert(Thing { health: 4 });
+
+        assert_eq!(added_after_loading, added);
+        assert_eq!(loaded, world);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn saves_are_upgraded_as_they_load() {
+        // Version 2 started every save's clock over
+        fn reset_ticks(save: &mut Value) -> Result<(), String> {
+            save["ticks"] = 0.into();
+            Ok(())
+        }
+
+        let dir = scratch_dir("save-upgrade");
+
+        let mut things = SlotMap::with_key();
+        let key = things.insert(Thing { health: 3 });
+
+        let world = TestWorld {
+            events: vec![TestEvent::Moved(key)],
+            things,
+            names: SecondaryMap::new(),
+            ticks: 42,
+        };
+
+        Saves::new(&dir, Migrations::new(1))
+            .save("old", &world)
+            .unwrap();
+
+        let saves = Saves::new(&dir, Migrations::new(2).migration(1, reset_ticks));
+        let loaded: TestWorld = saves.load("old").unwrap();
+
+        assert_eq!(loaded, TestWorld { ticks: 0, ..world });
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
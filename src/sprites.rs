@@ -1,22 +1,37 @@
+mod text;
+
 use ggez;
+use ggez::filesystem;
 use ggez::graphics;
 use ggez::graphics::DrawParam;
 use ggez::nalgebra as na;
-use ggez::{Context, GameResult};
+use ggez::{Context, GameError, GameResult};
+
+use crate::{AppConfig, AppConfigError};
+
+use serde::Deserialize;
+use serde_yaml;
+
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use ggez::graphics::spritebatch::SpriteBatch;
 
 pub use ggez::graphics::Color;
 
-// TODO: Make these constants into parameters wherever practical
-const GRID_WIDTH: u8 = 8;
-const GRID_HEIGHT: u8 = 8;
-
-const SPRITE_GRID_LENGTH: usize = (GRID_WIDTH * GRID_HEIGHT) as usize;
+pub use text::ParseSpriteGridError;
 
 pub const SPRITE_SCALE: f32 = 4.0;
 pub const SPRITE_SIZE: f32 = 8.0;
 
+/// A position along one axis of the sprite grid
+pub type GridUnit = u16;
+
+/// An (x, y) position on the sprite grid
+pub type Coords = (GridUnit, GridUnit);
+
 /// A sprite that can be rendered to the sprite grid
 ///
 /// Use helper functions like `turnip` to generate a specific sprite.
@@ -25,10 +40,10 @@ pub const SPRITE_SIZE: f32 = 8.0;
 ///
 /// ## Examples
 ///
-/// ```
+/// ```ignore
 /// let purpleish = Color { r: 0.5, g: 0.1, b: 0.7, a: 1.0 };
 /// let sprite = Sprite::turnip().colored(purpleish);
-/// sprite_grid.render_sprite_at(sprite, x, y);
+/// sprite_grid.render_sprite_at(sprite, x, y)?;
 /// ```
 ///
 /// ## TODO
@@ -99,11 +114,11 @@ impl GridParam {
         GridParam { draw_param }
     }
 
-    fn at(&self, x: u8, y: u8) -> Self {
+    fn at(&self, x: GridUnit, y: GridUnit, sprite_size: f32) -> Self {
         let x = x as f32;
         let y = y as f32;
 
-        let point = na::Point2::new(SPRITE_SIZE * x, SPRITE_SIZE * y);
+        let point = na::Point2::new(sprite_size * x, sprite_size * y);
 
         GridParam {
             draw_param: self.draw_param.dest(point),
@@ -144,7 +159,178 @@ enum SpriteType {
     Empty,
 }
 
-pub struct SpriteContext {
+/// A complete set of sprite images that `SpriteContext` can switch to at runtime
+///
+/// Games keep asking for `Sprite::turnip()` and friends; the active theme decides how they look.
+///
+/// Extra themes are discovered under `resources/themes/<name>/`, each with the numbered sprite
+/// images (`1.png` through `21.png`) and a `theme.yaml` like so:
+///
+/// ```yaml
+/// sprite_size: 16.0
+/// inverted: false
+/// ```
+///
+/// A theme can also come from a single sprite sheet ("atlas") instead, with the sprites laid out
+/// left to right, top to bottom, in the same order as the numbered images. Add `atlas: SHEET.png`
+/// to `theme.yaml` to use one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteTheme {
+    pub name: String,
+    pub directory: String,
+    pub atlas: Option<String>,
+    pub sprite_size: f32,
+    pub inverted: bool,
+}
+
+impl SpriteTheme {
+    pub fn new<S, D>(name: S, directory: D, sprite_size: f32) -> Self
+    where
+        S: AsRef<str>,
+        D: AsRef<str>,
+    {
+        Self {
+            name: name.as_ref().to_string(),
+            directory: directory.as_ref().to_string(),
+            atlas: None,
+            sprite_size,
+            inverted: false,
+        }
+    }
+
+    /// A theme cut out of a single sprite sheet, with `sprite_size` square cells
+    pub fn from_atlas<S, A>(name: S, atlas: A, sprite_size: f32) -> Self
+    where
+        S: AsRef<str>,
+        A: AsRef<str>,
+    {
+        Self {
+            atlas: Some(atlas.as_ref().to_string()),
+            ..Self::new(name, "", sprite_size)
+        }
+    }
+
+    /// The original Bitter Jam 2020 tiles
+    pub fn bitter_jam() -> Self {
+        Self::new(DEFAULT_THEME_NAME, "/separate", SPRITE_SIZE).inverted(true)
+    }
+
+    /// Marks the source images as black-on-white, which the sprite context flips on load
+    pub fn inverted(self, inverted: bool) -> Self {
+        Self { inverted, ..self }
+    }
+
+    fn scale(&self, window_scale: f32) -> f32 {
+        window_scale * SPRITE_SIZE / self.sprite_size
+    }
+}
+
+pub const DEFAULT_THEME_NAME: &str = "bitter-jam";
+
+const THEMES_DIR: &str = "/themes";
+
+#[derive(Debug, Deserialize)]
+struct ThemeManifest {
+    sprite_size: f32,
+    #[serde(default)]
+    inverted: bool,
+    #[serde(default)]
+    atlas: Option<String>,
+}
+
+enum SpriteSource {
+    Directory(String),
+    Atlas {
+        pixels: Vec<u8>,
+        width: usize,
+        height: usize,
+    },
+}
+
+impl SpriteSource {
+    fn load(ctx: &mut Context, theme: &SpriteTheme) -> GameResult<Self> {
+        let atlas_path = match &theme.atlas {
+            Some(atlas_path) => atlas_path,
+            None => return Ok(SpriteSource::Directory(theme.directory.clone())),
+        };
+
+        let atlas = graphics::Image::new(ctx, atlas_path)?;
+
+        // `finalize()` has already checked atlases lying loose in a resource directory, but not
+        // ones packed into zips or embedded in the binary
+
+        let atlas_dimensions = (u32::from(atlas.width()), u32::from(atlas.height()));
+        let size = theme.sprite_size as u32;
+
+        if size == 0 || atlas_dimensions.0 % size != 0 || atlas_dimensions.1 % size != 0 {
+            return Err(AppConfigError::SpriteSizeMismatch {
+                atlas: atlas_path.clone(),
+                atlas_dimensions,
+                sprite_size: theme.sprite_size,
+            }
+            .into());
+        }
+
+        Ok(SpriteSource::Atlas {
+            pixels: atlas.to_rgba8(ctx)?,
+            width: atlas.width() as usize,
+            height: atlas.height() as usize,
+        })
+    }
+
+    fn image(
+        &self,
+        ctx: &mut Context,
+        sprite_size: f32,
+        sprite_number: usize,
+    ) -> GameResult<graphics::Image> {
+        let (pixels, width, height) = match self {
+            SpriteSource::Directory(directory) => {
+                let filepath = format!("{}/{}.png", directory, sprite_number);
+                return graphics::Image::new(ctx, filepath);
+            }
+            SpriteSource::Atlas {
+                pixels,
+                width,
+                height,
+            } => (pixels, *width, *height),
+        };
+
+        let size = sprite_size as usize;
+
+        let no_room = || {
+            GameError::ResourceLoadError(format!(
+                "Sprite atlas has no room for sprite {} at {}x{}",
+                sprite_number, size, size
+            ))
+        };
+
+        if size == 0 || size > width {
+            return Err(no_room());
+        }
+
+        let columns = width / size;
+        let cell = sprite_number - 1;
+
+        let (left, top) = ((cell % columns) * size, (cell / columns) * size);
+
+        if top + size > height {
+            return Err(no_room());
+        }
+
+        let mut cell_pixels: Vec<u8> = Vec::with_capacity(size * size * 4);
+
+        for y in top..(top + size) {
+            let row_start = (y * width + left) * 4;
+            cell_pixels.extend_from_slice(&pixels[row_start..(row_start + size * 4)]);
+        }
+
+        graphics::Image::from_rgba8(ctx, size as u16, size as u16, &cell_pixels)
+    }
+}
+
+struct SpriteSet {
+    theme: SpriteTheme,
     curves: SpriteBatch,
     lines: SpriteBatch,
     crosses: SpriteBatch,
@@ -168,41 +354,46 @@ pub struct SpriteContext {
     altars: SpriteBatch,
 }
 
-impl SpriteContext {
-    pub fn new(ctx: &mut Context) -> GameResult<Self> {
-        let sprite_context = Self {
-            curves: prep_sprites(ctx, 1)?,
-            lines: prep_sprites(ctx, 2)?,
-            crosses: prep_sprites(ctx, 3)?,
-            corner_triangles: prep_sprites(ctx, 4)?,
-            small_circles: prep_sprites(ctx, 5)?,
-            big_circles: prep_sprites(ctx, 6)?,
-            diamonds: prep_sprites(ctx, 7)?,
-            dashes: prep_sprites(ctx, 8)?,
-            dots: prep_sprites(ctx, 9)?,
-            booms: prep_sprites(ctx, 10)?,
-            skulls: prep_sprites(ctx, 11)?,
-            side_triangles: prep_sprites(ctx, 12)?,
-            ships: prep_sprites(ctx, 13)?,
-            hearts: prep_sprites(ctx, 14)?,
-            cursors: prep_sprites(ctx, 15)?,
-            turnips: prep_sprites(ctx, 16)?,
-            squids: prep_sprites(ctx, 17)?,
-            lizards: prep_sprites(ctx, 18)?,
-            balls: prep_sprites(ctx, 19)?,
-            crabs: prep_sprites(ctx, 20)?,
-            altars: prep_sprites(ctx, 21)?,
+impl SpriteSet {
+    fn new(ctx: &mut Context, theme: SpriteTheme) -> GameResult<Self> {
+        let source = SpriteSource::load(ctx, &theme)?;
+        let theme = &theme;
+        let source = &source;
+
+        let sprite_set = Self {
+            curves: prep_sprites(ctx, theme, source, 1)?,
+            lines: prep_sprites(ctx, theme, source, 2)?,
+            crosses: prep_sprites(ctx, theme, source, 3)?,
+            corner_triangles: prep_sprites(ctx, theme, source, 4)?,
+            small_circles: prep_sprites(ctx, theme, source, 5)?,
+            big_circles: prep_sprites(ctx, theme, source, 6)?,
+            diamonds: prep_sprites(ctx, theme, source, 7)?,
+            dashes: prep_sprites(ctx, theme, source, 8)?,
+            dots: prep_sprites(ctx, theme, source, 9)?,
+            booms: prep_sprites(ctx, theme, source, 10)?,
+            skulls: prep_sprites(ctx, theme, source, 11)?,
+            side_triangles: prep_sprites(ctx, theme, source, 12)?,
+            ships: prep_sprites(ctx, theme, source, 13)?,
+            hearts: prep_sprites(ctx, theme, source, 14)?,
+            cursors: prep_sprites(ctx, theme, source, 15)?,
+            turnips: prep_sprites(ctx, theme, source, 16)?,
+            squids: prep_sprites(ctx, theme, source, 17)?,
+            lizards: prep_sprites(ctx, theme, source, 18)?,
+            balls: prep_sprites(ctx, theme, source, 19)?,
+            crabs: prep_sprites(ctx, theme, source, 20)?,
+            altars: prep_sprites(ctx, theme, source, 21)?,
+            theme: theme.clone(),
         };
 
-        Ok(sprite_context)
+        Ok(sprite_set)
     }
 
-    fn draw_all_sprites(&mut self, ctx: &mut Context) -> GameResult {
+    fn draw_all_sprites(&mut self, ctx: &mut Context, window_scale: f32) -> GameResult {
+        let scale = self.theme.scale(window_scale);
+
         let origin_param = graphics::DrawParam::new()
             .dest(na::Point2::new(0.0, 0.0))
-            .scale(na::Vector2::new(SPRITE_SCALE, SPRITE_SCALE));
-
-        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+            .scale(na::Vector2::new(scale, scale));
 
         graphics::draw(ctx, &self.lines, origin_param)?;
         graphics::draw(ctx, &self.curves, origin_param)?;
@@ -248,17 +439,21 @@ impl SpriteContext {
         self.crabs.clear();
         self.altars.clear();
 
-        graphics::present(ctx)?;
-
         Ok(())
     }
 
-    fn render_sprite_at(&mut self, sprite_type: SpriteType, gp: GridParam, x: u8, y: u8) {
+    fn render_sprite_at(
+        &mut self,
+        sprite_type: SpriteType,
+        gp: GridParam,
+        x: GridUnit,
+        y: GridUnit,
+    ) {
         if sprite_type == SpriteType::Empty {
             return;
         }
 
-        let gp = gp.at(x, y);
+        let gp = gp.at(x, y, self.theme.sprite_size);
 
         match sprite_type {
             SpriteType::Ship => self.ships.add(gp.draw_param),
@@ -272,16 +467,160 @@ impl SpriteContext {
             _ => unimplemented!("Unimplemented sprite type: {:?}", sprite_type),
         };
     }
+}
+
+/// Holds every registered sprite theme and renders sprite grids with the active one
+pub struct SpriteContext {
+    sprite_sets: Vec<SpriteSet>,
+    active_set: usize,
+    window_scale: f32,
+    overlay: Option<graphics::Text>,
+    // Hidden again once the time's up
+    notice: Option<(graphics::Text, Instant)>,
+}
+
+impl SpriteContext {
+    /// Loads the Bitter Jam tiles plus any themes found under `resources/themes/`
+    pub fn new(ctx: &mut Context) -> GameResult<Self> {
+        let mut sprite_context = Self {
+            sprite_sets: vec![SpriteSet::new(ctx, SpriteTheme::bitter_jam())?],
+            active_set: 0,
+            window_scale: SPRITE_SCALE,
+            overlay: None,
+            notice: None,
+        };
+
+        for theme in discover_themes(ctx)? {
+            sprite_context.register_theme(ctx, theme)?;
+        }
+
+        Ok(sprite_context)
+    }
+
+    /// Like `new`, but scaled to the app's window and starting out with the app's sprite theme
+    pub fn from_app_config(ctx: &mut Context, app_config: &AppConfig) -> GameResult<Self> {
+        let mut sprite_context = Self::new(ctx)?;
+
+        sprite_context.window_scale = app_config.window_scale();
+
+        let theme = app_config.sprite_theme();
+
+        if !sprite_context.use_theme(&theme.name) {
+            sprite_context.register_theme(ctx, theme.clone())?;
+            sprite_context.use_theme(&theme.name);
+        }
+
+        Ok(sprite_context)
+    }
+
+    /// Loads a theme's sprites, replacing any previously registered theme of the same name
+    pub fn register_theme(&mut self, ctx: &mut Context, theme: SpriteTheme) -> GameResult {
+        let sprite_set = SpriteSet::new(ctx, theme)?;
+
+        let existing = self
+            .sprite_sets
+            .iter()
+            .position(|s| s.theme.name == sprite_set.theme.name);
+
+        match existing {
+            Some(i) => self.sprite_sets[i] = sprite_set,
+            None => self.sprite_sets.push(sprite_set),
+        }
+
+        Ok(())
+    }
+
+    /// Switches to the named theme, returning `false` if no such theme is registered
+    pub fn use_theme<S>(&mut self, name: S) -> bool
+    where
+        S: AsRef<str>,
+    {
+        let found = self
+            .sprite_sets
+            .iter()
+            .position(|s| s.theme.name == name.as_ref());
+
+        if let Some(i) = found {
+            self.active_set = i;
+        }
+
+        found.is_some()
+    }
+
+    /// Switches to the next registered theme, wrapping around to the first
+    pub fn cycle_theme(&mut self) {
+        self.active_set = (self.active_set + 1) % self.sprite_sets.len();
+    }
+
+    pub fn active_theme(&self) -> &SpriteTheme {
+        &self.sprite_sets[self.active_set].theme
+    }
+
+    pub fn theme_names(&self) -> Vec<&str> {
+        self.sprite_sets
+            .iter()
+            .map(|s| s.theme.name.as_str())
+            .collect()
+    }
+
+    fn draw_all_sprites(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+
+        let window_scale = self.window_scale;
+
+        self.sprite_sets[self.active_set].draw_all_sprites(ctx, window_scale)?;
+
+        if let Some(overlay) = &self.overlay {
+            draw_text_box(ctx, overlay, TextPlacement::Overlay)?;
+        }
+
+        if let Some((_, hide_at)) = &self.notice {
+            if Instant::now() >= *hide_at {
+                self.notice = None;
+            }
+        }
+
+        if let Some((notice, _)) = &self.notice {
+            draw_text_box(ctx, notice, TextPlacement::Notice)?;
+        }
+
+        graphics::present(ctx)?;
+
+        Ok(())
+    }
+
+    /// Shows a message over the sprite grid until `clear_overlay` is called, e.g. config errors
+    pub fn show_overlay<S>(&mut self, message: S)
+    where
+        S: AsRef<str>,
+    {
+        self.overlay = Some(graphics::Text::new(message.as_ref()));
+    }
+
+    pub fn clear_overlay(&mut self) {
+        self.overlay = None;
+    }
+
+    /// Briefly shows a message, e.g. "Saved", leaving any overlay where it is
+    pub fn show_notice<S>(&mut self, message: S)
+    where
+        S: AsRef<str>,
+    {
+        let text = graphics::Text::new(message.as_ref());
+        self.notice = Some((text, Instant::now() + NOTICE_DURATION));
+    }
 
     pub fn render_sprite_grid(&mut self, sprite_grid: SpriteGrid, ctx: &mut Context) -> GameResult {
-        for x in 0..GRID_WIDTH {
-            for y in 0..GRID_HEIGHT {
+        let sprite_set = &mut self.sprite_sets[self.active_set];
+
+        for x in 0..sprite_grid.width {
+            for y in 0..sprite_grid.height {
                 let sprite_type = sprite_grid.sprite_type_at(x, y);
                 let grid_param = match sprite_grid.grid_param_at(x, y) {
                     Some(gp) => gp,
                     None => GridParam::new(),
                 };
-                self.render_sprite_at(sprite_type, grid_param, x, y);
+                sprite_set.render_sprite_at(sprite_type, grid_param, x, y);
             }
         }
 
@@ -289,48 +628,246 @@ impl SpriteContext {
     }
 }
 
+const OVERLAY_MARGIN: f32 = 8.0;
+
+// How long a notice stays up
+const NOTICE_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum TextPlacement {
+    Overlay,
+    Notice,
+}
+
+// Wraps the text to the window and puts a dark backdrop behind it so it stays readable.
+// Overlays go at the top of the window and notices at the bottom, so both can be seen at once.
+fn draw_text_box(ctx: &mut Context, text: &graphics::Text, placement: TextPlacement) -> GameResult {
+    let screen = graphics::screen_coordinates(ctx);
+
+    let mut text = text.clone();
+    text.set_bounds(
+        na::Point2::new(screen.w - OVERLAY_MARGIN * 4.0, screen.h),
+        graphics::Align::Left,
+    );
+
+    let (text_width, text_height) = text.dimensions(ctx);
+    let box_height = text_height as f32 + OVERLAY_MARGIN * 2.0;
+
+    let (top, color) = match placement {
+        TextPlacement::Overlay => (OVERLAY_MARGIN, [1.0, 0.4, 0.4, 1.0]),
+        TextPlacement::Notice => (screen.h - OVERLAY_MARGIN - box_height, [1.0, 1.0, 1.0, 1.0]),
+    };
+
+    let backdrop = graphics::Mesh::new_rectangle(
+        ctx,
+        graphics::DrawMode::fill(),
+        graphics::Rect::new(
+            OVERLAY_MARGIN,
+            top,
+            text_width as f32 + OVERLAY_MARGIN * 2.0,
+            box_height,
+        ),
+        [0.0, 0.0, 0.0, 0.8].into(),
+    )?;
+
+    graphics::draw(ctx, &backdrop, DrawParam::new())?;
+
+    let text_position = na::Point2::new(OVERLAY_MARGIN * 2.0, top + OVERLAY_MARGIN);
+    graphics::draw(
+        ctx,
+        &text,
+        DrawParam::new().dest(text_position).color(color.into()),
+    )?;
+
+    Ok(())
+}
+
+fn discover_themes(ctx: &mut Context) -> GameResult<Vec<SpriteTheme>> {
+    let mut themes = vec![];
+
+    if !filesystem::is_dir(ctx, THEMES_DIR) {
+        return Ok(themes);
+    }
+
+    let mut theme_dirs: Vec<PathBuf> = filesystem::read_dir(ctx, THEMES_DIR)?
+        .filter(|path| filesystem::is_dir(ctx, path))
+        .collect();
+
+    theme_dirs.sort();
+
+    for theme_dir in theme_dirs {
+        let manifest_path = theme_dir.join("theme.yaml");
+
+        if !filesystem::is_file(ctx, &manifest_path) {
+            continue;
+        }
+
+        let manifest: ThemeManifest =
+            serde_yaml::from_reader(filesystem::open(ctx, &manifest_path)?)
+                .map_err(|e| GameError::ResourceLoadError(format!("{:?}: {}", manifest_path, e)))?;
+
+        if let Some(theme) = theme_from_manifest(&theme_dir, manifest) {
+            themes.push(theme);
+        }
+    }
+
+    Ok(themes)
+}
+
+// Named after its directory, with its sprites inside it
+fn theme_from_manifest(theme_dir: &Path, manifest: ThemeManifest) -> Option<SpriteTheme> {
+    let name = theme_dir.file_name()?.to_string_lossy().to_string();
+
+    let directory = theme_dir.to_string_lossy();
+
+    let theme = match manifest.atlas {
+        Some(atlas) => {
+            let atlas_path = format!("{}/{}", directory, atlas);
+            SpriteTheme::from_atlas(name, atlas_path, manifest.sprite_size)
+        }
+        None => SpriteTheme::new(name, directory, manifest.sprite_size),
+    };
+
+    Some(theme.inverted(manifest.inverted))
+}
+
+/// The sprites to render on each cell of the screen
+///
+/// A grid can be written out as a text picture with `to_string()` and read back with `parse()`,
+/// which makes for handy snapshot assertions without a graphics context:
+///
+/// ```ignore
+/// let expected: SpriteGrid = "[sprites]\nOOOOOOOO\n...".parse()?;
+/// assert_eq!(render::sprite_grid_from_world(&world), expected);
+/// ```
 pub struct SpriteGrid {
-    sprite_types: [SpriteType; SPRITE_GRID_LENGTH],
-    grid_params: [Option<GridParam>; SPRITE_GRID_LENGTH],
+    width: GridUnit,
+    height: GridUnit,
+    sprite_types: Vec<SpriteType>,
+    grid_params: Vec<Option<GridParam>>,
+}
+
+/// Returned by the bounds-checked `SpriteGrid` accessors for cells that are off the grid
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OutOfBoundsError {
+    pub coords: Coords,
+    pub dimensions: (GridUnit, GridUnit),
 }
 
-fn index(x: u8, y: u8) -> usize {
-    (y * GRID_WIDTH + x) as usize
+impl fmt::Display for OutOfBoundsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "({}, {}) is outside of the {}x{} sprite grid",
+            self.coords.0, self.coords.1, self.dimensions.0, self.dimensions.1
+        )
+    }
+}
+
+impl Error for OutOfBoundsError {}
+
+impl From<OutOfBoundsError> for GameError {
+    fn from(e: OutOfBoundsError) -> Self {
+        GameError::RenderError(e.to_string())
+    }
 }
 
 impl SpriteGrid {
-    pub fn new() -> Self {
+    pub fn new(width: GridUnit, height: GridUnit) -> Self {
+        let length = width as usize * height as usize;
+
         SpriteGrid {
-            sprite_types: [SpriteType::Empty; SPRITE_GRID_LENGTH],
-            grid_params: [None; SPRITE_GRID_LENGTH],
+            width,
+            height,
+            sprite_types: vec![SpriteType::Empty; length],
+            grid_params: vec![None; length],
         }
     }
 
-    pub fn render_sprite_at(&mut self, sprite: Sprite, x: u8, y: u8) {
-        self.sprite_types[index(x, y)] = sprite.sprite_type;
-        self.grid_params[index(x, y)] = Some(sprite.grid_param.at(x, y));
+    pub fn dimensions(&self) -> (GridUnit, GridUnit) {
+        (self.width, self.height)
     }
 
-    fn sprite_type_at(&self, x: u8, y: u8) -> SpriteType {
-        self.sprite_types[index(x, y)]
+    pub fn contains(&self, x: GridUnit, y: GridUnit) -> bool {
+        x < self.width && y < self.height
     }
 
-    fn grid_param_at(&self, x: u8, y: u8) -> Option<GridParam> {
-        self.grid_params[index(x, y)]
+    fn index(&self, x: GridUnit, y: GridUnit) -> Option<usize> {
+        if self.contains(x, y) {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+
+    fn out_of_bounds(&self, x: GridUnit, y: GridUnit) -> OutOfBoundsError {
+        OutOfBoundsError {
+            coords: (x, y),
+            dimensions: self.dimensions(),
+        }
     }
-}
 
-fn prep_sprites(ctx: &mut Context, sprite_number: usize) -> GameResult<SpriteBatch> {
-    let filepath = format!("/separate/{}.png", sprite_number);
+    /// Renders the sprite at the given cell, failing if the cell is off the grid
+    pub fn render_sprite_at(
+        &mut self,
+        sprite: Sprite,
+        x: GridUnit,
+        y: GridUnit,
+    ) -> Result<(), OutOfBoundsError> {
+        let i = self.index(x, y).ok_or_else(|| self.out_of_bounds(x, y))?;
 
-    let original = graphics::Image::new(ctx, filepath).unwrap();
-    let inverted = invert(ctx, &original)?;
+        self.sprite_types[i] = sprite.sprite_type;
+        self.grid_params[i] = Some(sprite.grid_param.at(x, y, SPRITE_SIZE));
 
-    let mut inverted_batch = SpriteBatch::new(inverted);
-    inverted_batch.set_filter(ggez::graphics::FilterMode::Nearest);
+        Ok(())
+    }
+
+    /// The sprite rendered at the given cell, if any
+    pub fn sprite_at(&self, x: GridUnit, y: GridUnit) -> Result<Option<Sprite>, OutOfBoundsError> {
+        let i = self.index(x, y).ok_or_else(|| self.out_of_bounds(x, y))?;
+
+        let sprite = match self.sprite_types[i] {
+            SpriteType::Empty => None,
+            sprite_type => Some(Sprite {
+                sprite_type,
+                grid_param: self.grid_params[i].unwrap_or_else(GridParam::new),
+            }),
+        };
+
+        Ok(sprite)
+    }
+
+    fn sprite_type_at(&self, x: GridUnit, y: GridUnit) -> SpriteType {
+        match self.index(x, y) {
+            Some(i) => self.sprite_types[i],
+            None => SpriteType::Empty,
+        }
+    }
+
+    fn grid_param_at(&self, x: GridUnit, y: GridUnit) -> Option<GridParam> {
+        self.index(x, y).and_then(|i| self.grid_params[i])
+    }
+}
 
-    // Source images are "inverted" by our standard, hence the reverse positioning
-    Ok(inverted_batch)
+fn prep_sprites(
+    ctx: &mut Context,
+    theme: &SpriteTheme,
+    source: &SpriteSource,
+    sprite_number: usize,
+) -> GameResult<SpriteBatch> {
+    let original = source.image(ctx, theme.sprite_size, sprite_number)?;
+
+    // Source images may be "inverted" by our standard, hence the reverse positioning
+    let image = if theme.inverted {
+        invert(ctx, &original)?
+    } else {
+        original
+    };
+
+    let mut batch = SpriteBatch::new(image);
+    batch.set_filter(ggez::graphics::FilterMode::Nearest);
+
+    Ok(batch)
 }
 
 fn invert(ctx: &mut Context, image: &graphics::Image) -> GameResult<graphics::Image> {
@@ -356,5 +893,47 @@ fn invert(ctx: &mut Context, image: &graphics::Image) -> GameResult<graphics::Im
         })
         .collect();
 
-    graphics::Image::from_rgba8(ctx, 8, 8, &image_u8_i)
+    graphics::Image::from_rgba8(ctx, image.width(), image.height(), &image_u8_i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::resource_dir;
+
+    #[test]
+    fn shipped_themes_are_discovered() {
+        let themes_dir = resource_dir().join("themes");
+
+        let mut names: Vec<String> = fs::read_dir(&themes_dir)
+            .unwrap()
+            .map(|entry| {
+                let theme_dir = entry.unwrap().path();
+                let manifest: ThemeManifest = serde_yaml::from_str(
+                    &fs::read_to_string(theme_dir.join("theme.yaml")).unwrap(),
+                )
+                .unwrap();
+
+                for sprite_number in 1..=21 {
+                    let image = theme_dir.join(format!("{}.png", sprite_number));
+                    assert!(image.is_file(), "{} is missing", image.display());
+                }
+
+                // As ggez sees it, from the resource directory
+                let mounted_dir = Path::new(THEMES_DIR).join(theme_dir.file_name().unwrap());
+                let theme = theme_from_manifest(&mounted_dir, manifest).unwrap();
+
+                assert_eq!(theme.sprite_size, 16.0);
+                assert_eq!(theme.directory, format!("{}/{}", THEMES_DIR, theme.name));
+
+                theme.name
+            })
+            .collect();
+        names.sort();
+
+        assert_eq!(names, vec!["16x16", "high-contrast"]);
+    }
 }
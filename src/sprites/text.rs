@@ -0,0 +1,498 @@
+use crate::sprites::{Color, GridUnit, Sprite, SpriteGrid, SpriteType};
+
+use ggez::graphics::WHITE;
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+const SPRITES_HEADER: &str = "[sprites]";
+const COLORS_HEADER: &str = "[colors]";
+const LEGEND_HEADER: &str = "[legend]";
+
+const UNCOLORED: char = '.';
+
+const LEGEND_KEYS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+// Where keys carry on once LEGEND_KEYS runs out, just past ASCII's punctuation and symbols
+const MORE_LEGEND_KEYS_START: u32 = 0xC0;
+
+// Every key a legend can hand out, so that there's always one for another color
+fn legend_keys() -> impl Iterator<Item = char> {
+    let more_keys = (MORE_LEGEND_KEYS_START..=char::MAX as u32)
+        .filter_map(std::char::from_u32)
+        .filter(|c| !c.is_whitespace() && !c.is_control());
+
+    LEGEND_KEYS.chars().chain(more_keys)
+}
+
+impl SpriteType {
+    fn to_char(self) -> char {
+        match self {
+            SpriteType::Curve => 'c',
+            SpriteType::Line => '|',
+            SpriteType::Cross => 'x',
+            SpriteType::CornerTriangle => 'v',
+            SpriteType::SmallCircle => 'o',
+            SpriteType::BigCircle => 'O',
+            SpriteType::Diamond => 'd',
+            SpriteType::Dash => '-',
+            SpriteType::Dot => ',',
+            SpriteType::Boom => '*',
+            SpriteType::Skull => 'S',
+            SpriteType::SideTriangle => '>',
+            SpriteType::Ship => 'A',
+            SpriteType::Heart => 'h',
+            SpriteType::Cursor => '+',
+            SpriteType::Turnip => 'T',
+            SpriteType::Squid => 'q',
+            SpriteType::Lizard => 'L',
+            SpriteType::Ball => '@',
+            SpriteType::Crab => 'C',
+            SpriteType::Altar => '#',
+            SpriteType::Empty => '.',
+        }
+    }
+
+    fn from_char(c: char) -> Option<Self> {
+        let sprite_type = match c {
+            'c' => SpriteType::Curve,
+            '|' => SpriteType::Line,
+            'x' => SpriteType::Cross,
+            'v' => SpriteType::CornerTriangle,
+            'o' => SpriteType::SmallCircle,
+            'O' => SpriteType::BigCircle,
+            'd' => SpriteType::Diamond,
+            '-' => SpriteType::Dash,
+            ',' => SpriteType::Dot,
+            '*' => SpriteType::Boom,
+            'S' => SpriteType::Skull,
+            '>' => SpriteType::SideTriangle,
+            'A' => SpriteType::Ship,
+            'h' => SpriteType::Heart,
+            '+' => SpriteType::Cursor,
+            'T' => SpriteType::Turnip,
+            'q' => SpriteType::Squid,
+            'L' => SpriteType::Lizard,
+            '@' => SpriteType::Ball,
+            'C' => SpriteType::Crab,
+            '#' => SpriteType::Altar,
+            '.' => SpriteType::Empty,
+            _ => return None,
+        };
+
+        Some(sprite_type)
+    }
+}
+
+/// Why a sprite grid picture couldn't be parsed, along with the (1-based) line it happened on
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseSpriteGridError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ParseSpriteGridError {
+    fn new<S>(line: usize, message: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        Self {
+            line,
+            message: message.as_ref().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseSpriteGridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseSpriteGridError {}
+
+impl SpriteGrid {
+    fn color_at(&self, x: GridUnit, y: GridUnit) -> Option<Color> {
+        if self.sprite_type_at(x, y) == SpriteType::Empty {
+            return None;
+        }
+
+        match self.grid_param_at(x, y) {
+            Some(gp) if gp.draw_param.color != WHITE => Some(gp.draw_param.color),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for SpriteGrid {
+    fn eq(&self, other: &Self) -> bool {
+        if self.dimensions() != other.dimensions() {
+            return false;
+        }
+
+        for x in 0..self.width {
+            for y in 0..self.height {
+                if self.sprite_type_at(x, y) != other.sprite_type_at(x, y) {
+                    return false;
+                }
+
+                if self.color_at(x, y) != other.color_at(x, y) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+}
+
+/// Renders the grid as a compact picture, one character per sprite
+///
+/// Tinted sprites get a second `[colors]` layer that refers to the `[legend]` below it:
+///
+/// ```text
+/// [sprites]
+/// OOOOOOOO
+/// O..LT..O
+/// ...
+/// [colors]
+/// ........
+/// ...ab...
+/// ...
+/// [legend]
+/// a = 0.8 0.2 0.2 1
+/// b = 1 0 0 1
+/// ```
+impl fmt::Display for SpriteGrid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut keys = legend_keys();
+        let mut legend: Vec<(char, Color)> = vec![];
+        let mut colors = String::new();
+
+        writeln!(f, "{}", SPRITES_HEADER)?;
+
+        for y in 0..self.height {
+            let mut row = String::new();
+
+            for x in 0..self.width {
+                row.push(self.sprite_type_at(x, y).to_char());
+
+                let color_key = match self.color_at(x, y) {
+                    Some(color) => match legend.iter().find(|(_, c)| *c == color) {
+                        Some((key, _)) => *key,
+                        None => {
+                            // Runs out only past a million colors, more than a grid can hold
+                            let key = keys.next().ok_or(fmt::Error)?;
+                            legend.push((key, color));
+                            key
+                        }
+                    },
+                    None => UNCOLORED,
+                };
+
+                colors.push(color_key);
+            }
+
+            colors.push('\n');
+            writeln!(f, "{}", row)?;
+        }
+
+        if legend.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(f, "{}", COLORS_HEADER)?;
+        write!(f, "{}", colors)?;
+
+        writeln!(f, "{}", LEGEND_HEADER)?;
+
+        for (key, color) in legend {
+            writeln!(
+                f,
+                "{} = {} {} {} {}",
+                key, color.r, color.g, color.b, color.a
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SpriteGrid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "SpriteGrid")?;
+        write!(f, "{}", self)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Section {
+    Sprites,
+    Colors,
+    Legend,
+}
+
+/// Parses the picture format produced by `Display`
+///
+/// The grid takes its dimensions from the picture itself.
+/// The `[sprites]` header is optional, so a bare picture works too.
+/// Blank lines are ignored.
+impl FromStr for SpriteGrid {
+    type Err = ParseSpriteGridError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut section = Section::Sprites;
+
+        let mut sprite_rows: Vec<(usize, &str)> = vec![];
+        let mut color_rows: Vec<(usize, &str)> = vec![];
+        let mut legend: Vec<(char, Color)> = vec![];
+
+        for (i, line) in s.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+
+            match line {
+                "" => continue,
+                SPRITES_HEADER => section = Section::Sprites,
+                COLORS_HEADER => section = Section::Colors,
+                LEGEND_HEADER => section = Section::Legend,
+                _ => match section {
+                    Section::Sprites => sprite_rows.push((line_number, line)),
+                    Section::Colors => color_rows.push((line_number, line)),
+                    Section::Legend => legend.push(parse_legend_entry(line_number, line)?),
+                },
+            }
+        }
+
+        let last_line = s.lines().count();
+
+        let (width, height) = layer_dimensions(&sprite_rows, "sprite", last_line)?;
+
+        if !color_rows.is_empty() {
+            let color_dimensions = layer_dimensions(&color_rows, "color", last_line)?;
+
+            if color_dimensions != (width, height) {
+                return Err(ParseSpriteGridError::new(
+                    color_rows[0].0,
+                    format!(
+                        "expected a {}x{} color layer to match the sprites, found {}x{}",
+                        width, height, color_dimensions.0, color_dimensions.1
+                    ),
+                ));
+            }
+        }
+
+        let mut sprite_grid = SpriteGrid::new(width, height);
+
+        for (y, (line_number, row)) in sprite_rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let sprite_type = SpriteType::from_char(c).ok_or_else(|| {
+                    ParseSpriteGridError::new(*line_number, format!("unknown sprite '{}'", c))
+                })?;
+
+                if sprite_type != SpriteType::Empty {
+                    let sprite = Sprite::new(sprite_type);
+                    sprite_grid
+                        .render_sprite_at(sprite, x as GridUnit, y as GridUnit)
+                        .map_err(|e| ParseSpriteGridError::new(*line_number, e.to_string()))?;
+                }
+            }
+        }
+
+        for (y, (line_number, row)) in color_rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == UNCOLORED {
+                    continue;
+                }
+
+                let color = legend
+                    .iter()
+                    .find(|(key, _)| *key == c)
+                    .map(|(_, color)| *color)
+                    .ok_or_else(|| {
+                        ParseSpriteGridError::new(
+                            *line_number,
+                            format!("'{}' is not in the legend", c),
+                        )
+                    })?;
+
+                let (x, y) = (x as GridUnit, y as GridUnit);
+
+                let sprite_type = sprite_grid.sprite_type_at(x, y);
+
+                if sprite_type == SpriteType::Empty {
+                    return Err(ParseSpriteGridError::new(
+                        *line_number,
+                        format!("color '{}' at ({}, {}) has no sprite to tint", c, x, y),
+                    ));
+                }
+
+                let sprite = Sprite::new(sprite_type).colored(color);
+                sprite_grid
+                    .render_sprite_at(sprite, x, y)
+                    .map_err(|e| ParseSpriteGridError::new(*line_number, e.to_string()))?;
+            }
+        }
+
+        Ok(sprite_grid)
+    }
+}
+
+fn layer_dimensions(
+    rows: &[(usize, &str)],
+    layer_name: &str,
+    last_line: usize,
+) -> Result<(GridUnit, GridUnit), ParseSpriteGridError> {
+    let too_big = |line_number: usize| {
+        ParseSpriteGridError::new(
+            line_number,
+            format!("the {} layer is larger than {}", layer_name, GridUnit::MAX),
+        )
+    };
+
+    let first_row = match rows.first() {
+        Some((_, row)) => row,
+        None => {
+            return Err(ParseSpriteGridError::new(
+                last_line,
+                format!("found no {} rows", layer_name),
+            ))
+        }
+    };
+
+    let width = first_row.chars().count();
+
+    for (line_number, row) in rows {
+        let row_width = row.chars().count();
+
+        if row_width != width {
+            return Err(ParseSpriteGridError::new(
+                *line_number,
+                format!("expected {} columns, found {}", width, row_width),
+            ));
+        }
+    }
+
+    if width > GridUnit::MAX as usize {
+        return Err(too_big(rows[0].0));
+    }
+
+    if rows.len() > GridUnit::MAX as usize {
+        return Err(too_big(rows[rows.len() - 1].0));
+    }
+
+    Ok((width as GridUnit, rows.len() as GridUnit))
+}
+
+fn parse_legend_entry(
+    line_number: usize,
+    line: &str,
+) -> Result<(char, Color), ParseSpriteGridError> {
+    let invalid = || {
+        ParseSpriteGridError::new(
+            line_number,
+            format!(
+                "expected a legend entry like 'a = 1 0 0 1', found '{}'",
+                line
+            ),
+        )
+    };
+
+    let mut parts = line.splitn(2, '=');
+
+    let key = parts.next().ok_or_else(invalid)?.trim();
+    let channels = parts.next().ok_or_else(invalid)?;
+
+    let mut key_chars = key.chars();
+    let key = match (key_chars.next(), key_chars.next()) {
+        (Some(k), None) if k != UNCOLORED => k,
+        _ => return Err(invalid()),
+    };
+
+    let channels: Vec<f32> = channels
+        .split_whitespace()
+        .map(|c| c.parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+
+    let color = match channels.as_slice() {
+        [r, g, b] => Color::new(*r, *g, *b, 1.0),
+        [r, g, b, a] => Color::new(*r, *g, *b, *a),
+        _ => return Err(invalid()),
+    };
+
+    Ok((key, color))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tinted_grid(width: GridUnit, height: GridUnit) -> SpriteGrid {
+        let mut sprite_grid = SpriteGrid::new(width, height);
+
+        for x in 0..width {
+            for y in 0..height {
+                let i = f32::from(y) * f32::from(width) + f32::from(x);
+                let color = Color::new(i / 1000.0, 0.5, 0.25, 1.0);
+                let sprite = Sprite::new(SpriteType::Turnip).colored(color);
+
+                sprite_grid.render_sprite_at(sprite, x, y).unwrap();
+            }
+        }
+
+        sprite_grid
+    }
+
+    #[test]
+    fn pictures_parse_back_into_the_same_grid() {
+        let mut sprite_grid = SpriteGrid::new(4, 3);
+        sprite_grid
+            .render_sprite_at(Sprite::new(SpriteType::Crab), 0, 0)
+            .unwrap();
+        sprite_grid
+            .render_sprite_at(Sprite::new(SpriteType::Heart).colored(WHITE), 3, 0)
+            .unwrap();
+        sprite_grid
+            .render_sprite_at(
+                Sprite::new(SpriteType::Skull).colored(Color::new(0.8, 0.2, 0.2, 1.0)),
+                1,
+                2,
+            )
+            .unwrap();
+        sprite_grid
+            .render_sprite_at(
+                Sprite::new(SpriteType::Turnip).colored(Color::new(0.0, 1.0, 0.0, 0.5)),
+                2,
+                1,
+            )
+            .unwrap();
+
+        let picture = sprite_grid.to_string();
+
+        assert_eq!(picture.parse::<SpriteGrid>(), Ok(sprite_grid));
+    }
+
+    #[test]
+    fn grids_with_more_colors_than_keys_still_print() {
+        let sprite_grid = tinted_grid(10, 10);
+
+        let picture = sprite_grid.to_string();
+
+        assert_eq!(
+            picture.lines().filter(|line| line.contains('=')).count(),
+            100
+        );
+        assert_eq!(picture.parse::<SpriteGrid>(), Ok(sprite_grid));
+    }
+
+    #[test]
+    fn parse_errors_give_the_line() {
+        let picture = "[sprites]\nT.\nTT\n[colors]\na.\nbb\n[legend]\na = 1 0 0\n";
+
+        assert_eq!(
+            picture.parse::<SpriteGrid>(),
+            Err(ParseSpriteGridError::new(6, "'b' is not in the legend"))
+        );
+    }
+}
@@ -0,0 +1,14 @@
+//! Helpers shared by the unit tests
+
+use std::fs;
+use std::path::PathBuf;
+
+/// An empty directory of its own under the system's temp dir, cleared out from any earlier run
+pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("sweet-turnips-{}-{}", name, std::process::id()));
+
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}